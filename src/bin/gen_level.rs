@@ -1,14 +1,15 @@
 use anyhow::{bail, Context, Result};
 use binary_greedy_mesher_demo_rs as demo;
+use demo::data::level_file::{LevelFile, LevelMetadata};
 use demo::{get_xyz_key, get_zxy_index, CS, CS_P, CS_P3};
 use std::env;
-use std::fs;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 struct Args {
     output: PathBuf,
     chunks_per_side: u8,
+    chunks_high: u8,
     seed: u64,
     noise_scale: f32,
     height_scale: f32,
@@ -22,6 +23,7 @@ fn parse_args() -> Result<Args> {
 
     let mut out = PathBuf::from("levels/generated_level");
     let mut chunks_per_side: u8 = 1;
+    let mut chunks_high: u8 = 1;
     let mut seed: u64 = 0;
     let mut noise_scale: f32 = 0.035;
     let mut height_scale: f32 = 1.0;
@@ -46,6 +48,18 @@ fn parse_args() -> Result<Args> {
                     bail!("--chunks-per-side must be >= 1");
                 }
             }
+            "-y" | "--chunks-high" => {
+                chunks_high = args
+                    .next()
+                    .context("--chunks-high requires a value")?
+                    .parse::<u16>()
+                    .context("--chunks-high must be an integer")?
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("--chunks-high must fit in 1..=255"))?;
+                if chunks_high == 0 {
+                    bail!("--chunks-high must be >= 1");
+                }
+            }
             "-s" | "--seed" => {
                 seed = args
                     .next()
@@ -102,6 +116,7 @@ fn parse_args() -> Result<Args> {
     Ok(Args {
         output: out,
         chunks_per_side,
+        chunks_high,
         seed,
         noise_scale,
         height_scale,
@@ -122,6 +137,7 @@ USAGE:
 OPTIONS:
   -o, --output <path>           Output file path (default: levels/generated_level)
   -c, --chunks-per-side <n>     Number of chunks along X and Z (1..=255) (default: 1)
+  -y, --chunks-high <n>         Number of chunks along Y (1..=255) (default: 1)
   -s, --seed <u64>              Seed (default: 0)
       --noise-scale <f32>       World noise scale (default: 0.035)
       --height-scale <f32>      Height multiplier (default: 1.0)
@@ -131,7 +147,7 @@ OPTIONS:
   -h, --help                    Print help
 
 NOTES:
-  - This generator outputs a flat chunk stack (y=0) because the current level format stores size^2 chunks.
+  - Terrain heights span all --chunks-high layers; chunks that end up all air are not stored.
   - Chunk voxel dimensions are fixed by the demo constants (CS=62, CS_P=64).
 "
     );
@@ -210,43 +226,30 @@ fn fbm_2d(seed: u64, x: f32, y: f32, octaves: u8, gain: f32, lacunarity: f32) ->
     if norm > 0.0 { sum / norm } else { 0.0 }
 }
 
-fn rle_encode_sparse_trailing_zeros(voxels: &[u8]) -> Vec<u8> {
-    debug_assert_eq!(voxels.len(), CS_P3);
-
-    let end = voxels
-        .iter()
-        .rposition(|&v| v != 0)
-        .map(|i| i + 1)
-        .unwrap_or(0);
-
-    let mut out = Vec::<u8>::new();
-    let mut i = 0usize;
-
-    while i < end {
-        let ty = voxels[i];
-        let mut run = 1usize;
-        while i + run < end && voxels[i + run] == ty && run < (u8::MAX as usize) {
-            run += 1;
-        }
-
-        out.push(ty);
-        out.push(run as u8);
-        i += run;
-    }
-
-    out
-}
-
 fn write_level_file(path: &Path, args: &Args) -> Result<()> {
     let size = args.chunks_per_side as usize;
-    let table_len = size * size;
-
-    let mut chunk_rle: Vec<Vec<u8>> = Vec::with_capacity(table_len);
+    let height_chunks = args.chunks_high as usize;
+    let mut level = LevelFile::default();
+    level.metadata = LevelMetadata {
+        generator_seed: args.seed,
+        entries: vec![
+            ("generator".to_string(), "gen_level".to_string()),
+            ("noise_scale".to_string(), args.noise_scale.to_string()),
+            ("height_scale".to_string(), args.height_scale.to_string()),
+            ("octaves".to_string(), args.octaves.to_string()),
+            ("gain".to_string(), args.gain.to_string()),
+            ("lacunarity".to_string(), args.lacunarity.to_string()),
+        ],
+    };
+
+    // Terrain spans the whole chunk stack: a column's height runs from the bottom of y=0 up to
+    // `height_chunks * CS` voxels.
+    let stack_height = height_chunks * CS;
+    let mut heights = vec![0usize; CS * CS];
+    let mut voxels = vec![0u8; CS_P3];
 
     for cz in 0..size {
         for cx in 0..size {
-            let mut voxels = vec![0u8; CS_P3];
-
             for z in 1..=CS {
                 for x in 1..=CS {
                     let wx = (cx * CS + (x - 1)) as f32;
@@ -262,56 +265,37 @@ fn write_level_file(path: &Path, args: &Args) -> Result<()> {
                     );
 
                     let t = (n * 0.5 + 0.5).clamp(0.0, 1.0).powf(1.35);
-                    let height = ((t * (CS as f32) * args.height_scale) as i32).clamp(0, CS as i32) as usize;
-
-                    for y in 1..=height {
-                        let idx = get_zxy_index(x, y, z);
-                        voxels[idx] = 1;
-                    }
+                    heights[(z - 1) * CS + (x - 1)] =
+                        ((t * (stack_height as f32) * args.height_scale) as i32).clamp(0, stack_height as i32) as usize;
                 }
             }
 
-            chunk_rle.push(rle_encode_sparse_trailing_zeros(&voxels));
-        }
-    }
-
-    let mut bytes: Vec<u8> = Vec::new();
-    bytes.push(args.chunks_per_side);
-    bytes.resize(1 + table_len * 12, 0u8);
-
-    for rle in &chunk_rle {
-        bytes.extend_from_slice(rle);
-    }
-
-    let mut table_offset = 1usize;
-    let mut data_offset = 1 + table_len * 12;
-
-    for cz in 0..size {
-        for cx in 0..size {
-            let i = cz * size + cx;
-            let rle = &chunk_rle[i];
-
-            let key = get_xyz_key(cx as u8, 0, cz as u8);
-            let begin = data_offset as u32;
-            let sz = rle.len() as u32;
-
-            bytes[table_offset..table_offset + 4].copy_from_slice(&key.to_le_bytes());
-            bytes[table_offset + 4..table_offset + 8].copy_from_slice(&begin.to_le_bytes());
-            bytes[table_offset + 8..table_offset + 12].copy_from_slice(&sz.to_le_bytes());
-
-            table_offset += 12;
-            data_offset += rle.len();
-        }
-    }
+            for cy in 0..height_chunks {
+                voxels.fill(0);
+                let mut solid = false;
+
+                for z in 1..=CS {
+                    for x in 1..=CS {
+                        let height = heights[(z - 1) * CS + (x - 1)];
+                        for y in 1..=CS {
+                            if cy * CS + y > height {
+                                break;
+                            }
+                            voxels[get_zxy_index(x, y, z)] = 1;
+                            solid = true;
+                        }
+                    }
+                }
 
-    if let Some(parent) = path.parent() {
-        if !parent.as_os_str().is_empty() {
-            fs::create_dir_all(parent).with_context(|| format!("create dir: {}", parent.display()))?;
+                // All-air chunks are left out of the sparse chunk table.
+                if solid {
+                    level.insert_chunk(get_xyz_key(cx as u8, cy as u8, cz as u8), &voxels)?;
+                }
+            }
         }
     }
 
-    fs::write(path, bytes).with_context(|| format!("write level file: {}", path.display()))?;
-    Ok(())
+    level.save_to_file(path)
 }
 
 fn main() -> Result<()> {
@@ -332,9 +316,10 @@ fn main() -> Result<()> {
 
     write_level_file(&args.output, &args)?;
     eprintln!(
-        "Wrote level: {} (chunks_per_side={}, seed={}, chunk_dims={}^3 incl padding)",
+        "Wrote level: {} (chunks_per_side={}, chunks_high={}, seed={}, chunk_dims={}^3 incl padding)",
         args.output.display(),
         args.chunks_per_side,
+        args.chunks_high,
         args.seed,
         CS_P
     );
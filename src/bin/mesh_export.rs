@@ -0,0 +1,153 @@
+use anyhow::{bail, Context, Result};
+use binary_greedy_mesher_demo_rs as demo;
+use demo::data::level_file::LevelFile;
+use demo::export::{gltf, mesh_level, obj};
+use demo::data::blocks::BlockRegistry;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Obj,
+    Glb,
+}
+
+impl Format {
+    fn parse(s: &str) -> Result<Self> {
+        match s {
+            "obj" => Ok(Self::Obj),
+            "glb" => Ok(Self::Glb),
+            _ => bail!("Unknown format: {s}. Expected obj or glb."),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Args {
+    level: PathBuf,
+    output: PathBuf,
+    format: Format,
+    blocks: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = env::args().skip(1);
+
+    let mut level: Option<PathBuf> = None;
+    let mut output: Option<PathBuf> = None;
+    let mut format: Option<Format> = None;
+    let mut blocks: Option<PathBuf> = None;
+
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "-l" | "--level" => {
+                level = Some(PathBuf::from(args.next().context("--level requires a value")?));
+            }
+            "-o" | "--output" => {
+                output = Some(PathBuf::from(args.next().context("--output requires a value")?));
+            }
+            "-f" | "--format" => {
+                format = Some(Format::parse(&args.next().context("--format requires a value")?)?);
+            }
+            "-b" | "--blocks" => {
+                blocks = Some(PathBuf::from(args.next().context("--blocks requires a value")?));
+            }
+            "-h" | "--help" => {
+                print_usage();
+                std::process::exit(0);
+            }
+            _ => bail!("Unknown arg: {a}. Use --help."),
+        }
+    }
+
+    // Without --format, go by the output extension; without either, export OBJ.
+    let format = match (format, &output) {
+        (Some(format), _) => format,
+        (None, Some(path)) if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("glb")) => Format::Glb,
+        (None, _) => Format::Obj,
+    };
+    let output = output.unwrap_or_else(|| match format {
+        Format::Obj => PathBuf::from("level.obj"),
+        Format::Glb => PathBuf::from("level.glb"),
+    });
+
+    Ok(Args {
+        level: level.context("--level is required. Use --help.")?,
+        output,
+        format,
+        blocks,
+    })
+}
+
+fn print_usage() {
+    eprintln!(
+        "\
+Meshes every chunk of a level file and writes the result as Wavefront OBJ or binary glTF.
+
+USAGE:
+  cargo run --bin mesh_export -- --level <path> [options]
+
+OPTIONS:
+  -l, --level <path>            Level file to export
+  -o, --output <path>           Output path (default: level.obj / level.glb)
+  -f, --format <obj|glb>        Output format (default: from the output extension, else obj)
+  -b, --blocks <path>           Block registry JSON for colours (default: built-in palette)
+  -h, --help                    Print help
+
+NOTES:
+  - Vertices are in world voxel units; chunk (x, y, z) is offset by (x, y, z) * CS.
+  - One material per voxel type (voxel_<type>) using the block registry's colours; transparent
+    types are meshed behind opaque ones and blended.
+  - OBJ writes world-space vertices and a .mtl next to the .obj.
+  - glTF writes one node per chunk, translated by (x, y, z) * CS, with chunk-local vertices.
+"
+    );
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+
+    let mut level = LevelFile::default();
+    level.load_from_file(&args.level)?;
+    let blocks = args.blocks.as_deref().map(BlockRegistry::load).transpose()?.unwrap_or_default();
+    let chunks = mesh_level(&level, &blocks)?;
+
+    if let Some(parent) = args.output.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent).with_context(|| format!("create dir: {}", parent.display()))?;
+    }
+
+    match args.format {
+        Format::Obj => {
+            let mtl_path = args.output.with_extension("mtl");
+            let mtl_name = mtl_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .context("output path must have a UTF-8 file name")?;
+
+            let mut mtl = BufWriter::new(File::create(&mtl_path).with_context(|| format!("create {}", mtl_path.display()))?);
+            obj::write_mtl(&mut mtl, &chunks, &blocks)?;
+            mtl.flush()?;
+
+            let mut out = BufWriter::new(create_output(&args)?);
+            obj::write_obj(&mut out, &chunks, Some(mtl_name))?;
+            out.flush()?;
+        }
+        Format::Glb => {
+            let mut out = BufWriter::new(create_output(&args)?);
+            gltf::write_glb(&mut out, &chunks, &blocks)?;
+            out.flush()?;
+        }
+    }
+
+    let quads: usize = chunks.iter().map(|c| c.quad_count()).sum();
+    eprintln!("Wrote {} ({} chunks, {} quads)", args.output.display(), chunks.len(), quads);
+    Ok(())
+}
+
+fn create_output(args: &Args) -> Result<File> {
+    File::create(&args.output).with_context(|| format!("create {}", args.output.display()))
+}
@@ -0,0 +1,122 @@
+use anyhow::{bail, Context, Result};
+use binary_greedy_mesher_demo_rs as demo;
+use demo::data::level_file::LevelFile;
+use demo::data::vox::VoxFile;
+use demo::data::blocks::BlockRegistry;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+struct Args {
+    level: PathBuf,
+    output: PathBuf,
+    min: [u8; 3],
+    max: [u8; 3],
+    blocks: Option<PathBuf>,
+}
+
+fn parse_chunk_coord(flag: &str, value: Option<String>) -> Result<[u8; 3]> {
+    let value = value.with_context(|| format!("{flag} requires a value"))?;
+    let parts = value
+        .split(',')
+        .map(|p| p.trim().parse::<u8>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("{flag} must be x,y,z chunk coordinates in 0..=255"))?;
+    parts.try_into().map_err(|_| anyhow::anyhow!("{flag} must be x,y,z chunk coordinates"))
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = env::args().skip(1);
+
+    let mut level: Option<PathBuf> = None;
+    let mut output = PathBuf::from("level.vox");
+    let mut min = [0u8; 3];
+    let mut max = [u8::MAX; 3];
+    let mut blocks: Option<PathBuf> = None;
+
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "-l" | "--level" => {
+                level = Some(PathBuf::from(args.next().context("--level requires a value")?));
+            }
+            "-o" | "--output" => {
+                output = PathBuf::from(args.next().context("--output requires a value")?);
+            }
+            "--min" => min = parse_chunk_coord("--min", args.next())?,
+            "--max" => max = parse_chunk_coord("--max", args.next())?,
+            "-b" | "--blocks" => {
+                blocks = Some(PathBuf::from(args.next().context("--blocks requires a value")?));
+            }
+            "-h" | "--help" => {
+                print_usage();
+                std::process::exit(0);
+            }
+            _ => bail!("Unknown arg: {a}. Use --help."),
+        }
+    }
+
+    if (0..3).any(|i| min[i] > max[i]) {
+        bail!("--min must not exceed --max on any axis");
+    }
+
+    Ok(Args {
+        level: level.context("--level is required. Use --help.")?,
+        output,
+        min,
+        max,
+        blocks,
+    })
+}
+
+fn print_usage() {
+    eprintln!(
+        "\
+Writes the chunks of a level file as a MagicaVoxel .vox file.
+
+USAGE:
+  cargo run --bin vox_export -- --level <path> [options]
+
+OPTIONS:
+  -l, --level <path>            Level file to export
+  -o, --output <path>           Output .vox path (default: level.vox)
+      --min <x,y,z>             Lowest chunk to include (default: 0,0,0)
+      --max <x,y,z>             Highest chunk to include (default: 255,255,255)
+  -b, --blocks <path>           Block registry JSON for colours (default: built-in palette)
+  -h, --help                    Print help
+
+NOTES:
+  - The scene is split into models of at most 256^3 voxels.
+  - Colour index n is voxel type n, using the block registry's colours. Import again with
+    `vox_import --palette index` to keep the types unchanged.
+"
+    );
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+
+    let mut level = LevelFile::default();
+    level.load_from_file(&args.level)?;
+
+    let in_range = |(x, y, z): (u8, u8, u8)| {
+        let c = [x, y, z];
+        (0..3).all(|i| (args.min[i]..=args.max[i]).contains(&c[i]))
+    };
+    let blocks = args.blocks.as_deref().map(BlockRegistry::load).transpose()?.unwrap_or_default();
+    let vox = VoxFile::from_level(&level, &blocks, in_range)?;
+    if vox.models.is_empty() {
+        bail!("No voxels in the selected chunks");
+    }
+
+    if let Some(parent) = args.output.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent).with_context(|| format!("create dir: {}", parent.display()))?;
+    }
+    fs::write(&args.output, vox.to_bytes()).with_context(|| format!("write {}", args.output.display()))?;
+
+    let voxels: usize = vox.models.iter().map(|m| m.voxels.len()).sum();
+    eprintln!("Wrote {} ({} models, {} voxels)", args.output.display(), vox.models.len(), voxels);
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use anyhow::{bail, Context, Result};
+use binary_greedy_mesher_demo_rs as demo;
+use demo::data::level_file::LevelMetadata;
+use demo::data::vox::{PaletteMapping, VoxFile};
+use demo::data::blocks::BlockRegistry;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+struct Args {
+    input: PathBuf,
+    output: PathBuf,
+    palette: PaletteMapping,
+    blocks: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = env::args().skip(1);
+
+    let mut input: Option<PathBuf> = None;
+    let mut output = PathBuf::from("levels/imported_level");
+    let mut palette = PaletteMapping::Nearest;
+    let mut blocks: Option<PathBuf> = None;
+
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "-i" | "--input" => {
+                input = Some(PathBuf::from(args.next().context("--input requires a value")?));
+            }
+            "-o" | "--output" => {
+                output = PathBuf::from(args.next().context("--output requires a value")?);
+            }
+            "-p" | "--palette" => {
+                palette = match args.next().context("--palette requires a value")?.as_str() {
+                    "nearest" => PaletteMapping::Nearest,
+                    "index" => PaletteMapping::Index,
+                    other => bail!("Unknown palette mapping: {other}. Expected nearest or index."),
+                };
+            }
+            "-b" | "--blocks" => {
+                blocks = Some(PathBuf::from(args.next().context("--blocks requires a value")?));
+            }
+            "-h" | "--help" => {
+                print_usage();
+                std::process::exit(0);
+            }
+            _ => bail!("Unknown arg: {a}. Use --help."),
+        }
+    }
+
+    Ok(Args {
+        input: input.context("--input is required. Use --help.")?,
+        output,
+        palette,
+        blocks,
+    })
+}
+
+fn print_usage() {
+    eprintln!(
+        "\
+Converts a MagicaVoxel .vox file into a level file.
+
+USAGE:
+  cargo run --bin vox_import -- --input <file.vox> [options]
+
+OPTIONS:
+  -i, --input <path>            .vox file to convert
+  -o, --output <path>           Output level path (default: levels/imported_level)
+  -p, --palette <mapping>       nearest: type with the closest block colour (default)
+                                index:   colour index is the voxel type
+  -b, --blocks <path>           Block registry JSON for colours (default: built-in palette)
+  -h, --help                    Print help
+
+NOTES:
+  - MagicaVoxel is Z-up; the level is Y-up. The scene is moved so its lowest corner is at the origin.
+  - All models of the scene graph are placed with their translations and rotations (first frame).
+"
+    );
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+
+    let bytes = fs::read(&args.input).with_context(|| format!("read {}", args.input.display()))?;
+    let vox = VoxFile::parse(&bytes).with_context(|| format!("parse {}", args.input.display()))?;
+
+    let blocks = args.blocks.as_deref().map(BlockRegistry::load).transpose()?.unwrap_or_default();
+    let mut level = vox.to_level(args.palette, &blocks)?;
+    level.metadata = LevelMetadata {
+        generator_seed: 0,
+        entries: vec![
+            ("generator".to_string(), "vox_import".to_string()),
+            ("source".to_string(), args.input.display().to_string()),
+            ("palette".to_string(), format!("{:?}", args.palette).to_lowercase()),
+        ],
+    };
+    level.save_to_file(&args.output)?;
+
+    let [x, y, z] = level.dims();
+    eprintln!(
+        "Wrote level: {} ({} models, {} chunks, dims={x}x{y}x{z})",
+        args.output.display(),
+        vox.models.len(),
+        level.chunk_table.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,198 @@
+use crate::mesher::TransparencyTable;
+use anyhow::{bail, ensure, Context, Result};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Number of voxel types a `u8` can hold, including air (type 0).
+pub const BLOCK_TYPE_COUNT: usize = 256;
+
+/// Colours of voxel types 1..=8 in the built-in registry, the palette the viewer always had.
+pub const CLASSIC_COLORS: [(&str, [f32; 3]); 8] = [
+    ("light_blue", [0.2, 0.659, 0.839]),
+    ("grey", [0.302, 0.302, 0.302]),
+    ("green", [0.278, 0.600, 0.141]),
+    ("blue", [0.1, 0.1, 0.6]),
+    ("cyan", [0.1, 0.6, 0.6]),
+    ("magenta", [0.6, 0.1, 0.6]),
+    ("yellow", [0.6, 0.6, 0.1]),
+    ("red", [0.6, 0.1, 0.1]),
+];
+
+/// How one voxel type looks and whether it lets light through.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockType {
+    pub name: String,
+    /// sRGB colour, each channel in `0.0..=1.0`.
+    pub color: [f32; 3],
+    /// 1.0 for solid blocks; anything lower makes the type transparent to the mesher.
+    pub opacity: f32,
+    /// Emissive blocks are drawn at full colour, without shading or ambient occlusion.
+    pub emissive: bool,
+    /// Layer in a block texture array, for renderers that texture blocks.
+    pub texture: Option<u32>,
+}
+
+impl BlockType {
+    pub fn is_transparent(&self) -> bool {
+        self.opacity < 1.0
+    }
+
+    fn fallback(ty: u8) -> Self {
+        Self {
+            name: format!("block_{ty}"),
+            color: fallback_color(ty),
+            opacity: 1.0,
+            emissive: false,
+            texture: None,
+        }
+    }
+}
+
+/// Properties of every voxel type, indexed by type. Type 0 is air and has no entry of its own.
+///
+/// The default registry holds the classic eight colours as types 1..=8; every type a registry
+/// doesn't define gets a generated colour, so all 255 non-air types can be drawn and exported.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockRegistry {
+    blocks: Vec<BlockType>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        let mut blocks: Vec<BlockType> = (0..BLOCK_TYPE_COUNT).map(|ty| BlockType::fallback(ty as u8)).collect();
+        blocks[0].name = "air".to_string();
+        blocks[0].opacity = 0.0;
+        for (i, (name, color)) in CLASSIC_COLORS.into_iter().enumerate() {
+            blocks[i + 1].name = name.to_string();
+            blocks[i + 1].color = color;
+        }
+        Self { blocks }
+    }
+}
+
+impl BlockRegistry {
+    /// Reads a registry from JSON of the form
+    ///
+    /// ```json
+    /// { "blocks": [{ "id": 9, "name": "glass", "color": [0.8, 0.9, 1.0], "opacity": 0.4,
+    ///                "emissive": false, "texture": 3 }] }
+    /// ```
+    ///
+    /// `id` (1..=255) and `color` are required. Types the file doesn't list keep their default.
+    pub fn from_json(text: &str) -> Result<Self> {
+        let document: Value = serde_json::from_str(text).context("Block registry is not valid JSON")?;
+        let entries = document
+            .get("blocks")
+            .and_then(Value::as_array)
+            .context("Block registry needs a \"blocks\" array")?;
+
+        let mut registry = Self::default();
+        let mut seen = HashSet::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let (ty, block) = parse_block(entry).with_context(|| format!("Invalid entry blocks[{i}]"))?;
+            ensure!(seen.insert(ty), "Block type {ty} is defined more than once");
+            registry.set(ty, block);
+        }
+        Ok(registry)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_json(&text).with_context(|| format!("Failed to load block registry {}", path.display()))
+    }
+
+    pub fn get(&self, ty: u8) -> &BlockType {
+        &self.blocks[ty as usize]
+    }
+
+    /// Replaces the definition of non-air type `ty`.
+    pub fn set(&mut self, ty: u8, block: BlockType) {
+        assert_ne!(ty, 0, "air cannot be redefined");
+        self.blocks[ty as usize] = block;
+    }
+
+    pub fn color(&self, ty: u8) -> [f32; 3] {
+        self.get(ty).color
+    }
+
+    /// Non-air types with their definitions, in type order.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &BlockType)> {
+        self.blocks.iter().enumerate().skip(1).map(|(ty, block)| (ty as u8, block))
+    }
+
+    /// First type called `name`.
+    pub fn find(&self, name: &str) -> Option<u8> {
+        self.iter().find(|(_, block)| block.name == name).map(|(ty, _)| ty)
+    }
+
+    /// Which types [`crate::mesher::mesh_with_transparency`] should treat as transparent.
+    pub fn transparency_table(&self) -> TransparencyTable {
+        let mut table = [false; BLOCK_TYPE_COUNT];
+        for (ty, block) in self.iter() {
+            table[ty as usize] = block.is_transparent();
+        }
+        table
+    }
+
+    pub fn has_transparent_types(&self) -> bool {
+        self.iter().any(|(_, block)| block.is_transparent())
+    }
+}
+
+fn parse_block(entry: &Value) -> Result<(u8, BlockType)> {
+    let id = entry.get("id").and_then(Value::as_u64).context("\"id\" must be a number")?;
+    ensure!((1..BLOCK_TYPE_COUNT as u64).contains(&id), "\"id\" must be in 1..=255, got {id}");
+    let ty = id as u8;
+
+    let color = match entry.get("color").and_then(Value::as_array).map(Vec::as_slice) {
+        Some([r, g, b]) => [r, g, b].map(|c| c.as_f64().map(|c| c as f32)),
+        _ => bail!("\"color\" must be an [r, g, b] array"),
+    };
+    let Some(color) = color.into_iter().collect::<Option<Vec<f32>>>() else {
+        bail!("\"color\" channels must be numbers");
+    };
+    ensure!(color.iter().all(|c| (0.0..=1.0).contains(c)), "\"color\" channels must be in 0..=1, got {color:?}");
+
+    let name = match entry.get("name") {
+        None => format!("block_{ty}"),
+        Some(name) => name.as_str().context("\"name\" must be a string")?.to_string(),
+    };
+    let opacity = match entry.get("opacity") {
+        None => 1.0,
+        Some(opacity) => opacity.as_f64().context("\"opacity\" must be a number")? as f32,
+    };
+    ensure!((0.0..=1.0).contains(&opacity), "\"opacity\" must be in 0..=1, got {opacity}");
+    let emissive = match entry.get("emissive") {
+        None => false,
+        Some(emissive) => emissive.as_bool().context("\"emissive\" must be true or false")?,
+    };
+    let texture = match entry.get("texture") {
+        None | Some(Value::Null) => None,
+        Some(texture) => {
+            let texture = texture.as_u64().context("\"texture\" must be a layer index")?;
+            Some(u32::try_from(texture).context("\"texture\" is out of range")?)
+        }
+    };
+
+    Ok((ty, BlockType { name, color: [color[0], color[1], color[2]], opacity, emissive, texture }))
+}
+
+/// Colour for a type nobody defined: hues spread by the golden ratio so neighbouring types differ.
+fn fallback_color(ty: u8) -> [f32; 3] {
+    let hue = (ty as f32 * 0.618_034).fract() * 6.0;
+    let (saturation, value) = (0.55, 0.75);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let [r, g, b] = match hue as u32 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    let m = value - chroma;
+    [r + m, g + m, b + m]
+}
@@ -1,6 +1,23 @@
+use crate::data::rle::{self, RleError};
+use crate::{parse_xyz_key, CS_P3, MAX_CHUNK};
 use anyhow::{Context, Result};
 use bytemuck::{Pod, Zeroable};
-use std::{fs, path::Path};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+const CHUNK_TABLE_ENTRY_SIZE: usize = std::mem::size_of::<ChunkTableEntry>();
+
+/// Identifies versioned level files. Files without it use the legacy layout: a single size
+/// byte followed by a `size * size` chunk table of y=0 chunks.
+pub const LEVEL_MAGIC: [u8; 4] = *b"BGML";
+pub const LEVEL_VERSION: u16 = 3;
+
+// Version 2: magic, version: u16, dims: [u8; 3], flags: u8, chunk_count: u32
+const HEADER_SIZE_V2: usize = 4 + 2 + 3 + 1 + 4;
+// Version 3 appends metadata_size: u32 and a CRC-32 of everything after the header
+// (metadata block, chunk table and RLE data).
+const HEADER_SIZE_V3: usize = HEADER_SIZE_V2 + 4 + 4;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
@@ -10,46 +27,467 @@ pub struct ChunkTableEntry {
     pub rle_data_size: u32,
 }
 
+/// Free-form information about how a level was produced. Stored from version 3 on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LevelMetadata {
+    pub generator_seed: u64,
+    pub entries: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub enum LevelFileError {
+    Io { path: PathBuf, source: io::Error },
+    /// The file is neither a versioned level file nor a valid legacy one.
+    WrongFormat(String),
+    UnsupportedVersion(u16),
+    /// The file is a level file but its contents are truncated or fail the checksum.
+    Corrupt(String),
+    /// The file parsed but its chunk table does not describe valid chunk data.
+    Invalid(ValidationReport),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// The entry's RLE range does not fit inside the file's data section.
+    OutOfBounds { index: usize, key: u32, begin: u32, size: u32 },
+    /// The RLE stream is malformed or describes more than `CS_P3` voxels.
+    Rle { index: usize, key: u32, error: RleError },
+    DuplicateKey { key: u32, first: usize, second: usize },
+    /// Two entries share bytes of RLE data.
+    Overlap { first: usize, second: usize },
+    /// The RLE stream describes fewer than `CS_P3` voxels and the rest of the chunk decodes as
+    /// air. [`LevelFile::insert_chunk`] drops trailing air this way, so this is not an error.
+    ShortStream { index: usize, key: u32, decoded: usize },
+}
+
+impl ValidationIssue {
+    /// Whether the issue makes the chunk table unsafe to decode.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, ValidationIssue::ShortStream { .. })
+    }
+}
+
+/// Everything found in a level's chunk table, in table order per check. The level is safe to
+/// decode when none of the issues is an error.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|issue| issue.is_error())
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::OutOfBounds { index, key, begin, size } => {
+                write!(f, "entry {index} (key {key:#08x}): data {begin}+{size} is outside the data section")
+            }
+            ValidationIssue::Rle { index, key, error } => {
+                write!(f, "entry {index} (key {key:#08x}): {error}")
+            }
+            ValidationIssue::DuplicateKey { key, first, second } => {
+                write!(f, "entries {first} and {second} share key {key:#08x}")
+            }
+            ValidationIssue::Overlap { first, second } => {
+                write!(f, "entries {first} and {second} have overlapping data ranges")
+            }
+            ValidationIssue::ShortStream { index, key, decoded } => {
+                write!(f, "entry {index} (key {key:#08x}): stream ends after {decoded} of {CS_P3} voxels")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} chunk table problem(s)", self.errors().count())?;
+        for issue in self.errors() {
+            write!(f, "\n  {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for LevelFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelFileError::Io { path, source } => write!(f, "Failed to read level file {}: {source}", path.display()),
+            LevelFileError::WrongFormat(reason) => write!(f, "Not a level file: {reason}"),
+            LevelFileError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported level file version {version} (supported: 2..={LEVEL_VERSION})")
+            }
+            LevelFileError::Corrupt(reason) => write!(f, "Corrupt level file: {reason}"),
+            LevelFileError::Invalid(report) => write!(f, "Invalid level file: {report}"),
+        }
+    }
+}
+
+impl std::error::Error for LevelFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LevelFileError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct LevelFile {
     pub chunk_table: Vec<ChunkTableEntry>,
     pub buffer: Vec<u8>,
-    size: u8,
+    pub metadata: LevelMetadata,
+    dims: [u8; 3],
+    // Offset of the first byte of RLE data in `buffer` (after the header and chunk table).
+    data_start: usize,
 }
 
 impl LevelFile {
+    /// Level extent in chunks along X, Y and Z.
+    pub fn dims(&self) -> [u8; 3] {
+        self.dims
+    }
+
+    /// Horizontal extent in chunks (the legacy square size).
     pub fn size(&self) -> u8 {
-        self.size
-    }
-
-    pub fn load_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let bytes = fs::read(&path).with_context(|| format!("Failed to read level file: {}", path.as_ref().display()))?;
-        anyhow::ensure!(!bytes.is_empty(), "Level file is empty");
-
-        self.size = bytes[0];
-        let table_len = (self.size as usize) * (self.size as usize);
-        let table_bytes = table_len * std::mem::size_of::<ChunkTableEntry>();
-        anyhow::ensure!(bytes.len() >= 1 + table_bytes, "Level file is truncated (missing chunk table)");
-
-        let table_start = 1;
-        let table_end = table_start + table_bytes;
-        let table_slice = &bytes[table_start..table_end];
-        // The on-disk chunk table is tightly packed bytes; it may not be aligned for safe casting.
-        // Decode manually as little-endian u32 triplets.
-        self.chunk_table.clear();
-        self.chunk_table.reserve(table_len);
-        for i in 0..table_len {
-            let base = i * 12;
-            let key = u32::from_le_bytes(table_slice[base..base + 4].try_into().unwrap());
-            let rle_data_begin = u32::from_le_bytes(table_slice[base + 4..base + 8].try_into().unwrap());
-            let rle_data_size = u32::from_le_bytes(table_slice[base + 8..base + 12].try_into().unwrap());
-            self.chunk_table.push(ChunkTableEntry {
-                key,
-                rle_data_begin,
-                rle_data_size,
+        self.dims[0].max(self.dims[2])
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), LevelFileError> {
+        let bytes = fs::read(&path).map_err(|source| LevelFileError::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        })?;
+
+        let parsed = parse_level(&bytes, true)?;
+        self.dims = parsed.dims;
+        self.metadata = parsed.metadata;
+        self.chunk_table = parsed.chunk_table;
+        self.data_start = parsed.data_start;
+        self.buffer = bytes;
+
+        let report = self.validate();
+        if !report.is_ok() {
+            return Err(LevelFileError::Invalid(report));
+        }
+        Ok(())
+    }
+
+    /// Checks every chunk table entry against `buffer`: ranges must lie in the data section and
+    /// not overlap, keys must be unique, and each RLE stream must be whole `(type, length)` pairs
+    /// describing at most `CS_P3` voxels. Shorter streams, including empty entries, are reported
+    /// as [`ValidationIssue::ShortStream`] without failing the report.
+    pub fn validate(&self) -> ValidationReport {
+        validate_chunk_table(&self.chunk_table, &self.buffer, self.data_start, true)
+    }
+
+    /// RLE-encodes a padded `CS_P3` voxel buffer and stores it under `key`, replacing any chunk
+    /// already stored there. The encoded data is appended to `buffer`; space used by a replaced
+    /// chunk is only reclaimed by [`LevelFile::save_to_file`].
+    pub fn insert_chunk(&mut self, key: u32, voxels: &[u8]) -> Result<()> {
+        anyhow::ensure!(voxels.len() == CS_P3, "Chunk voxels must be CS_P3 ({CS_P3}) bytes, got {}", voxels.len());
+        let (x, y, z) = parse_xyz_key(key);
+        anyhow::ensure!(
+            x <= MAX_CHUNK && y <= MAX_CHUNK && z <= MAX_CHUNK,
+            "Chunk ({x}, {y}, {z}) is outside the maximum level size"
+        );
+
+        let rle = rle::encode_sparse_trailing_zeros(voxels);
+        let entry = ChunkTableEntry {
+            key,
+            rle_data_begin: u32::try_from(self.buffer.len()).context("Level data exceeds 4 GiB")?,
+            rle_data_size: rle.len() as u32,
+        };
+        self.buffer.extend_from_slice(&rle);
+
+        match self.chunk_table.iter_mut().find(|e| e.key == key) {
+            Some(existing) => *existing = entry,
+            None => self.chunk_table.push(entry),
+        }
+        self.dims = [self.dims[0].max(x + 1), self.dims[1].max(y + 1), self.dims[2].max(z + 1)];
+        Ok(())
+    }
+
+    /// Removes the chunk stored under `key`, returning its table entry if it existed. Its RLE data
+    /// stays in `buffer` until the next [`LevelFile::save_to_file`].
+    pub fn remove_chunk(&mut self, key: u32) -> Option<ChunkTableEntry> {
+        let index = self.chunk_table.iter().position(|e| e.key == key)?;
+        Some(self.chunk_table.remove(index))
+    }
+
+    /// Writes the level in the current versioned layout: the header, the metadata block, a
+    /// sparse chunk table holding one entry per stored chunk, then the RLE data packed in table
+    /// order. Afterwards `buffer` and `chunk_table` describe the written file, which drops the
+    /// data of replaced and removed chunks from memory as well.
+    pub fn save_to_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        let metadata = self.metadata.to_bytes()?;
+        let table_len = self.chunk_table.len();
+        let table_start = HEADER_SIZE_V3 + metadata.len();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&LEVEL_MAGIC);
+        bytes.extend_from_slice(&LEVEL_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.dims);
+        bytes.push(0);
+        bytes.extend_from_slice(&(table_len as u32).to_le_bytes());
+        bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // CRC, filled in below
+        bytes.extend_from_slice(&metadata);
+        bytes.resize(table_start + table_len * CHUNK_TABLE_ENTRY_SIZE, 0u8);
+
+        let data_start = bytes.len();
+        let mut chunk_table = Vec::with_capacity(table_len);
+        let mut table_offset = table_start;
+        for entry in &self.chunk_table {
+            let rle = self.chunk_data(entry)?;
+
+            let begin = u32::try_from(bytes.len()).context("Level data exceeds 4 GiB")?;
+            let sz = rle.len() as u32;
+            bytes[table_offset..table_offset + 4].copy_from_slice(&entry.key.to_le_bytes());
+            bytes[table_offset + 4..table_offset + 8].copy_from_slice(&begin.to_le_bytes());
+            bytes[table_offset + 8..table_offset + 12].copy_from_slice(&sz.to_le_bytes());
+            bytes.extend_from_slice(rle);
+            chunk_table.push(ChunkTableEntry {
+                key: entry.key,
+                rle_data_begin: begin,
+                rle_data_size: sz,
             });
+
+            table_offset += CHUNK_TABLE_ENTRY_SIZE;
         }
+
+        let crc = crc32fast::hash(&bytes[HEADER_SIZE_V3..]);
+        bytes[HEADER_SIZE_V3 - 4..HEADER_SIZE_V3].copy_from_slice(&crc.to_le_bytes());
+
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent).with_context(|| format!("create dir: {}", parent.display()))?;
+        }
+
+        fs::write(path, &bytes).with_context(|| format!("write level file: {}", path.display()))?;
+
+        self.chunk_table = chunk_table;
         self.buffer = bytes;
+        self.data_start = data_start;
         Ok(())
     }
+
+    /// RLE data of `entry` within `buffer`.
+    pub fn chunk_data(&self, entry: &ChunkTableEntry) -> Result<&[u8]> {
+        let start = entry.rle_data_begin as usize;
+        let end = start + entry.rle_data_size as usize;
+        self.buffer
+            .get(start..end)
+            .with_context(|| format!("Chunk {:#08x} data is out of bounds", entry.key))
+    }
+}
+
+impl LevelMetadata {
+    // generator_seed: u64, entry_count: u16, then per entry a u16-length-prefixed UTF-8 key and
+    // value.
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.generator_seed.to_le_bytes());
+        let count = u16::try_from(self.entries.len()).context("Too many metadata entries")?;
+        out.extend_from_slice(&count.to_le_bytes());
+        for (key, value) in &self.entries {
+            for s in [key, value] {
+                let len = u16::try_from(s.len()).with_context(|| format!("Metadata string too long: {key}"))?;
+                out.extend_from_slice(&len.to_le_bytes());
+                out.extend_from_slice(s.as_bytes());
+            }
+        }
+        Ok(out)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let generator_seed = u64::from_le_bytes(bytes.get(0..8)?.try_into().ok()?);
+        let count = u16::from_le_bytes(bytes.get(8..10)?.try_into().ok()?);
+
+        let mut p = 10usize;
+        let mut read_string = || -> Option<String> {
+            let len = u16::from_le_bytes(bytes.get(p..p + 2)?.try_into().ok()?) as usize;
+            let s = std::str::from_utf8(bytes.get(p + 2..p + 2 + len)?).ok()?.to_string();
+            p += 2 + len;
+            Some(s)
+        };
+
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let key = read_string()?;
+            let value = read_string()?;
+            entries.push((key, value));
+        }
+        Some(Self { generator_seed, entries })
+    }
+}
+
+#[inline]
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// A level's header and chunk table, decoded without copying the RLE data.
+pub(crate) struct ParsedLevel {
+    pub dims: [u8; 3],
+    pub metadata: LevelMetadata,
+    pub chunk_table: Vec<ChunkTableEntry>,
+    // Offset of the first byte of RLE data (after the header, metadata and chunk table).
+    pub data_start: usize,
+}
+
+/// Parses the header and chunk table of a legacy or versioned level file. `verify_checksum`
+/// reads every byte of the file, so lazy loaders can skip it and rely on checked decoding.
+pub(crate) fn parse_level(bytes: &[u8], verify_checksum: bool) -> Result<ParsedLevel, LevelFileError> {
+    let dims;
+    let mut metadata = LevelMetadata::default();
+
+    let (table_start, table_len) = if bytes.starts_with(&LEVEL_MAGIC) {
+        let corrupt = |reason: &str| LevelFileError::Corrupt(reason.to_string());
+        let version = bytes
+            .get(4..6)
+            .map(|v| u16::from_le_bytes(v.try_into().unwrap()))
+            .ok_or_else(|| corrupt("truncated header"))?;
+        let header_size = match version {
+            2 => HEADER_SIZE_V2,
+            3 => HEADER_SIZE_V3,
+            _ => return Err(LevelFileError::UnsupportedVersion(version)),
+        };
+        if bytes.len() < header_size {
+            return Err(corrupt("truncated header"));
+        }
+
+        dims = [bytes[6], bytes[7], bytes[8]];
+        let chunk_count = read_u32(bytes, 10) as usize;
+
+        if version == 2 {
+            (HEADER_SIZE_V2, chunk_count)
+        } else {
+            let metadata_size = read_u32(bytes, 14) as usize;
+            let crc = read_u32(bytes, 18);
+            if verify_checksum && crc32fast::hash(&bytes[HEADER_SIZE_V3..]) != crc {
+                return Err(corrupt("checksum mismatch"));
+            }
+
+            let metadata_bytes = bytes
+                .get(HEADER_SIZE_V3..HEADER_SIZE_V3 + metadata_size)
+                .ok_or_else(|| corrupt("truncated metadata block"))?;
+            metadata = LevelMetadata::from_bytes(metadata_bytes).ok_or_else(|| corrupt("malformed metadata block"))?;
+            (HEADER_SIZE_V3 + metadata_size, chunk_count)
+        }
+    } else {
+        let size = *bytes.first().ok_or_else(|| LevelFileError::WrongFormat("file is empty".to_string()))?;
+        if size == 0 {
+            return Err(LevelFileError::WrongFormat("legacy level size is 0".to_string()));
+        }
+        dims = [size, 1, size];
+        (1, (size as usize) * (size as usize))
+    };
+
+    let table_bytes = table_len * CHUNK_TABLE_ENTRY_SIZE;
+    if bytes.len() < table_start + table_bytes {
+        let reason = "truncated chunk table".to_string();
+        return Err(if table_start == 1 {
+            LevelFileError::WrongFormat(reason)
+        } else {
+            LevelFileError::Corrupt(reason)
+        });
+    }
+
+    let table_end = table_start + table_bytes;
+    let table_slice = &bytes[table_start..table_end];
+    // The on-disk chunk table is tightly packed bytes; it may not be aligned for safe casting.
+    // Decode manually as little-endian u32 triplets.
+    let mut chunk_table = Vec::with_capacity(table_len);
+    for i in 0..table_len {
+        let base = i * 12;
+        chunk_table.push(ChunkTableEntry {
+            key: read_u32(table_slice, base),
+            rle_data_begin: read_u32(table_slice, base + 4),
+            rle_data_size: read_u32(table_slice, base + 8),
+        });
+    }
+
+    Ok(ParsedLevel {
+        dims,
+        metadata,
+        chunk_table,
+        data_start: table_end,
+    })
+}
+
+/// See [`LevelFile::validate`]. Without `check_streams` only the table itself is checked, which
+/// avoids touching the RLE data.
+pub(crate) fn validate_chunk_table(
+    chunk_table: &[ChunkTableEntry],
+    buffer: &[u8],
+    data_start: usize,
+    check_streams: bool,
+) -> ValidationReport {
+    let mut issues = Vec::new();
+    let mut first_by_key: HashMap<u32, usize> = HashMap::new();
+    let mut ranges: Vec<(usize, usize, usize)> = Vec::new();
+
+    for (index, entry) in chunk_table.iter().enumerate() {
+        let key = entry.key;
+        if let Some(&first) = first_by_key.get(&key) {
+            issues.push(ValidationIssue::DuplicateKey { key, first, second: index });
+        } else {
+            first_by_key.insert(key, index);
+        }
+
+        let begin = entry.rle_data_begin as usize;
+        let end = begin + entry.rle_data_size as usize;
+        if entry.rle_data_size == 0 {
+            if check_streams {
+                issues.push(ValidationIssue::ShortStream { index, key, decoded: 0 });
+            }
+            continue;
+        }
+        if begin < data_start || end > buffer.len() {
+            issues.push(ValidationIssue::OutOfBounds {
+                index,
+                key,
+                begin: entry.rle_data_begin,
+                size: entry.rle_data_size,
+            });
+            continue;
+        }
+        ranges.push((begin, end, index));
+
+        if check_streams {
+            match rle::decoded_len(&buffer[begin..end], CS_P3) {
+                Ok(decoded) if decoded < CS_P3 => issues.push(ValidationIssue::ShortStream { index, key, decoded }),
+                Ok(_) => {}
+                Err(error) => issues.push(ValidationIssue::Rle { index, key, error }),
+            }
+        }
+    }
+
+    ranges.sort_unstable();
+    let mut furthest: Option<(usize, usize)> = None; // (end, index)
+    for &(begin, end, index) in &ranges {
+        if let Some((furthest_end, furthest_index)) = furthest {
+            if begin < furthest_end {
+                let (first, second) = (furthest_index.min(index), furthest_index.max(index));
+                issues.push(ValidationIssue::Overlap { first, second });
+            }
+            if end <= furthest_end {
+                continue;
+            }
+        }
+        furthest = Some((end, index));
+    }
+
+    ValidationReport { issues }
 }
@@ -0,0 +1,84 @@
+use crate::data::level_file::{parse_level, validate_chunk_table, ChunkTableEntry, LevelFileError, LevelMetadata};
+use crate::data::rle::{self, RleError};
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+/// Read-only level backed by a memory map of the file.
+///
+/// Only the header and chunk table are read when opening; chunk data is paged in by the OS
+/// when a chunk is looked up or decoded. Opening checks the table against the file size but
+/// not the checksum or the RLE streams, so decode through [`MappedLevel::decode_chunk`].
+pub struct MappedLevel {
+    mmap: Mmap,
+    chunk_table: Vec<ChunkTableEntry>,
+    index: HashMap<u32, usize>,
+    metadata: LevelMetadata,
+    dims: [u8; 3],
+}
+
+impl MappedLevel {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LevelFileError> {
+        let io_error = |source| LevelFileError::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        };
+        let file = File::open(&path).map_err(io_error)?;
+        // SAFETY: the map is read-only; as with any mapped file, modifying it on disk while
+        // it is open is not supported.
+        let mmap = unsafe { Mmap::map(&file) }.map_err(io_error)?;
+
+        let parsed = parse_level(&mmap, false)?;
+        let report = validate_chunk_table(&parsed.chunk_table, &mmap, parsed.data_start, false);
+        if !report.is_ok() {
+            return Err(LevelFileError::Invalid(report));
+        }
+
+        let index = parsed
+            .chunk_table
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.key, i))
+            .collect();
+
+        Ok(Self {
+            mmap,
+            chunk_table: parsed.chunk_table,
+            index,
+            metadata: parsed.metadata,
+            dims: parsed.dims,
+        })
+    }
+
+    /// Level extent in chunks along X, Y and Z.
+    pub fn dims(&self) -> [u8; 3] {
+        self.dims
+    }
+
+    pub fn metadata(&self) -> &LevelMetadata {
+        &self.metadata
+    }
+
+    pub fn chunk_table(&self) -> &[ChunkTableEntry] {
+        &self.chunk_table
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = u32> + '_ {
+        self.chunk_table.iter().map(|entry| entry.key)
+    }
+
+    /// Raw RLE data of the chunk stored under `key`.
+    pub fn chunk(&self, key: u32) -> Option<&[u8]> {
+        let entry = &self.chunk_table[*self.index.get(&key)?];
+        let begin = entry.rle_data_begin as usize;
+        Some(&self.mmap[begin..begin + entry.rle_data_size as usize])
+    }
+
+    /// Decodes the chunk stored under `key` with the checked RLE decoder. Returns `None` if the
+    /// level has no such chunk.
+    pub fn decode_chunk(&self, key: u32, voxels: &mut [u8], opaque_mask: &mut [u64]) -> Option<Result<usize, RleError>> {
+        let rle = self.chunk(key)?;
+        Some(rle::try_decompress_to_voxels_and_opaque_mask(rle, voxels, opaque_mask))
+    }
+}
@@ -1,2 +1,5 @@
+pub mod blocks;
 pub mod level_file;
+pub mod mapped_level;
 pub mod rle;
+pub mod vox;
@@ -1,4 +1,30 @@
-use crate::{CS_P2};
+use crate::CS_P2;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RleError {
+    /// The run starting at byte `offset` would write past the end of the voxel buffer.
+    /// `decoded` voxels were described by the runs before it.
+    Overrun { offset: usize, decoded: usize, run_length: usize },
+    /// The stream ends with a type byte at `offset` that has no run length.
+    TrailingByte { offset: usize, decoded: usize },
+}
+
+impl fmt::Display for RleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RleError::Overrun { offset, decoded, run_length } => write!(
+                f,
+                "run at byte {offset} (length {run_length}) overruns the voxel buffer after {decoded} voxels"
+            ),
+            RleError::TrailingByte { offset, decoded } => {
+                write!(f, "dangling type byte at {offset} after {decoded} voxels")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RleError {}
 
 #[inline]
 fn get_bit_range(low: u8, high: u8) -> u64 {
@@ -11,6 +37,48 @@ fn get_bit_range(low: u8, high: u8) -> u64 {
     }
 }
 
+/// Number of voxels `rle` describes, checking that it is whole `(type, length)` pairs that fit in
+/// `capacity` voxels.
+pub fn decoded_len(rle: &[u8], capacity: usize) -> Result<usize, RleError> {
+    let mut decoded = 0usize;
+    for (pair, run) in rle.chunks_exact(2).enumerate() {
+        let run_length = run[1] as usize;
+        if decoded + run_length > capacity {
+            return Err(RleError::Overrun {
+                offset: pair * 2,
+                decoded,
+                run_length,
+            });
+        }
+        decoded += run_length;
+    }
+    if !rle.len().is_multiple_of(2) {
+        return Err(RleError::TrailingByte {
+            offset: rle.len() - 1,
+            decoded,
+        });
+    }
+    Ok(decoded)
+}
+
+/// Checked variant of [`decompress_to_voxels_and_opaque_mask`] for untrusted data.
+///
+/// The whole stream is validated before anything is written. On success `opaque_mask` is
+/// rebuilt from scratch, voxels past the end of the stream are set to air, and the number of
+/// voxels the stream described is returned.
+pub fn try_decompress_to_voxels_and_opaque_mask(
+    rle: &[u8],
+    voxels: &mut [u8],
+    opaque_mask: &mut [u64],
+) -> Result<usize, RleError> {
+    let decoded = decoded_len(rle, voxels.len().min(opaque_mask.len() * 64))?;
+
+    opaque_mask.fill(0);
+    decompress_to_voxels_and_opaque_mask(rle, voxels, opaque_mask);
+    voxels[decoded..].fill(0);
+    Ok(decoded)
+}
+
 pub fn decompress_to_voxels_and_opaque_mask(rle: &[u8], voxels: &mut [u8], opaque_mask: &mut [u64]) {
     debug_assert_eq!(opaque_mask.len(), CS_P2);
 
@@ -44,7 +112,7 @@ pub fn decompress_to_voxels_and_opaque_mask(rle: &[u8], voxels: &mut [u8], opaqu
                         opaque_mask_bit_index + (remaining as u8) - 1,
                     );
                 }
-                opaque_mask_bit_index = opaque_mask_bit_index + remaining as u8;
+                opaque_mask_bit_index += remaining as u8;
                 remaining = 0;
             } else if remaining >= 64 && opaque_mask_bit_index == 0 {
                 let count = remaining / 64;
@@ -68,3 +136,40 @@ pub fn decompress_to_voxels_and_opaque_mask(rle: &[u8], voxels: &mut [u8], opaqu
         u_i += len;
     }
 }
+
+/// Encodes `voxels` as `(type, run_length)` byte pairs, splitting runs longer than 255.
+/// [`decompress_to_voxels_and_opaque_mask`] reproduces the input exactly.
+pub fn encode(voxels: &[u8]) -> Vec<u8> {
+    encode_runs(voxels)
+}
+
+/// Like [`encode`], but drops the trailing run of air. The decoders leave anything past the end
+/// of the stream as air, so a chunk with empty upper layers costs nothing to store. This is the
+/// encoding written to level files.
+pub fn encode_sparse_trailing_zeros(voxels: &[u8]) -> Vec<u8> {
+    let end = voxels
+        .iter()
+        .rposition(|&v| v != 0)
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    encode_runs(&voxels[..end])
+}
+
+fn encode_runs(voxels: &[u8]) -> Vec<u8> {
+    let mut out = Vec::<u8>::new();
+    let mut i = 0usize;
+
+    while i < voxels.len() {
+        let ty = voxels[i];
+        let mut run = 1usize;
+        while i + run < voxels.len() && voxels[i + run] == ty && run < (u8::MAX as usize) {
+            run += 1;
+        }
+
+        out.push(ty);
+        out.push(run as u8);
+        i += run;
+    }
+
+    out
+}
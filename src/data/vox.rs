@@ -0,0 +1,534 @@
+use crate::data::blocks::BlockRegistry;
+use crate::data::level_file::LevelFile;
+use crate::data::rle;
+use crate::{get_xyz_key, get_zxy_index, parse_xyz_key, CS, CS_P2, CS_P3, MAX_CHUNK};
+use anyhow::{bail, ensure, Context, Result};
+use std::collections::HashMap;
+
+/// Identifies MagicaVoxel files; followed by a little-endian `i32` version (150 or 200).
+pub const VOX_MAGIC: [u8; 4] = *b"VOX ";
+/// Version written by [`VoxFile::to_bytes`].
+pub const VOX_VERSION: i32 = 150;
+/// Largest model edge MagicaVoxel accepts.
+pub const VOX_MAX_MODEL_SIZE: u32 = 256;
+
+/// One `SIZE`/`XYZI` pair. Voxels are `[x, y, z, colour index]` in MagicaVoxel's Z-up space;
+/// colour index 0 is unused (empty).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VoxModel {
+    pub size: [u32; 3],
+    pub voxels: Vec<[u8; 4]>,
+}
+
+/// A model placed in the scene by the `nTRN`/`nGRP`/`nSHP` node graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoxInstance {
+    pub model: usize,
+    /// Position of the model's centre (`size / 2`, rounded down).
+    pub translation: [i32; 3],
+    /// Rows of the model's rotation, applied around its centre.
+    pub rotation: [[i32; 3]; 3],
+}
+
+/// The parts of a `.vox` file the level converter uses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// `RGBA` chunk contents; entry `i` is the colour of index `i + 1`.
+    pub palette: Option<[[u8; 4]; 256]>,
+    /// Empty when the file has no scene graph, in which case every model sits at the origin.
+    pub instances: Vec<VoxInstance>,
+}
+
+/// How `.vox` colour indices become voxel types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteMapping {
+    /// The type whose registry colour is closest to the palette colour. Files without an `RGBA`
+    /// chunk fall back to [`PaletteMapping::Index`].
+    Nearest,
+    /// The colour index itself (1..=255).
+    Index,
+}
+
+const IDENTITY: [[i32; 3]; 3] = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+enum Node {
+    Transform { child: i32, translation: [i32; 3], rotation: [[i32; 3]; 3] },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<i32> },
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.offset.checked_add(len).filter(|&end| end <= self.bytes.len());
+        let Some(end) = end else {
+            bail!("Unexpected end of .vox data at offset {} (wanted {len} bytes)", self.offset);
+        };
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let offset = self.offset;
+        usize::try_from(self.i32()?).with_context(|| format!("Negative length at offset {offset}"))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.len()?;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>> {
+        let count = self.len()?;
+        (0..count).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+}
+
+impl VoxFile {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        ensure!(bytes.len() >= 8 && bytes[..4] == VOX_MAGIC, "Not a MagicaVoxel file (missing \"VOX \" magic)");
+        let mut r = Reader { bytes, offset: 8 };
+
+        let id = r.take(4)?;
+        ensure!(id == b"MAIN", "Expected MAIN chunk, found {:?}", String::from_utf8_lossy(id));
+        let content_size = r.len()?;
+        let children_size = r.len()?;
+        r.take(content_size)?;
+        let end = r.offset + children_size;
+        ensure!(end <= bytes.len(), "MAIN chunk extends past the end of the file");
+
+        let mut models = Vec::new();
+        let mut size: Option<[u32; 3]> = None;
+        let mut palette = None;
+        let mut nodes: HashMap<i32, Node> = HashMap::new();
+
+        while r.offset < end {
+            let chunk_offset = r.offset;
+            let id: [u8; 4] = r.take(4)?.try_into().unwrap();
+            let content_size = r.len()?;
+            let children_size = r.len()?;
+            let mut c = Reader { bytes: r.take(content_size)?, offset: 0 };
+            r.take(children_size)?;
+
+            let context = || format!("{} chunk at offset {chunk_offset}", String::from_utf8_lossy(&id));
+            match &id {
+                b"SIZE" => {
+                    let dims = [c.i32()?, c.i32()?, c.i32()?];
+                    ensure!(dims.iter().all(|&d| d > 0 && d <= 256), "Invalid model size {dims:?} in {}", context());
+                    size = Some(dims.map(|d| d as u32));
+                }
+                b"XYZI" => {
+                    let size = size.take().with_context(|| format!("{} without a preceding SIZE", context()))?;
+                    let count = c.len().with_context(context)?;
+                    let voxels: Vec<[u8; 4]> = c
+                        .take(count * 4)
+                        .with_context(context)?
+                        .chunks_exact(4)
+                        .map(|v| [v[0], v[1], v[2], v[3]])
+                        .collect();
+                    if let Some(v) = voxels.iter().find(|v| (0..3).any(|i| v[i] as u32 >= size[i])) {
+                        bail!("Voxel {v:?} lies outside model size {size:?} in {}", context());
+                    }
+                    models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    let mut colors = [[0u8; 4]; 256];
+                    for (color, rgba) in colors.iter_mut().zip(c.take(1024).with_context(context)?.chunks_exact(4)) {
+                        *color = [rgba[0], rgba[1], rgba[2], rgba[3]];
+                    }
+                    palette = Some(colors);
+                }
+                b"nTRN" => {
+                    let (id, node) = parse_transform(&mut c).with_context(context)?;
+                    nodes.insert(id, node);
+                }
+                b"nGRP" => {
+                    let id = c.i32()?;
+                    c.dict()?;
+                    let count = c.len()?;
+                    let children = (0..count).map(|_| c.i32()).collect::<Result<_>>().with_context(context)?;
+                    nodes.insert(id, Node::Group { children });
+                }
+                b"nSHP" => {
+                    let id = c.i32()?;
+                    c.dict()?;
+                    let count = c.len()?;
+                    let mut shape_models = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        shape_models.push(c.i32().with_context(context)?);
+                        c.dict().with_context(context)?;
+                    }
+                    nodes.insert(id, Node::Shape { models: shape_models });
+                }
+                // PACK, MATL, LAYR, rOBJ, rCAM, NOTE, IMAP, ...
+                _ => {}
+            }
+        }
+
+        let mut instances = Vec::new();
+        if nodes.contains_key(&0) {
+            collect_instances(&nodes, 0, [0; 3], IDENTITY, &mut instances, 0)?;
+        }
+        if let Some(instance) = instances.iter().find(|i| i.model >= models.len()) {
+            bail!("Scene references model {} but the file has {}", instance.model, models.len());
+        }
+
+        Ok(Self { models, palette, instances })
+    }
+
+    /// Every voxel of the scene as `(position, colour index)` in MagicaVoxel's Z-up space.
+    pub fn world_voxels(&self) -> impl Iterator<Item = ([i32; 3], u8)> + '_ {
+        let placed: Vec<VoxInstance> = if self.instances.is_empty() {
+            (0..self.models.len())
+                .map(|model| {
+                    let half = self.models[model].size.map(|s| (s / 2) as i32);
+                    VoxInstance { model, translation: half, rotation: IDENTITY }
+                })
+                .collect()
+        } else {
+            self.instances.clone()
+        };
+
+        placed.into_iter().flat_map(move |instance| {
+            let model = &self.models[instance.model];
+            let half = model.size.map(|s| (s / 2) as i32);
+            model.voxels.iter().map(move |v| {
+                let local = [v[0] as i32 - half[0], v[1] as i32 - half[1], v[2] as i32 - half[2]];
+                let rotated = transform(&instance.rotation, local);
+                ([0, 1, 2].map(|i| instance.translation[i] + rotated[i]), v[3])
+            })
+        })
+    }
+
+    /// Voxel type of every colour index under `mapping`, matching colours against `blocks`.
+    /// Index 0 (empty) maps to air.
+    pub fn type_table(&self, mapping: PaletteMapping, blocks: &BlockRegistry) -> [u8; 256] {
+        let mut table = [0u8; 256];
+        for (index, ty) in table.iter_mut().enumerate().skip(1) {
+            *ty = match (mapping, &self.palette) {
+                (PaletteMapping::Nearest, Some(palette)) => nearest_type(blocks, palette[index - 1]),
+                _ => index as u8,
+            };
+        }
+        table
+    }
+
+    /// Converts the scene into a level. MagicaVoxel's Z-up axes become Y-up (`x, z, -y`) and
+    /// the scene is moved so its lowest corner sits at the origin.
+    ///
+    /// Each chunk is stored with the 1-voxel padding taken from its neighbours; chunks that
+    /// would only hold padding are left out.
+    pub fn to_level(&self, mapping: PaletteMapping, blocks: &BlockRegistry) -> Result<LevelFile> {
+        let types = self.type_table(mapping, blocks);
+        let voxels: Vec<([i32; 3], u8)> = self
+            .world_voxels()
+            .map(|([x, y, z], index)| ([x, z, -y], types[index as usize]))
+            .filter(|&(_, ty)| ty != 0)
+            .collect();
+
+        let mut min = [i32::MAX; 3];
+        for (pos, _) in &voxels {
+            for i in 0..3 {
+                min[i] = min[i].min(pos[i]);
+            }
+        }
+
+        let cs = CS as i32;
+        let mut chunks: HashMap<[i32; 3], (Vec<u8>, bool)> = HashMap::new();
+        for (pos, ty) in voxels {
+            let world = [0, 1, 2].map(|i| pos[i] - min[i]);
+            let home = world.map(|w| w / cs);
+            ensure!(
+                home.iter().all(|&c| c <= MAX_CHUNK as i32),
+                "Scene is too large: voxel at {world:?} falls in chunk {home:?}"
+            );
+
+            // Each voxel is interior to one chunk and padding in up to seven neighbours.
+            for dz in -1..=1 {
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let chunk = [home[0] + dx, home[1] + dy, home[2] + dz];
+                        let local = [0, 1, 2].map(|i| world[i] - chunk[i] * cs + 1);
+                        if chunk.iter().any(|&c| c < 0) || local.iter().any(|&l| !(0..cs + 2).contains(&l)) {
+                            continue;
+                        }
+
+                        let (chunk_voxels, interior) =
+                            chunks.entry(chunk).or_insert_with(|| (vec![0u8; CS_P3], false));
+                        chunk_voxels[get_zxy_index(local[0] as usize, local[1] as usize, local[2] as usize)] = ty;
+                        *interior |= (dx, dy, dz) == (0, 0, 0);
+                    }
+                }
+            }
+        }
+
+        let mut keys: Vec<[i32; 3]> = chunks.iter().filter(|(_, (_, interior))| *interior).map(|(k, _)| *k).collect();
+        keys.sort_unstable_by_key(|&[x, y, z]| (z, y, x));
+
+        let mut level = LevelFile::default();
+        for [x, y, z] in keys {
+            level.insert_chunk(get_xyz_key(x as u8, y as u8, z as u8), &chunks[&[x, y, z]].0)?;
+        }
+        Ok(level)
+    }
+
+    /// Collects the interior voxels of every chunk for which `include` returns true, splitting
+    /// them into models of at most [`VOX_MAX_MODEL_SIZE`]³ placed by a scene graph.
+    ///
+    /// The level's Y-up axes become MagicaVoxel's Z-up (`x, -z, y`), the inverse of
+    /// [`VoxFile::to_level`]. Colour index `n` is voxel type `n`, coloured from `blocks`, so
+    /// importing with [`PaletteMapping::Index`] gives back the same types.
+    pub fn from_level(
+        level: &LevelFile,
+        blocks: &BlockRegistry,
+        include: impl Fn((u8, u8, u8)) -> bool,
+    ) -> Result<Self> {
+        let entries: Vec<_> = level.chunk_table.iter().filter(|e| include(parse_xyz_key(e.key))).collect();
+
+        // Tiles start at the lowest corner of the selected chunks, in MagicaVoxel axes.
+        let cs = CS as i32;
+        let mut origin = [i32::MAX; 3];
+        for entry in &entries {
+            let (x, y, z) = parse_xyz_key(entry.key);
+            let corner = [x as i32 * cs, -((z as i32 + 1) * cs - 1), y as i32 * cs];
+            for i in 0..3 {
+                origin[i] = origin[i].min(corner[i]);
+            }
+        }
+
+        let tile_size = VOX_MAX_MODEL_SIZE as i32;
+        let mut tiles: HashMap<[i32; 3], Vec<[u8; 4]>> = HashMap::new();
+        let mut voxels = vec![0u8; CS_P3];
+        let mut mask = vec![0u64; CS_P2];
+        for entry in entries {
+            let (cx, cy, cz) = parse_xyz_key(entry.key);
+            rle::try_decompress_to_voxels_and_opaque_mask(level.chunk_data(entry)?, &mut voxels, &mut mask)
+                .with_context(|| format!("Failed to decode chunk ({cx}, {cy}, {cz})"))?;
+
+            for y in 1..=CS {
+                for x in 1..=CS {
+                    for z in 1..=CS {
+                        let ty = voxels[get_zxy_index(x, y, z)];
+                        if ty == 0 {
+                            continue;
+                        }
+                        let world = [
+                            cx as i32 * cs + x as i32 - 1,
+                            cy as i32 * cs + y as i32 - 1,
+                            cz as i32 * cs + z as i32 - 1,
+                        ];
+                        let vox = [world[0], -world[2], world[1]];
+                        let offset = [0, 1, 2].map(|i| vox[i] - origin[i]);
+                        let tile = offset.map(|o| o / tile_size);
+                        let local = offset.map(|o| (o % tile_size) as u8);
+                        tiles.entry(tile).or_default().push([local[0], local[1], local[2], ty]);
+                    }
+                }
+            }
+        }
+
+        let mut tiles: Vec<_> = tiles.into_iter().collect();
+        tiles.sort_unstable_by_key(|&([x, y, z], _)| (z, y, x));
+
+        let mut models = Vec::with_capacity(tiles.len());
+        let mut instances = Vec::with_capacity(tiles.len());
+        for (tile, voxels) in tiles {
+            let mut size = [1u32; 3];
+            for v in &voxels {
+                for i in 0..3 {
+                    size[i] = size[i].max(v[i] as u32 + 1);
+                }
+            }
+            let translation = [0, 1, 2].map(|i| origin[i] + tile[i] * tile_size + (size[i] / 2) as i32);
+            instances.push(VoxInstance { model: models.len(), translation, rotation: IDENTITY });
+            models.push(VoxModel { size, voxels });
+        }
+
+        let mut palette = [[0u8; 4]; 256];
+        for (i, color) in palette.iter_mut().enumerate().take(255) {
+            let [r, g, b] = blocks.color(i as u8 + 1).map(|c| (c * 255.0).round() as u8);
+            *color = [r, g, b, 255];
+        }
+
+        Ok(Self { models, palette: Some(palette), instances })
+    }
+
+    /// Serializes the file: every model's `SIZE`/`XYZI`, a scene graph placing each instance
+    /// (root transform, one group, a transform and shape per instance) and the palette.
+    /// Rotations are written as the identity.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut children = Vec::new();
+        for model in &self.models {
+            write_chunk(&mut children, b"SIZE", &ints(&model.size.map(|s| s as i32)));
+            let mut xyzi = ints(&[model.voxels.len() as i32]);
+            xyzi.extend(model.voxels.iter().flatten());
+            write_chunk(&mut children, b"XYZI", &xyzi);
+        }
+
+        if !self.instances.is_empty() {
+            let count = self.instances.len() as i32;
+            write_chunk(&mut children, b"nTRN", &transform_node(0, 1, -1, [0; 3]));
+            let mut group = ints(&[1]);
+            group.extend(dict(&[]));
+            group.extend(ints(&[count]));
+            group.extend((0..count).flat_map(|i| (2 + 2 * i).to_le_bytes()));
+            write_chunk(&mut children, b"nGRP", &group);
+
+            for (i, instance) in self.instances.iter().enumerate() {
+                let id = 2 + 2 * i as i32;
+                write_chunk(&mut children, b"nTRN", &transform_node(id, id + 1, 0, instance.translation));
+                let mut shape = ints(&[id + 1]);
+                shape.extend(dict(&[]));
+                shape.extend(ints(&[1, instance.model as i32]));
+                shape.extend(dict(&[]));
+                write_chunk(&mut children, b"nSHP", &shape);
+            }
+        }
+
+        if let Some(palette) = &self.palette {
+            write_chunk(&mut children, b"RGBA", palette.as_flattened());
+        }
+
+        let mut out = VOX_MAGIC.to_vec();
+        out.extend_from_slice(&VOX_VERSION.to_le_bytes());
+        out.extend_from_slice(b"MAIN");
+        out.extend(ints(&[0, children.len() as i32]));
+        out.extend(children);
+        out
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend(ints(&[content.len() as i32, 0]));
+    out.extend_from_slice(content);
+}
+
+fn ints(values: &[i32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn dict(entries: &[(&str, String)]) -> Vec<u8> {
+    let mut out = ints(&[entries.len() as i32]);
+    for (key, value) in entries {
+        for s in [*key, value.as_str()] {
+            out.extend(ints(&[s.len() as i32]));
+            out.extend_from_slice(s.as_bytes());
+        }
+    }
+    out
+}
+
+fn transform_node(id: i32, child: i32, layer: i32, [x, y, z]: [i32; 3]) -> Vec<u8> {
+    let mut content = ints(&[id]);
+    content.extend(dict(&[]));
+    content.extend(ints(&[child, -1, layer, 1]));
+    content.extend(dict(&[("_t", format!("{x} {y} {z}"))]));
+    content
+}
+
+fn parse_transform(c: &mut Reader) -> Result<(i32, Node)> {
+    let id = c.i32()?;
+    c.dict()?;
+    let child = c.i32()?;
+    let _reserved = c.i32()?;
+    let _layer = c.i32()?;
+    let frames = c.len()?;
+
+    let mut translation = [0; 3];
+    let mut rotation = IDENTITY;
+    // Only the first animation frame is used.
+    for frame in 0..frames {
+        let attributes = c.dict()?;
+        if frame != 0 {
+            continue;
+        }
+        if let Some(t) = attributes.get("_t") {
+            let parts: Vec<i32> = t.split_whitespace().map(str::parse).collect::<Result<_, _>>()?;
+            translation = parts.try_into().map_err(|_| anyhow::anyhow!("Invalid translation {t:?}"))?;
+        }
+        if let Some(r) = attributes.get("_r") {
+            rotation = r
+                .parse()
+                .ok()
+                .and_then(rotation_matrix)
+                .with_context(|| format!("Invalid rotation {r:?}"))?;
+        }
+    }
+    Ok((id, Node::Transform { child, translation, rotation }))
+}
+
+fn collect_instances(
+    nodes: &HashMap<i32, Node>,
+    id: i32,
+    translation: [i32; 3],
+    rotation: [[i32; 3]; 3],
+    instances: &mut Vec<VoxInstance>,
+    depth: usize,
+) -> Result<()> {
+    ensure!(depth < 256, "Scene graph is cyclic or too deep");
+    match nodes.get(&id).with_context(|| format!("Scene references missing node {id}"))? {
+        // Transforms are relative to the parent: world = parent_r * (r * p + t) + parent_t.
+        Node::Transform { child, translation: t, rotation: r } => {
+            let offset = transform(&rotation, *t);
+            let translation = [0, 1, 2].map(|i| translation[i] + offset[i]);
+            let rotation = [0, 1, 2].map(|row| [0, 1, 2].map(|col| (0..3).map(|k| rotation[row][k] * r[k][col]).sum()));
+            if let Some(Node::Shape { models }) = nodes.get(child) {
+                for &model in models {
+                    let model = usize::try_from(model).context("Negative model id")?;
+                    instances.push(VoxInstance { model, translation, rotation });
+                }
+                Ok(())
+            } else {
+                collect_instances(nodes, *child, translation, rotation, instances, depth + 1)
+            }
+        }
+        Node::Group { children } => {
+            for &child in children {
+                collect_instances(nodes, child, translation, rotation, instances, depth + 1)?;
+            }
+            Ok(())
+        }
+        Node::Shape { .. } => bail!("Shape node {id} is not under a transform"),
+    }
+}
+
+/// Rows of the rotation encoded in a `_r` byte: bits 0-1 and 2-3 give the column of the
+/// non-zero entry in rows 0 and 1 (row 2 takes the remaining one), bits 4-6 make a row negative.
+fn rotation_matrix(r: u8) -> Option<[[i32; 3]; 3]> {
+    let first = (r & 3) as usize;
+    let second = ((r >> 2) & 3) as usize;
+    if first > 2 || second > 2 || first == second {
+        return None;
+    }
+    let third = 3 - first - second;
+    let mut m = [[0; 3]; 3];
+    for (row, column) in [first, second, third].into_iter().enumerate() {
+        m[row][column] = if r & (0x10 << row) != 0 { -1 } else { 1 };
+    }
+    Some(m)
+}
+
+fn transform(rotation: &[[i32; 3]; 3], p: [i32; 3]) -> [i32; 3] {
+    rotation.map(|row| row[0] * p[0] + row[1] * p[1] + row[2] * p[2])
+}
+
+/// Non-air voxel type whose colour in `blocks` is closest to `rgba`.
+fn nearest_type(blocks: &BlockRegistry, rgba: [u8; 4]) -> u8 {
+    let distance = |color: &[f32; 3]| -> f32 { (0..3).map(|i| (color[i] * 255.0 - rgba[i] as f32).powi(2)).sum() };
+    let (ty, _) = blocks
+        .iter()
+        .min_by(|(_, a), (_, b)| distance(&a.color).total_cmp(&distance(&b.color)))
+        .unwrap();
+    ty
+}
@@ -0,0 +1,208 @@
+use crate::CS;
+use crate::data::blocks::BlockRegistry;
+use crate::mesher::{ChunkMesh, FACE_NORMALS, QUAD_TRIANGLES};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+const GLB_MAGIC: u32 = 0x4654_6C67; // "glTF"
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F_534A; // "JSON"
+const CHUNK_BIN: u32 = 0x004E_4942; // "BIN\0"
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+/// Geometry of one primitive (all quads of one voxel type in one chunk), in chunk-local units.
+#[derive(Default)]
+struct Primitive {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl Primitive {
+    fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for p in &self.positions {
+            for i in 0..3 {
+                min[i] = min[i].min(p[i]);
+                max[i] = max[i].max(p[i]);
+            }
+        }
+        (min, max)
+    }
+}
+
+/// Converts an sRGB-encoded colour channel to the linear space glTF expects for `baseColorFactor`.
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Writes `chunks` as a binary glTF 2.0 (`.glb`) file.
+///
+/// Every chunk becomes a node translated by its position × `CS`, holding a mesh with one indexed
+/// triangle primitive per voxel type. Materials are shared between chunks, one per voxel type,
+/// with the colours from `blocks` converted to linear space. Transparent types are blended and
+/// emissive ones emit their colour.
+pub fn write_glb<W: Write>(out: &mut W, chunks: &[ChunkMesh], blocks: &BlockRegistry) -> io::Result<()> {
+    let mut positions: Vec<u8> = Vec::new();
+    let mut normals: Vec<u8> = Vec::new();
+    let mut indices: Vec<u8> = Vec::new();
+
+    let mut materials: BTreeMap<u8, usize> = BTreeMap::new();
+    for chunk in chunks {
+        for (_, quad) in chunk.quads() {
+            materials.insert(quad.voxel_type(), 0);
+        }
+    }
+    for (i, material) in materials.values_mut().enumerate() {
+        *material = i;
+    }
+
+    let mut accessors: Vec<Value> = Vec::new();
+    let mut meshes: Vec<Value> = Vec::new();
+    let mut nodes: Vec<Value> = Vec::with_capacity(chunks.len());
+
+    for chunk in chunks {
+        let mut by_type: BTreeMap<u8, Primitive> = BTreeMap::new();
+        for (face, quad) in chunk.quads() {
+            let normal = FACE_NORMALS[face].map(|n| n as f32);
+            let primitive = by_type.entry(quad.voxel_type()).or_default();
+            let first = primitive.positions.len() as u32;
+            primitive.positions.extend(quad.vertices(face).map(|v| v.map(|c| c as f32)));
+            primitive.normals.extend([normal; 4]);
+            primitive.indices.extend(QUAD_TRIANGLES.map(|i| first + i as u32));
+        }
+
+        let pos = chunk.chunk_pos * CS as i32;
+        let mut node = json!({
+            "name": format!("chunk_{}_{}_{}", chunk.chunk_pos.x, chunk.chunk_pos.y, chunk.chunk_pos.z),
+            "translation": [pos.x as f32, pos.y as f32, pos.z as f32],
+        });
+        if by_type.is_empty() {
+            nodes.push(node);
+            continue;
+        }
+
+        let mut primitives: Vec<Value> = Vec::with_capacity(by_type.len());
+        for (ty, primitive) in &by_type {
+            let count = primitive.positions.len();
+            let (min, max) = primitive.bounds();
+
+            let position_accessor = accessors.len();
+            accessors.push(json!({
+                "bufferView": 0, "byteOffset": positions.len(), "componentType": FLOAT,
+                "count": count, "type": "VEC3", "min": min, "max": max,
+            }));
+            accessors.push(json!({
+                "bufferView": 1, "byteOffset": normals.len(), "componentType": FLOAT,
+                "count": count, "type": "VEC3",
+            }));
+            accessors.push(json!({
+                "bufferView": 2, "byteOffset": indices.len(), "componentType": UNSIGNED_INT,
+                "count": primitive.indices.len(), "type": "SCALAR",
+            }));
+            positions.extend_from_slice(bytemuck::cast_slice(&primitive.positions));
+            normals.extend_from_slice(bytemuck::cast_slice(&primitive.normals));
+            indices.extend_from_slice(bytemuck::cast_slice(&primitive.indices));
+
+            primitives.push(json!({
+                "attributes": { "POSITION": position_accessor, "NORMAL": position_accessor + 1 },
+                "indices": position_accessor + 2,
+                "material": materials[ty],
+            }));
+        }
+
+        node["mesh"] = json!(meshes.len());
+        meshes.push(json!({ "name": node["name"].clone(), "primitives": primitives }));
+        nodes.push(node);
+    }
+
+    let materials: Vec<Value> = materials
+        .keys()
+        .map(|&ty| {
+            let block = blocks.get(ty);
+            let [r, g, b] = block.color.map(srgb_to_linear);
+            let mut material = json!({
+                "name": format!("voxel_{ty}"),
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [r, g, b, block.opacity],
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 1.0,
+                },
+            });
+            if block.is_transparent() {
+                material["alphaMode"] = json!("BLEND");
+            }
+            if block.emissive {
+                material["emissiveFactor"] = json!([r, g, b]);
+            }
+            material
+        })
+        .collect();
+
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "binary_greedy_mesher_demo_rs" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+    });
+
+    // Empty buffer views are not allowed, so a level without any faces only gets the node tree.
+    let mut bin = Vec::new();
+    if !indices.is_empty() {
+        let mut views = Vec::with_capacity(3);
+        for (data, target, stride) in [
+            (&positions, ARRAY_BUFFER, Some(12)),
+            (&normals, ARRAY_BUFFER, Some(12)),
+            (&indices, ELEMENT_ARRAY_BUFFER, None),
+        ] {
+            let mut view = json!({ "buffer": 0, "byteOffset": bin.len(), "byteLength": data.len(), "target": target });
+            if let Some(stride) = stride {
+                view["byteStride"] = json!(stride);
+            }
+            views.push(view);
+            bin.extend_from_slice(data);
+        }
+        document["buffers"] = json!([{ "byteLength": bin.len() }]);
+        document["bufferViews"] = json!(views);
+        document["accessors"] = json!(accessors);
+        document["meshes"] = json!(meshes);
+        document["materials"] = json!(materials);
+    }
+
+    let mut json = serde_json::to_vec(&document).map_err(io::Error::other)?;
+    pad_to_4(&mut json, b' ');
+    pad_to_4(&mut bin, 0);
+
+    let mut total_len = 12 + 8 + json.len();
+    if !bin.is_empty() {
+        total_len += 8 + bin.len();
+    }
+    let total_len = u32::try_from(total_len).map_err(|_| io::Error::other("glb exceeds 4 GiB"))?;
+
+    out.write_all(&GLB_MAGIC.to_le_bytes())?;
+    out.write_all(&GLB_VERSION.to_le_bytes())?;
+    out.write_all(&total_len.to_le_bytes())?;
+    write_chunk(out, CHUNK_JSON, &json)?;
+    if !bin.is_empty() {
+        write_chunk(out, CHUNK_BIN, &bin)?;
+    }
+    Ok(())
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: u32, data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_le_bytes())?;
+    out.write_all(&kind.to_le_bytes())?;
+    out.write_all(data)
+}
+
+fn pad_to_4(data: &mut Vec<u8>, fill: u8) {
+    while !data.len().is_multiple_of(4) {
+        data.push(fill);
+    }
+}
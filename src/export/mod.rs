@@ -0,0 +1,42 @@
+pub mod gltf;
+pub mod obj;
+
+use crate::data::blocks::BlockRegistry;
+use crate::data::level_file::LevelFile;
+use crate::data::rle;
+use crate::mesher::{mesh, mesh_with_transparency, ChunkMesh, MeshData, QuadData};
+use crate::{parse_xyz_key, CS, CS_P3};
+use anyhow::{Context, Result};
+use glam::IVec3;
+use rayon::prelude::*;
+
+/// Decodes and meshes every chunk of `level` in parallel, in chunk table order. Types that
+/// `blocks` marks as transparent are meshed with [`mesh_with_transparency`].
+pub fn mesh_level(level: &LevelFile, blocks: &BlockRegistry) -> Result<Vec<ChunkMesh>> {
+    let transparency = blocks.has_transparent_types().then(|| blocks.transparency_table());
+    level
+        .chunk_table
+        .par_iter()
+        .map(|entry| {
+            let (x, y, z) = parse_xyz_key(entry.key);
+            let chunk_pos = IVec3::new(x as i32, y as i32, z as i32);
+
+            let mut voxels = vec![0u8; CS_P3];
+            let mut mesh_data = MeshData::new(10_000);
+            rle::try_decompress_to_voxels_and_opaque_mask(level.chunk_data(entry)?, &mut voxels, &mut mesh_data.opaque_mask)
+                .with_context(|| format!("Failed to decode chunk {chunk_pos}"))?;
+            match &transparency {
+                Some(transparency) => mesh_with_transparency(&voxels, transparency, &mut mesh_data),
+                None => mesh(&voxels, &mut mesh_data),
+            }
+
+            Ok(ChunkMesh::from_mesh_data(chunk_pos, &mesh_data))
+        })
+        .collect()
+}
+
+/// World-space corners of `quad` on `face` of `chunk`, indexed by `gl_VertexID & 3`.
+pub(crate) fn world_vertices(chunk: &ChunkMesh, face: usize, quad: &QuadData) -> [[i32; 3]; 4] {
+    let offset = chunk.chunk_pos * CS as i32;
+    quad.vertices(face).map(|v| [v[0] + offset.x, v[1] + offset.y, v[2] + offset.z])
+}
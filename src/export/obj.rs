@@ -0,0 +1,80 @@
+use crate::data::blocks::BlockRegistry;
+use crate::export::world_vertices;
+use crate::mesher::{ChunkMesh, FACE_NORMALS, QUAD_TRIANGLES};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// Material name used for a voxel type in OBJ/MTL output.
+pub fn material_name(ty: u8) -> String {
+    format!("voxel_{ty}")
+}
+
+/// Writes `chunks` as a Wavefront OBJ in world coordinates (chunk position × `CS`).
+///
+/// Every quad becomes four vertices and two triangles; faces are grouped by voxel type under
+/// `usemtl voxel_<type>`. `mtl_file`, when given, is referenced with `mtllib`.
+pub fn write_obj<W: Write>(out: &mut W, chunks: &[ChunkMesh], mtl_file: Option<&str>) -> io::Result<()> {
+    writeln!(out, "# Exported by binary_greedy_mesher_demo_rs")?;
+    if let Some(mtl_file) = mtl_file {
+        writeln!(out, "mtllib {mtl_file}")?;
+    }
+
+    for [x, y, z] in FACE_NORMALS {
+        writeln!(out, "vn {x} {y} {z}")?;
+    }
+
+    // OBJ indices are 1-based; remember the first vertex of each quad per material.
+    let mut quads_by_type: BTreeMap<u8, Vec<(usize, usize)>> = BTreeMap::new();
+    let mut next_vertex = 1usize;
+    for chunk in chunks {
+        for (face, quad) in chunk.quads() {
+            for [x, y, z] in world_vertices(chunk, face, quad) {
+                writeln!(out, "v {x} {y} {z}")?;
+            }
+            quads_by_type.entry(quad.voxel_type()).or_default().push((next_vertex, face + 1));
+            next_vertex += 4;
+        }
+    }
+
+    for (ty, quads) in &quads_by_type {
+        writeln!(out, "usemtl {}", material_name(*ty))?;
+        for &(first, normal) in quads {
+            for triangle in QUAD_TRIANGLES.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| first + triangle[i]);
+                writeln!(out, "f {a}//{normal} {b}//{normal} {c}//{normal}")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a material library with one diffuse material per voxel type present in `chunks`,
+/// coloured from `blocks`. Transparent types get a dissolve (`d`) and emissive ones an emission
+/// colour (`Ke`).
+pub fn write_mtl<W: Write>(out: &mut W, chunks: &[ChunkMesh], blocks: &BlockRegistry) -> io::Result<()> {
+    let mut types: Vec<u8> = chunks
+        .iter()
+        .flat_map(|chunk| chunk.quads().map(|(_, quad)| quad.voxel_type()))
+        .collect();
+    types.sort_unstable();
+    types.dedup();
+
+    for ty in types {
+        let block = blocks.get(ty);
+        let [r, g, b] = block.color;
+        writeln!(out, "# {}", block.name)?;
+        writeln!(out, "newmtl {}", material_name(ty))?;
+        writeln!(out, "Kd {r} {g} {b}")?;
+        writeln!(out, "Ka 0 0 0")?;
+        if block.emissive {
+            writeln!(out, "Ke {r} {g} {b}")?;
+        }
+        if block.is_transparent() {
+            writeln!(out, "d {}", block.opacity)?;
+        }
+        writeln!(out, "illum 1")?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
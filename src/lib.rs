@@ -1,7 +1,10 @@
 pub mod data;
+pub mod export;
 pub mod mesher;
 pub mod misc;
 pub mod rendering;
+pub mod streaming;
+pub mod world;
 
 pub const CS: usize = 62;
 pub const CS_P: usize = CS + 2;
@@ -9,6 +12,10 @@ pub const CS_2: usize = CS * CS;
 pub const CS_P2: usize = CS_P * CS_P;
 pub const CS_P3: usize = CS_P * CS_P * CS_P;
 
+/// Largest chunk coordinate on each axis. Level files store their size in chunks as a `u8`, so
+/// chunk 255 can't be saved.
+pub const MAX_CHUNK: u8 = u8::MAX - 1;
+
 pub fn get_zxy_index(x: usize, y: usize, z: usize) -> usize {
     z + (x * CS_P) + (y * CS_P2)
 }
@@ -1,10 +1,16 @@
 use anyhow::{Context, Result};
 use binary_greedy_mesher_demo_rs as demo;
-use demo::data::{level_file::LevelFile, rle};
-use demo::mesher::{mesh, MeshData, QuadData};
-use demo::misc::{camera::Camera, shader::ShaderProgram};
+use demo::data::blocks::BlockRegistry;
+use demo::data::mapped_level::MappedLevel;
+use demo::mesher::{ChunkMesh, Lod, QuadData};
+use demo::misc::{camera::{Camera, Frustum}, shader::ShaderProgram};
 use demo::rendering::chunk_renderer::{ChunkRenderer, DrawElementsIndirectCommand};
-use demo::{parse_xyz_key, CS, CS_P3};
+use demo::rendering::gpu_culling::{cull_chunk, GpuCuller};
+use demo::rendering::hiz::HiZ;
+use demo::rendering::multi_draw::DrawPath;
+use demo::streaming::{ChunkStreamer, MeshQueue, MeshedChunk, StreamingConfig};
+use demo::world::World;
+use demo::CS;
 use glam::{IVec3, Vec3};
 use glutin::config::ConfigTemplateBuilder;
 use glutin::context::{ContextApi, ContextAttributesBuilder, Version};
@@ -14,14 +20,15 @@ use glutin_winit::DisplayBuilder;
 use glutin_winit::GlWindow;
 use glow::HasContext;
 use raw_window_handle::HasWindowHandle;
-use rayon::prelude::*;
+use std::collections::HashMap;
 use std::env;
 use std::rc::Rc;
 use std::num::NonZeroU32;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Instant;
 use winit::dpi::PhysicalSize;
-use winit::event::{DeviceEvent, ElementState, Event, WindowEvent};
+use winit::event::{DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::{CursorGrabMode, WindowAttributes};
@@ -29,14 +36,25 @@ use winit::window::{CursorGrabMode, WindowAttributes};
 const WINDOW_WIDTH: u32 = 1920;
 const WINDOW_HEIGHT: u32 = 1080;
 const DEFAULT_LEVEL_REL: &str = "levels/demo_terrain_96";
+const WINDOW_TITLE: &str = "Binary Greedy Meshing (Rust)";
+// How far away (in voxels) blocks can be broken or placed.
+const EDIT_REACH: f32 = 96.0;
+
+struct Args {
+    level: PathBuf,
+    view_radius: f32,
+    blocks: Option<PathBuf>,
+}
 
-fn resolve_level_path() -> Result<PathBuf> {
+fn parse_args() -> Result<Args> {
     // Usage:
-    //   cargo run -- [--level <path>]
-    //   cargo run -- -l <path>
+    //   cargo run -- [--level <path>] [--view-radius <chunks>] [--blocks <path>]
+    //   cargo run -- -l <path> -r <chunks> -b <path>
     // Paths that are not absolute are resolved relative to the crate root.
     let mut args = env::args().skip(1);
     let mut level: Option<PathBuf> = None;
+    let mut view_radius = StreamingConfig::default().view_radius;
+    let mut blocks: Option<PathBuf> = None;
 
     while let Some(a) = args.next() {
         match a.as_str() {
@@ -45,9 +63,27 @@ fn resolve_level_path() -> Result<PathBuf> {
                     args.next().ok_or_else(|| anyhow::anyhow!("{a} requires a path"))?,
                 ));
             }
+            "--view-radius" | "-r" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("{a} requires a number of chunks"))?;
+                view_radius = value
+                    .parse()
+                    .ok()
+                    .filter(|r: &f32| *r > 0.0)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid view radius '{value}'"))?;
+            }
+            "--blocks" | "-b" => {
+                blocks = Some(PathBuf::from(
+                    args.next().ok_or_else(|| anyhow::anyhow!("{a} requires a path"))?,
+                ));
+            }
             "--help" | "-h" => {
                 eprintln!(
-                    "Usage: binary_greedy_mesher_demo_rs [--level <path>]\n\nDefault: {DEFAULT_LEVEL_REL}\n"
+                    "Usage: binary_greedy_mesher_demo_rs [--level <path>] [--view-radius <chunks>] [--blocks <path>]\n\n\
+                     Default: {DEFAULT_LEVEL_REL}, view radius {}, built-in block palette\n\n\
+                     Controls: WASD + Shift fly, mouse look, left click break, right click place,\n\
+                     1-8 block type, [ ] or mouse wheel cycle block types, X wireframe,\n\
+                     M cycle draw path, G GPU culling, O occlusion culling (with G), Esc quit\n",
+                    StreamingConfig::default().view_radius
                 );
                 std::process::exit(0);
             }
@@ -57,12 +93,16 @@ fn resolve_level_path() -> Result<PathBuf> {
         }
     }
 
-    let level = level.unwrap_or_else(|| PathBuf::from(DEFAULT_LEVEL_REL));
-    if level.is_absolute() {
-        Ok(level)
-    } else {
-        Ok(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(level))
-    }
+    let resolve = |path: PathBuf| {
+        if path.is_absolute() {
+            path
+        } else {
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(path)
+        }
+    };
+    let level = resolve(level.unwrap_or_else(|| PathBuf::from(DEFAULT_LEVEL_REL)));
+    let blocks = blocks.map(resolve);
+    Ok(Args { level, view_radius, blocks })
 }
 
 const VERT_SRC: &str = r#"#version 460 core
@@ -76,6 +116,17 @@ layout(binding = 0, std430) readonly buffer ssbo1 {
   QuadData data[];
 };
 
+struct Block {
+  vec4 colorOpacity;
+  uint flags;
+  int texture;
+};
+
+// Indexed by voxel type; entry 0 is air and never drawn.
+layout(binding = 1, std430) readonly buffer blockPalette {
+  Block blocks[];
+};
+
 uniform mat4 u_view;
 uniform mat4 u_projection;
 
@@ -85,6 +136,9 @@ out VS_OUT {
   out vec3 pos;
   flat vec3 normal;
   flat vec3 color;
+  flat float opacity;
+  flat float emissive;
+  float ao;
 } vs_out;
 
 const vec3 normalLookup[6] = {
@@ -96,17 +150,6 @@ const vec3 normalLookup[6] = {
   vec3( 0, 0, -1 )
 };
 
-const vec3 colorLookup[8] = {
-  vec3(0.2, 0.659, 0.839),
-  vec3(0.302, 0.302, 0.302),
-  vec3(0.278, 0.600, 0.141),
-  vec3(0.1, 0.1, 0.6),
-  vec3(0.1, 0.6, 0.6),
-  vec3(0.6, 0.1, 0.6),
-  vec3(0.6, 0.6, 0.1),
-  vec3(0.6, 0.1, 0.1)
-};
-
 const int flipLookup[6] = int[6](1, -1, -1, 1, -1, 1);
 
 void main() {
@@ -120,7 +163,6 @@ void main() {
   uint quadData2 = data[ssboIndex].quadData2;
 
   ivec3 iVertexPos = ivec3(quadData1, quadData1 >> 6u, quadData1 >> 12u) & 63;
-  iVertexPos += chunkOffsetPos;
 
   int w = int((quadData1 >> 18u)&63u), h = int((quadData1 >> 24u)&63u);
   uint wDir = (face & 2) >> 1, hDir = 2 - (face >> 2);
@@ -129,9 +171,18 @@ void main() {
   iVertexPos[wDir] += (w * wMod * flipLookup[face]);
   iVertexPos[hDir] += (h * hMod);
 
+  // LOD quads are in cells of 2^n voxels; the last cell may overhang the chunk.
+  int lodScale = 1 << ((quadData2 >> 16u) & 3u);
+  iVertexPos = min(iVertexPos * lodScale, ivec3(62));
+  iVertexPos += chunkOffsetPos;
+
   vs_out.pos = iVertexPos;
   vs_out.normal = normalLookup[face];
-  vs_out.color = colorLookup[(quadData2&255u) - 1];
+  Block block = blocks[quadData2&255u];
+  vs_out.color = block.colorOpacity.rgb;
+  vs_out.opacity = block.colorOpacity.a;
+  vs_out.emissive = float(block.flags & 1u);
+  vs_out.ao = float((quadData2 >> (8u + 2u * uint(vertexID))) & 3u);
 
   vec3 vertexPos = iVertexPos - eye_position_int;
   vertexPos[wDir] += 0.0007 * flipLookup[face] * (wMod * 2 - 1);
@@ -143,12 +194,15 @@ void main() {
 
 const FRAG_SRC: &str = r#"#version 460 core
 
-layout(location=0) out vec3 out_color;
+layout(location=0) out vec4 out_color;
 
 in VS_OUT {
   vec3 pos;
   flat vec3 normal;
   flat vec3 color;
+  flat float opacity;
+  flat float emissive;
+  float ao;
 } fs_in;
 
 uniform vec3 eye_position;
@@ -164,25 +218,210 @@ void main() {
   float rim = 1 - max(dot(V, fs_in.normal), 0.0);
   rim = smoothstep(0.6, 1.0, rim);
 
-  out_color =
+  vec3 color =
     fs_in.color +
     (diffuse_color * max(0, dot(L, fs_in.normal))) +
     (rim_color * vec3(rim, rim, rim))
   ;
+  color *= 1.0 - (fs_in.ao * 0.2);
+
+  // Emissive blocks ignore lighting and AO.
+  color = mix(color, fs_in.color, fs_in.emissive);
+
+  // Only blended in the transparent pass; opaque blocks have an opacity of 1.
+  out_color = vec4(color, fs_in.opacity);
 }
 "#;
 
-#[derive(Clone)]
-struct ChunkMesh {
+struct ChunkState {
+    chunk_pos: IVec3,
+    lod: Lod,
+    cmds: [Option<DrawElementsIndirectCommand>; 6],
+    /// Drawn in the blended pass after all opaque chunks; not part of the GPU culler.
+    transparent_cmds: [Option<DrawElementsIndirectCommand>; 6],
+}
+
+// Chebyshev distance in chunks up to which each LOD is used; anything further is Lod::Eighth.
+const LOD_RINGS: [(i32, Lod); 3] = [(4, Lod::Full), (8, Lod::Half), (16, Lod::Quarter)];
+
+fn lod_for_chunk(camera_chunk_pos: IVec3, chunk_pos: IVec3) -> Lod {
+    let distance = (chunk_pos - camera_chunk_pos).abs().max_element();
+    LOD_RINGS
+        .iter()
+        .find(|(ring, _)| distance < *ring)
+        .map(|(_, lod)| *lod)
+        .unwrap_or(Lod::Eighth)
+}
+
+/// Swaps in the draw commands of chunks meshed in the background.
+fn upload_meshed(
+    meshed: Vec<MeshedChunk>,
+    renderer: &mut ChunkRenderer,
+    culler: &mut Option<GpuCuller>,
+    chunks: &mut HashMap<u32, ChunkState>,
+) {
+    for MeshedChunk { key, lod, mesh: cm } in meshed {
+        if let Some(old) = chunks.remove(&key) {
+            free_chunk(renderer, &old);
+        }
+
+        // Out of space usually means fragmentation, so compact once and retry.
+        let uploaded = upload_chunk(renderer, &cm, lod).or_else(|_| {
+            compact_renderer(renderer, culler, chunks)?;
+            upload_chunk(renderer, &cm, lod)
+        });
+        match uploaded {
+            Ok(chunk) => {
+                chunks.insert(key, chunk);
+            }
+            Err(e) => eprintln!("Failed to upload chunk {:?}: {e}", cm.chunk_pos),
+        }
+        sync_culler(culler, key, chunks.get(&key));
+    }
+}
+
+/// The non-air block type `step` types after `ty`, wrapping around within 1..=255.
+fn cycle_block_type(ty: u8, step: i32) -> u8 {
+    ((ty as i32 - 1 + step).rem_euclid(u8::MAX as i32) + 1) as u8
+}
+
+/// Frustum and face culling of a chunk on the CPU, with the same rules as the GPU culler.
+fn cull_chunk_state(frustum: &Frustum, eye_int: IVec3, chunk: &ChunkState) -> Option<[bool; 6]> {
+    let min = chunk.chunk_pos * CS as i32;
+    cull_chunk(frustum, eye_int, min, min + IVec3::splat(CS as i32))
+}
+
+/// Mirrors a chunk's draw commands into the GPU culler's persistent buffer.
+fn sync_culler(culler: &mut Option<GpuCuller>, key: u32, chunk: Option<&ChunkState>) {
+    let Some(culler) = culler else {
+        return;
+    };
+    match chunk {
+        Some(chunk) => {
+            let min = chunk.chunk_pos * CS as i32;
+            culler.set_chunk(key, &chunk.cmds, min, min + IVec3::splat(CS as i32));
+        }
+        None => culler.remove_chunk(key),
+    }
+}
+
+/// Draws a small crosshair in the middle of the screen by clearing two scissored rectangles.
+fn draw_crosshair(gl: &glow::Context, width: u32, height: u32) {
+    let (cx, cy) = (width as i32 / 2, height as i32 / 2);
+    unsafe {
+        gl.enable(glow::SCISSOR_TEST);
+        gl.clear_color(1.0, 1.0, 1.0, 1.0);
+        for (x, y, w, h) in [(cx - 8, cy - 1, 16, 2), (cx - 1, cy - 8, 2, 16)] {
+            gl.scissor(x, y, w, h);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+        }
+        gl.disable(glow::SCISSOR_TEST);
+        gl.clear_color(0.529, 0.808, 0.922, 0.0);
+    }
+}
+
+fn upload_chunk(renderer: &mut ChunkRenderer, cm: &ChunkMesh, lod: Lod) -> Result<ChunkState> {
+    let cmds = upload_faces(renderer, cm.chunk_pos, &cm.faces)?;
+    let transparent_cmds = match upload_faces(renderer, cm.chunk_pos, &cm.transparent_faces) {
+        Ok(transparent_cmds) => transparent_cmds,
+        Err(e) => {
+            free_commands(renderer, &cmds);
+            return Err(e);
+        }
+    };
+    Ok(ChunkState {
+        chunk_pos: cm.chunk_pos,
+        lod,
+        cmds,
+        transparent_cmds,
+    })
+}
+
+fn upload_faces(
+    renderer: &mut ChunkRenderer,
     chunk_pos: IVec3,
-    faces: [Vec<QuadData>; 6],
+    faces: &[Vec<QuadData>; 6],
+) -> Result<[Option<DrawElementsIndirectCommand>; 6]> {
+    let mut cmds: [Option<DrawElementsIndirectCommand>; 6] = std::array::from_fn(|_| None);
+    for face in 0..6u32 {
+        let quads = &faces[face as usize];
+        if quads.is_empty() {
+            continue;
+        }
+        let base_vertex = match renderer.upload_quads(quads) {
+            Ok(base_vertex) => base_vertex,
+            Err(e) => {
+                // Don't leak the faces uploaded so far.
+                free_commands(renderer, &cmds);
+                return Err(e);
+            }
+        };
+        let base_instance = (face << 24)
+            | ((chunk_pos.z as u32) << 16)
+            | ((chunk_pos.y as u32) << 8)
+            | (chunk_pos.x as u32);
+
+        cmds[face as usize] = Some(DrawElementsIndirectCommand {
+            index_count: (quads.len() as u32) * 6,
+            instance_count: 1,
+            first_index: 0,
+            base_vertex,
+            base_instance,
+        });
+    }
+    Ok(cmds)
+}
+
+fn free_chunk(renderer: &mut ChunkRenderer, chunk: &ChunkState) {
+    free_commands(renderer, &chunk.cmds);
+    free_commands(renderer, &chunk.transparent_cmds);
+}
+
+fn free_commands(renderer: &mut ChunkRenderer, cmds: &[Option<DrawElementsIndirectCommand>; 6]) {
+    for cmd in cmds.iter().flatten() {
+        renderer.free_quads(cmd.base_vertex);
+    }
+}
+
+/// Compacts the renderer's SSBO and points the draw commands at the moved quads.
+fn compact_renderer(
+    renderer: &mut ChunkRenderer,
+    culler: &mut Option<GpuCuller>,
+    chunks: &mut HashMap<u32, ChunkState>,
+) -> Result<()> {
+    let moved: HashMap<u32, u32> = renderer.compact()?.into_iter().map(|r| (r.from, r.to)).collect();
+    for cmd in chunks
+        .values_mut()
+        .flat_map(|chunk| chunk.cmds.iter_mut().chain(&mut chunk.transparent_cmds).flatten())
+    {
+        if let Some(&to) = moved.get(&cmd.base_vertex) {
+            cmd.base_vertex = to;
+        }
+    }
+    for (&key, chunk) in chunks.iter() {
+        sync_culler(culler, key, Some(chunk));
+    }
+
+    let stats = renderer.allocator_stats();
+    eprintln!(
+        "Compacted chunk buffer: moved {} allocations, {} / {} quads in use",
+        moved.len(),
+        stats.used,
+        stats.capacity
+    );
+    Ok(())
+}
+
+fn camera_chunk_pos(camera: &Camera) -> IVec3 {
+    let pos = (camera.position / (CS as f32)).floor();
+    IVec3::new(pos.x as i32, pos.y as i32, pos.z as i32)
 }
 
 fn main() -> Result<()> {
     // --- Window + GL context (winit + glutin) ---
     let event_loop = EventLoop::new()?;
     let window_attributes = WindowAttributes::default()
-        .with_title("Binary Greedy Meshing (Rust)")
+        .with_title(WINDOW_TITLE)
         .with_inner_size(PhysicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT));
 
     let template = ConfigTemplateBuilder::new().with_alpha_size(8).with_depth_size(24);
@@ -258,84 +497,75 @@ fn main() -> Result<()> {
     let u_eye = shader.uniform_location("eye_position").context("missing eye_position")?;
     let u_eye_int = shader.uniform_location("eye_position_int").context("missing eye_position_int")?;
 
-    let mut renderer = ChunkRenderer::new(&gl).context("create renderer")?;
+    let mut renderer = ChunkRenderer::new(&gl, |s| gl_display.get_proc_address(s) as *const _)
+        .context("create renderer")?;
+    eprintln!("Draw path: {:?}", renderer.draw_path());
 
-    // --- Load level file ---
-    let level_path = resolve_level_path()?;
-    let mut level = LevelFile::default();
-    level.load_from_file(&level_path)?;
+    // Optional compute-shader culling (toggled with G); needs GL 4.3 compute shaders.
+    let mut culler = match GpuCuller::new(&gl) {
+        Ok(culler) => Some(culler),
+        Err(e) => {
+            eprintln!("GPU culling unavailable: {e:#}");
+            None
+        }
+    };
+    let mut gpu_culling = false;
+
+    // Hi-Z occlusion culling on top of GPU culling (toggled with O). It culls against the last
+    // frame's depth without re-testing what it hid, so newly uncovered chunks appear a frame late.
+    let mut hiz = match culler
+        .as_ref()
+        .map(|_| HiZ::new(&gl, WINDOW_WIDTH, WINDOW_HEIGHT, gl_config.num_samples()))
+    {
+        Some(Ok(hiz)) => Some(hiz),
+        Some(Err(e)) => {
+            eprintln!("Occlusion culling unavailable: {e:#}");
+            None
+        }
+        None => None,
+    };
+    let mut occlusion = false;
+    let mut last_stats = Instant::now();
+
+    // --- Load level into an editable world ---
+    let args = parse_args()?;
+    let level = Arc::new(MappedLevel::open(&args.level)?);
+    let [size_x, size_y, size_z] = level.dims();
+    let blocks = match &args.blocks {
+        Some(path) => BlockRegistry::load(path)?,
+        None => BlockRegistry::default(),
+    };
+    renderer.set_blocks(&blocks);
+
+    // Chunks are streamed in around the camera; the world starts empty.
+    let mut world = World::new();
+    world.set_transparency(blocks.has_transparent_types().then(|| blocks.transparency_table()));
+    let mut streamer = ChunkStreamer::new(
+        Arc::clone(&level),
+        StreamingConfig {
+            view_radius: args.view_radius,
+            ..Default::default()
+        },
+    );
 
     // Camera matches the C++ initial placement (roughly)
     let cam_start = Vec3::new(
-        (level.size() as f32 * CS as f32) / 2.0,
-        100.0,
-        (level.size() as f32 * CS as f32) / 2.0 - 30.0,
+        (size_x as f32 * CS as f32) / 2.0,
+        (size_y as f32 * CS as f32) + 38.0,
+        (size_z as f32 * CS as f32) / 2.0 - 30.0,
     );
     let mut camera = Camera::new(cam_start, WINDOW_WIDTH, WINDOW_HEIGHT);
 
-    // --- Mesh all chunks (parallel compute, sequential upload) ---
-    let chunk_meshes: Vec<ChunkMesh> = level
-        .chunk_table
-        .par_iter()
-        .map(|entry| {
-            let (x, y, z) = parse_xyz_key(entry.key);
-            let chunk_pos = IVec3::new(x as i32, y as i32, z as i32);
-
-            let mut voxels = vec![0u8; CS_P3];
-            let mut mesh_data = MeshData::new(10_000);
-            mesh_data.opaque_mask.fill(0);
-
-            let start = entry.rle_data_begin as usize;
-            let end = start + entry.rle_data_size as usize;
-            let rle_slice = &level.buffer[start..end];
-            rle::decompress_to_voxels_and_opaque_mask(rle_slice, &mut voxels, &mut mesh_data.opaque_mask);
-            mesh(&voxels, &mut mesh_data);
-
-            let faces: [Vec<QuadData>; 6] = std::array::from_fn(|face| {
-                let begin = mesh_data.face_vertex_begin[face];
-                let len = mesh_data.face_vertex_length[face];
-                if len == 0 {
-                    Vec::new()
-                } else {
-                    mesh_data.vertices[begin..begin + len].to_vec()
-                }
-            });
-
-            ChunkMesh { chunk_pos, faces }
-        })
-        .collect();
-
-    // Upload and keep indirect commands per chunk/face.
-    let mut per_chunk_cmds: Vec<(IVec3, [Option<DrawElementsIndirectCommand>; 6])> = Vec::with_capacity(chunk_meshes.len());
-    for cm in chunk_meshes {
-        let mut cmds: [Option<DrawElementsIndirectCommand>; 6] = std::array::from_fn(|_| None);
-        for face in 0..6u32 {
-            let quads = &cm.faces[face as usize];
-            if quads.is_empty() {
-                continue;
-            }
-            let base_vertex = renderer.upload_quads(quads)?;
-            let base_instance = ((face as u32) << 24)
-                | ((cm.chunk_pos.z as u32) << 16)
-                | ((cm.chunk_pos.y as u32) << 8)
-                | (cm.chunk_pos.x as u32);
-
-            cmds[face as usize] = Some(DrawElementsIndirectCommand {
-                index_count: (quads.len() as u32) * 6,
-                instance_count: 1,
-                first_index: 0,
-                base_vertex,
-                base_instance,
-            });
-        }
-        per_chunk_cmds.push((cm.chunk_pos, cmds));
-    }
+    let mut chunks: HashMap<u32, ChunkState> = HashMap::new();
+    let mut mesh_queue = MeshQueue::new();
 
     // --- Main loop ---
     let mut last_frame = Instant::now();
     let noclip_speed: f32 = 250.0;
     let noclip_fast_multiplier: f32 = 4.0;
     let mut wireframe = false;
+    let mut place_type: u8 = 1;
+    let mut window_size = PhysicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT);
 
     #[derive(Default, Copy, Clone)]
     struct InputState {
@@ -352,6 +582,7 @@ fn main() -> Result<()> {
     let mut mouse_dx: f32 = 0.0;
     let mut mouse_dy: f32 = 0.0;
 
+    #[allow(deprecated)]
     let _ = event_loop.run(move |event, elwt| {
         elwt.set_control_flow(ControlFlow::Poll);
 
@@ -367,13 +598,50 @@ fn main() -> Result<()> {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => elwt.exit(),
                 WindowEvent::Resized(size) => {
-                    let _ = gl_surface.resize(
+                    gl_surface.resize(
                         &gl_context,
                         NonZeroU32::new(size.width.max(1)).unwrap(),
                         NonZeroU32::new(size.height.max(1)).unwrap(),
                     );
                     camera.handle_resolution(size.width.max(1), size.height.max(1));
                     unsafe { gl.viewport(0, 0, size.width as i32, size.height as i32) };
+                    window_size = size;
+                    if let Some(hiz) = &mut hiz
+                        && let Err(e) = hiz.resize(size.width, size.height)
+                    {
+                        eprintln!("Failed to resize Hi-Z buffer: {e:#}");
+                    }
+                }
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button,
+                    ..
+                } => {
+                    // Edits only mark chunks dirty; they are re-meshed before the next draw.
+                    if let Some(hit) = world.raycast(camera.position, camera.front, EDIT_REACH) {
+                        let edit = match button {
+                            MouseButton::Left => Some((hit.voxel, 0)),
+                            MouseButton::Right if hit.normal != IVec3::ZERO => Some((hit.voxel + hit.normal, place_type)),
+                            _ => None,
+                        };
+                        if let Some((pos, ty)) = edit
+                            && let Some(key) = World::chunk_key(pos)
+                            && streamer.can_edit(&world, key)
+                        {
+                            world.set_voxel(pos, ty);
+                            streamer.mark_edited(key);
+                        }
+                    }
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let scroll = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32,
+                    };
+                    if scroll != 0.0 {
+                        place_type = cycle_block_type(place_type, if scroll > 0.0 { 1 } else { -1 });
+                        eprintln!("Block: {place_type} {}", blocks.get(place_type).name);
+                    }
                 }
                 WindowEvent::KeyboardInput { event: key_event, .. } => {
                     if let PhysicalKey::Code(code) = key_event.physical_key {
@@ -385,9 +653,23 @@ fn main() -> Result<()> {
                             KeyCode::KeyS => input.s = pressed,
                             KeyCode::KeyD => input.d = pressed,
                             KeyCode::ShiftLeft | KeyCode::ShiftRight => input.shift = pressed,
+                            KeyCode::Digit1 => place_type = 1,
+                            KeyCode::Digit2 => place_type = 2,
+                            KeyCode::Digit3 => place_type = 3,
+                            KeyCode::Digit4 => place_type = 4,
+                            KeyCode::Digit5 => place_type = 5,
+                            KeyCode::Digit6 => place_type = 6,
+                            KeyCode::Digit7 => place_type = 7,
+                            KeyCode::Digit8 => place_type = 8,
                             _ => {}
                         }
 
+                        if let KeyCode::BracketLeft | KeyCode::BracketRight = code
+                            && pressed
+                        {
+                            place_type = cycle_block_type(place_type, if code == KeyCode::BracketRight { 1 } else { -1 });
+                            eprintln!("Block: {place_type} {}", blocks.get(place_type).name);
+                        }
                         if code == KeyCode::Escape && key_event.state == ElementState::Released {
                             elwt.exit();
                         }
@@ -400,6 +682,41 @@ fn main() -> Result<()> {
                                 );
                             }
                         }
+                        if code == KeyCode::KeyG && key_event.state == ElementState::Released && culler.is_some() {
+                            gpu_culling = !gpu_culling;
+                            eprintln!("GPU culling: {}", if gpu_culling { "on" } else { "off" });
+                            if !gpu_culling {
+                                window.set_title(WINDOW_TITLE);
+                            }
+                            if let Some(hiz) = &mut hiz {
+                                hiz.invalidate();
+                            }
+                        }
+                        if code == KeyCode::KeyO
+                            && key_event.state == ElementState::Released
+                            && let Some(hiz) = &mut hiz
+                        {
+                            // Culls with last frame's depth and doesn't re-test what it hid: chunks
+                            // that come into view can pop in one frame late.
+                            occlusion = !occlusion;
+                            // The pyramid isn't updated while occlusion culling is off.
+                            hiz.invalidate();
+                            eprintln!(
+                                "Occlusion culling: {}{}",
+                                if occlusion { "on" } else { "off" },
+                                if occlusion && !gpu_culling { " (needs GPU culling, press G)" } else { "" }
+                            );
+                        }
+                        if code == KeyCode::KeyM && key_event.state == ElementState::Released {
+                            let next = match renderer.draw_path() {
+                                DrawPath::PerCommand => [DrawPath::MultiDraw, DrawPath::MultiDrawCount],
+                                DrawPath::MultiDraw => [DrawPath::MultiDrawCount, DrawPath::PerCommand],
+                                DrawPath::MultiDrawCount => [DrawPath::PerCommand, DrawPath::MultiDraw],
+                            };
+                            if next.into_iter().any(|path| renderer.set_draw_path(path)) {
+                                eprintln!("Draw path: {:?}", renderer.draw_path());
+                            }
+                        }
                     }
                 }
                 _ => {}
@@ -430,6 +747,10 @@ fn main() -> Result<()> {
                 }
 
                 // Render
+                let hiz_frame = hiz.as_mut().filter(|_| gpu_culling && occlusion);
+                if let Some(hiz) = &hiz_frame {
+                    hiz.begin_frame();
+                }
                 unsafe { gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT) };
 
                 shader.bind();
@@ -437,32 +758,97 @@ fn main() -> Result<()> {
                 shader.set_mat4(&u_view, &camera.get_view_matrix());
                 shader.set_vec3(&u_eye, &camera.position);
 
-                let eye_int = camera.position.floor();
-                shader.set_ivec3(&u_eye_int, eye_int.x as i32, eye_int.y as i32, eye_int.z as i32);
-
-                let camera_chunk_pos = (camera.position / (CS as f32)).floor();
-                let camera_chunk_pos = IVec3::new(camera_chunk_pos.x as i32, camera_chunk_pos.y as i32, camera_chunk_pos.z as i32);
-
-                for (chunk_pos, cmds) in &per_chunk_cmds {
-                    for face in 0..6 {
-                        if let Some(cmd) = cmds[face] {
-                            let visible = match face {
-                                0 => camera_chunk_pos.y >= chunk_pos.y,
-                                1 => camera_chunk_pos.y <= chunk_pos.y,
-                                2 => camera_chunk_pos.x >= chunk_pos.x,
-                                3 => camera_chunk_pos.x <= chunk_pos.x,
-                                4 => camera_chunk_pos.z >= chunk_pos.z,
-                                5 => camera_chunk_pos.z <= chunk_pos.z,
-                                _ => true,
-                            };
-                            if visible {
+                let eye_int = camera.eye_position_int();
+                shader.set_ivec3(&u_eye_int, eye_int.x, eye_int.y, eye_int.z);
+
+                let camera_chunk_pos = camera_chunk_pos(&camera);
+
+                // Stream chunks in and out; loaded chunks show up as dirty below.
+                let update = streamer.update(&mut world, camera.position / CS as f32, camera.front);
+                for key in update.unloaded {
+                    mesh_queue.cancel(key);
+                    if let Some(old) = chunks.remove(&key) {
+                        free_chunk(&mut renderer, &old);
+                    }
+                    sync_culler(&mut culler, key, None);
+                }
+                for e in update.errors {
+                    eprintln!("Failed to stream chunk: {e:#}");
+                }
+
+                // Re-mesh edited and newly loaded chunks and those whose LOD ring changed since they were last meshed.
+                // Meshing runs in the background; finished chunks replace their old draws as they come in.
+                for (&key, chunk) in &chunks {
+                    if !mesh_queue.is_pending(key) && lod_for_chunk(camera_chunk_pos, chunk.chunk_pos) != chunk.lod {
+                        world.mark_dirty(key);
+                    }
+                }
+                mesh_queue.submit(&mut world, true, |chunk_pos| lod_for_chunk(camera_chunk_pos, chunk_pos));
+                upload_meshed(mesh_queue.poll(), &mut renderer, &mut culler, &mut chunks);
+
+                let frustum = camera.frustum();
+                if let Some(culler) = culler.as_mut().filter(|_| gpu_culling) {
+                    // Only the indirect-count path can draw a compacted list without reading its length back.
+                    let compact = renderer.draw_path() == DrawPath::MultiDrawCount;
+                    culler.dispatch(&frustum, eye_int, hiz_frame.as_ref().and_then(|hiz| hiz.last_frame()), compact);
+                    shader.bind();
+                    renderer.render_culled(culler);
+
+                    if last_stats.elapsed().as_secs_f32() >= 1.0 {
+                        last_stats = Instant::now();
+                        let stats = culler.read_stats();
+                        window.set_title(&format!(
+                            "{WINDOW_TITLE} - {} chunks, {} frustum culled, {} occluded, {} draws",
+                            culler.chunk_count(),
+                            stats.frustum_culled,
+                            stats.occluded,
+                            stats.draw_count
+                        ));
+                    }
+                } else {
+                    for chunk in chunks.values() {
+                        let Some(facing) = cull_chunk_state(&frustum, eye_int, chunk) else {
+                            continue;
+                        };
+                        for (cmd, facing) in chunk.cmds.iter().zip(facing) {
+                            if let Some(cmd) = *cmd
+                                && facing
+                            {
                                 renderer.add_draw_command(cmd);
                             }
                         }
                     }
+
+                    renderer.render();
                 }
 
-                renderer.render();
+                // Transparent quads go last, farthest chunk first, so they blend over everything behind them.
+                let mut transparent: Vec<(i32, &ChunkState, [bool; 6])> = chunks
+                    .values()
+                    .filter(|chunk| chunk.transparent_cmds.iter().any(Option::is_some))
+                    .filter_map(|chunk| {
+                        let facing = cull_chunk_state(&frustum, eye_int, chunk)?;
+                        let center = chunk.chunk_pos * CS as i32 + IVec3::splat(CS as i32 / 2) - eye_int;
+                        Some((center.length_squared(), chunk, facing))
+                    })
+                    .collect();
+                transparent.sort_unstable_by_key(|&(distance, ..)| std::cmp::Reverse(distance));
+                for (_, chunk, facing) in transparent {
+                    for (cmd, facing) in chunk.transparent_cmds.iter().zip(facing) {
+                        if let Some(cmd) = *cmd
+                            && facing
+                        {
+                            renderer.add_draw_command(cmd);
+                        }
+                    }
+                }
+                shader.bind();
+                renderer.render_transparent();
+
+                if let Some(hiz) = hiz_frame {
+                    hiz.end_frame(camera.projection * camera.get_view_matrix(), eye_int);
+                }
+                draw_crosshair(&gl, window_size.width, window_size.height);
 
                 gl_surface.swap_buffers(&gl_context).expect("swap_buffers");
 
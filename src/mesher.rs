@@ -1,143 +1,481 @@
-use crate::{CS, CS_2, CS_P, CS_P2};
 use bytemuck::{Pod, Zeroable};
+use glam::IVec3;
+use std::fmt::Debug;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not, Shl, Shr, Sub};
+
+/// Unsigned integer holding one padded column of a chunk's bit masks. Its width is the padded
+/// chunk size, so it picks the chunk size the mesher works with: `u32` for 30³ chunks, `u64`
+/// for 62³ (the renderer and level format, see [`crate::CS`]) and `u128` for 126³.
+pub trait ColumnMask:
+    Copy
+    + Eq
+    + Debug
+    + Send
+    + Sync
+    + 'static
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + Not<Output = Self>
+    + Shl<usize, Output = Self>
+    + Shr<usize, Output = Self>
+    + Sub<Output = Self>
+    + BitAndAssign
+    + BitOrAssign
+{
+    /// Padded chunk size: the number of bits in a column.
+    const CS_P: usize;
+    const CS: usize = Self::CS_P - 2;
+    const CS_2: usize = Self::CS * Self::CS;
+    const CS_P2: usize = Self::CS_P * Self::CS_P;
+    const CS_P3: usize = Self::CS_P2 * Self::CS_P;
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn trailing_zeros(self) -> u32;
+
+    /// Index of padded voxel `(x, y, z)` in a `CS_P3` buffer, as [`crate::get_zxy_index`].
+    fn zxy_index(x: usize, y: usize, z: usize) -> usize {
+        z + (x * Self::CS_P) + (y * Self::CS_P2)
+    }
+}
+
+macro_rules! impl_column_mask {
+    ($($ty:ty),*) => {$(
+        impl ColumnMask for $ty {
+            const CS_P: usize = <$ty>::BITS as usize;
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+
+            fn trailing_zeros(self) -> u32 {
+                <$ty>::trailing_zeros(self)
+            }
+        }
+    )*};
+}
+
+impl_column_mask!(u32, u64, u128);
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
 pub struct QuadData {
     pub quad_data1: u32,
     pub quad_data2: u32,
 }
 
+/// Outward normal of each face index, matching `normalLookup` in the vertex shader.
+pub const FACE_NORMALS: [[i32; 3]; 6] = [[0, 1, 0], [0, -1, 0], [1, 0, 0], [-1, 0, 0], [0, 0, 1], [0, 0, -1]];
+
+/// Vertices (`gl_VertexID & 3`) of the two front-facing triangles of a quad, as laid out in the
+/// renderer's index buffer.
+pub const QUAD_TRIANGLES: [usize; 6] = [2, 0, 1, 1, 3, 2];
+
+impl QuadData {
+    pub fn voxel_type(&self) -> u8 {
+        (self.quad_data2 & 255) as u8
+    }
+
+    /// `log2` of the LOD cell size the quad was meshed at.
+    pub fn lod_shift(&self) -> u32 {
+        (self.quad_data2 >> 16) & 3
+    }
+
+    /// Ambient occlusion of corner `vertex` (`gl_VertexID & 3`): 0 = unoccluded, 3 = fully occluded.
+    pub fn ambient_occlusion(&self, vertex: usize) -> u32 {
+        (self.quad_data2 >> (8 + 2 * vertex)) & 3
+    }
+
+    /// Chunk-local corner positions of the quad on `face`, indexed by `gl_VertexID & 3` and
+    /// decoded with the same width/height/flip rules as the vertex shader.
+    pub fn vertices(&self, face: usize) -> [[i32; 3]; 4] {
+        self.vertices_in::<u64>(face)
+    }
+
+    /// [`QuadData::vertices`] for a quad meshed into a `MeshData<C>`; coarse LOD quads are
+    /// clamped to `C::CS` instead of [`crate::CS`].
+    pub fn vertices_in<C: ColumnMask>(&self, face: usize) -> [[i32; 3]; 4] {
+        let d = self.quad_data1;
+        let high = self.quad_data2 >> 18;
+        let field = |i: u32| (((d >> (6 * i)) & 63) | (((high >> i) & 1) << 6)) as i32;
+        let base = [field(0), field(1), field(2)];
+        let w = field(3);
+        let h = field(4);
+        let w_dir = (face & 2) >> 1;
+        let h_dir = 2 - (face >> 2);
+        let scale = 1 << self.lod_shift();
+
+        std::array::from_fn(|vertex| {
+            let w_mod = (vertex >> 1) as i32;
+            let h_mod = (vertex & 1) as i32;
+            let mut pos = base;
+            pos[w_dir] += w * w_mod * FACE_FLIP[face] as i32;
+            pos[h_dir] += h * h_mod;
+            pos.map(|c| (c * scale).min(C::CS as i32))
+        })
+    }
+}
+
+/// The quads of one chunk split by face, as uploaded to the renderer or handed to the exporters.
+/// Transparent quads are kept apart so they can be drawn in their own blended pass.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkMesh {
+    pub chunk_pos: IVec3,
+    pub faces: [Vec<QuadData>; 6],
+    pub transparent_faces: [Vec<QuadData>; 6],
+}
+
+impl ChunkMesh {
+    pub fn from_mesh_data<C: ColumnMask>(chunk_pos: IVec3, mesh: &MeshData<C>) -> Self {
+        let range = |begin: usize, length: usize| mesh.vertices[begin..begin + length].to_vec();
+        Self {
+            chunk_pos,
+            faces: std::array::from_fn(|face| range(mesh.face_vertex_begin[face], mesh.face_vertex_length[face])),
+            transparent_faces: std::array::from_fn(|face| {
+                range(mesh.transparent_face_vertex_begin[face], mesh.transparent_face_vertex_length[face])
+            }),
+        }
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.faces.iter().chain(&self.transparent_faces).map(Vec::len).sum()
+    }
+
+    /// Every quad with its face, opaque quads first.
+    pub fn quads(&self) -> impl Iterator<Item = (usize, &QuadData)> {
+        [&self.faces, &self.transparent_faces].into_iter().flat_map(|faces| {
+            faces.iter().enumerate().flat_map(|(face, quads)| quads.iter().map(move |quad| (face, quad)))
+        })
+    }
+}
+
+/// Per-type transparency lookup indexed by voxel type. Entry 0 (air) is ignored.
+pub type TransparencyTable = [bool; 256];
+
+/// Working buffers and output of the mesher for chunks of `C::CS`³ voxels. Sizes below are
+/// `C`'s constants; the default `u64` matches the crate-level ones.
 #[derive(Debug)]
-pub struct MeshData {
-    pub face_masks: Vec<u64>,      // CS_2 * 6
-    pub opaque_mask: Vec<u64>,     // CS_P2
-    pub forward_merged: Vec<u8>,   // faces 0-3: CS; faces 4-5: CS_2
-    pub right_merged: Vec<u8>,     // faces 4-5: CS
+pub struct MeshData<C: ColumnMask = u64> {
+    pub face_masks: Vec<C>,          // CS_2 * 6
+    pub opaque_mask: Vec<C>,         // CS_P2
+    pub transparent_mask: Vec<C>,    // CS_P2, filled by mesh_with_transparency
+    pub forward_merged: Vec<u8>,     // faces 0-3: CS; faces 4-5: CS_2
+    pub right_merged: Vec<u8>,       // faces 4-5: CS
     pub vertices: Vec<QuadData>,
     pub face_vertex_begin: [usize; 6],
     pub face_vertex_length: [usize; 6],
+    // Transparent quads are written after all opaque quads so they can be drawn in a second,
+    // blended pass.
+    pub transparent_face_vertex_begin: [usize; 6],
+    pub transparent_face_vertex_length: [usize; 6],
+    // When set, quads carry per-corner ambient occlusion in bits 8..16 of `quad_data2` and only
+    // faces with identical occlusion are merged.
+    pub ambient_occlusion: bool,
+    pub lod_voxels: Vec<u8>,         // CS_P3 once mesh_lod has downsampled into it
 }
 
-impl MeshData {
+impl<C: ColumnMask> MeshData<C> {
     pub fn new(initial_quads: usize) -> Self {
         Self {
-            face_masks: vec![0u64; CS_2 * 6],
-            opaque_mask: vec![0u64; CS_P2],
-            forward_merged: vec![0u8; CS_2.max(CS)],
-            right_merged: vec![0u8; CS],
+            face_masks: vec![C::ZERO; C::CS_2 * 6],
+            opaque_mask: vec![C::ZERO; C::CS_P2],
+            transparent_mask: vec![C::ZERO; C::CS_P2],
+            forward_merged: vec![0u8; C::CS_2.max(C::CS)],
+            right_merged: vec![0u8; C::CS],
             vertices: vec![QuadData::default(); initial_quads],
             face_vertex_begin: [0; 6],
             face_vertex_length: [0; 6],
+            transparent_face_vertex_begin: [0; 6],
+            transparent_face_vertex_length: [0; 6],
+            ambient_occlusion: false,
+            lod_voxels: Vec::new(),
         }
     }
 
     pub fn clear_runtime(&mut self) {
         self.vertices.fill(QuadData::default());
-        self.face_masks.fill(0);
+        self.face_masks.fill(C::ZERO);
         self.forward_merged.fill(0);
         self.right_merged.fill(0);
         self.face_vertex_begin = [0; 6];
         self.face_vertex_length = [0; 6];
+        self.transparent_face_vertex_begin = [0; 6];
+        self.transparent_face_vertex_length = [0; 6];
+    }
+
+    /// Rebuilds `opaque_mask` from a padded `CS_P3` voxel buffer, setting every non-air voxel.
+    /// For the default width the RLE decoder fills the mask directly.
+    pub fn fill_opaque_mask(&mut self, voxels: &[u8]) {
+        fill_column_mask(voxels, &mut self.opaque_mask, |ty| ty != 0);
+    }
+}
+
+fn fill_column_mask<C: ColumnMask>(voxels: &[u8], mask: &mut [C], set: impl Fn(u8) -> bool) {
+    debug_assert_eq!(voxels.len(), C::CS_P3);
+    for (column, bits) in mask.iter_mut().enumerate() {
+        let column_voxels = &voxels[column * C::CS_P..(column + 1) * C::CS_P];
+        *bits = C::ZERO;
+        for (z, &ty) in column_voxels.iter().enumerate() {
+            if set(ty) {
+                *bits |= C::ONE << z;
+            }
+        }
     }
 }
 
 #[inline]
-fn get_axis_index(axis: usize, a: usize, b: usize, c: usize) -> usize {
+fn get_axis_index<C: ColumnMask>(axis: usize, a: usize, b: usize, c: usize) -> usize {
     if axis == 0 {
-        b + (a * CS_P) + (c * CS_P2)
+        b + (a * C::CS_P) + (c * C::CS_P2)
     } else if axis == 1 {
-        b + (c * CS_P) + (a * CS_P2)
+        b + (c * C::CS_P) + (a * C::CS_P2)
     } else {
-        c + (a * CS_P) + (b * CS_P2)
+        c + (a * C::CS_P) + (b * C::CS_P2)
     }
 }
 
 #[inline]
-fn get_quad(x: u32, y: u32, z: u32, w: u32, h: u32, ty: u32) -> QuadData {
-    let quad_data1 = (h << 24) | (w << 18) | (z << 12) | (y << 6) | x;
+fn get_quad(x: u32, y: u32, z: u32, w: u32, h: u32, ty: u32, ao: u32) -> QuadData {
+    let quad_data1 = ((h & 63) << 24) | ((w & 63) << 18) | ((z & 63) << 12) | ((y & 63) << 6) | (x & 63);
+    // Chunks wider than 62 need a 7th bit per field. Those go in bits 18..23 of quad_data2,
+    // which stay zero for the chunk size the renderer draws.
+    let high = (x >> 6) | ((y >> 6) << 1) | ((z >> 6) << 2) | ((w >> 6) << 3) | ((h >> 6) << 4);
     QuadData {
         quad_data1,
-        quad_data2: ty,
+        quad_data2: (high << 18) | (ao << 8) | ty,
     }
 }
 
-const P_MASK: u64 = !(1u64 << 63 | 1);
+// Voxel index offset of the neighbour each face looks at.
+#[inline]
+fn face_neighbour_offset<C: ColumnMask>(face: usize) -> isize {
+    [C::CS_P2 as isize, -(C::CS_P2 as isize), C::CS_P as isize, -(C::CS_P as isize), 1, -1][face]
+}
+
+// Voxel index offsets along the quad's width/height axes, and the width direction sign,
+// matching wDir/hDir/flipLookup in the vertex shader.
+#[inline]
+fn face_w_offset<C: ColumnMask>(face: usize) -> isize {
+    if face & 2 == 0 { C::CS_P as isize } else { C::CS_P2 as isize }
+}
+
+#[inline]
+fn face_h_offset<C: ColumnMask>(face: usize) -> isize {
+    if face < 4 { 1 } else { C::CS_P2 as isize }
+}
+
+const FACE_FLIP: [isize; 6] = [1, -1, -1, 1, -1, 1];
+
+#[inline]
+fn is_opaque<C: ColumnMask>(opaque_mask: &[C], index: usize) -> bool {
+    (opaque_mask[index / C::CS_P] >> (index % C::CS_P)) & C::ONE == C::ONE
+}
+
+/// Occlusion of the four corners of `face` on the voxel at `index`, 2 bits per corner
+/// (0 = unoccluded, 3 = fully occluded), indexed by the shader's `gl_VertexID & 3`.
+fn voxel_ao<C: ColumnMask>(opaque_mask: &[C], index: usize, face: usize) -> u32 {
+    let layer = index as isize + face_neighbour_offset::<C>(face);
+    let mut ao = 0u32;
+    for corner in 0..4isize {
+        let w_mod = corner >> 1;
+        let h_mod = corner & 1;
+        let du = (w_mod * 2 - 1) * FACE_FLIP[face] * face_w_offset::<C>(face);
+        let dv = (h_mod * 2 - 1) * face_h_offset::<C>(face);
+
+        let side1 = is_opaque(opaque_mask, (layer + du) as usize);
+        let side2 = is_opaque(opaque_mask, (layer + dv) as usize);
+        let occlusion = if side1 && side2 {
+            3
+        } else {
+            side1 as u32 + side2 as u32 + is_opaque(opaque_mask, (layer + du + dv) as usize) as u32
+        };
+        ao |= occlusion << (corner * 2);
+    }
+    ao
+}
+
+// Column bits of the voxels inside the chunk, without the two padding voxels.
+#[inline]
+fn p_mask<C: ColumnMask>() -> C {
+    !((C::ONE << (C::CS_P - 1)) | C::ONE)
+}
+
+pub fn mesh<C: ColumnMask>(voxels: &[u8], mesh: &mut MeshData<C>) {
+    cull_opaque_faces(mesh);
+
+    let mut begin = [0usize; 6];
+    let mut length = [0usize; 6];
+    let vertex_i = merge_faces(voxels, mesh, 0, &mut begin, &mut length);
+    mesh.face_vertex_begin = begin;
+    mesh.face_vertex_length = length;
+    mesh.transparent_face_vertex_begin = [vertex_i; 6];
+    mesh.transparent_face_vertex_length = [0; 6];
+}
+
+/// Two-pass variant of [`mesh`] for chunks containing transparent voxel types.
+///
+/// `opaque_mask` may be filled exactly as for [`mesh`] (every non-air voxel set); transparent
+/// voxels are removed from it here. Opaque faces are kept wherever they touch air or a
+/// transparent voxel. Transparent faces are culled against opaque voxels and against
+/// transparent voxels of the same type, and are written after the opaque quads into
+/// `transparent_face_vertex_begin`/`transparent_face_vertex_length`.
+pub fn mesh_with_transparency<C: ColumnMask>(voxels: &[u8], transparency: &TransparencyTable, mesh: &mut MeshData<C>) {
+    fill_column_mask(voxels, &mut mesh.transparent_mask, |ty| ty != 0 && transparency[ty as usize]);
+    for (opaque, transparent) in mesh.opaque_mask.iter_mut().zip(&mesh.transparent_mask) {
+        *opaque &= !*transparent;
+    }
+
+    cull_opaque_faces(mesh);
+
+    let mut begin = [0usize; 6];
+    let mut length = [0usize; 6];
+    let vertex_i = merge_faces(voxels, mesh, 0, &mut begin, &mut length);
+    mesh.face_vertex_begin = begin;
+    mesh.face_vertex_length = length;
+
+    cull_transparent_faces(voxels, mesh);
 
-pub fn mesh(voxels: &[u8], mesh: &mut MeshData) {
-    // Reset counts
-    let mut vertex_i: usize = 0;
+    merge_faces(voxels, mesh, vertex_i, &mut begin, &mut length);
+    mesh.transparent_face_vertex_begin = begin;
+    mesh.transparent_face_vertex_length = length;
+}
 
-    // Hidden face culling
-    for a in 1..(CS_P - 1) {
-        let a_cs_p = a * CS_P;
-        for b in 1..(CS_P - 1) {
-            let column_bits = mesh.opaque_mask[(a * CS_P) + b] & P_MASK;
-            let ba_index = (b - 1) + (a - 1) * CS;
-            let ab_index = (a - 1) + (b - 1) * CS;
+#[allow(clippy::identity_op, clippy::erasing_op)]
+fn cull_opaque_faces<C: ColumnMask>(mesh: &mut MeshData<C>) {
+    let (cs, cs_p, cs_2) = (C::CS, C::CS_P, C::CS_2);
+    let p_mask = p_mask::<C>();
+    for a in 1..(cs_p - 1) {
+        let a_cs_p = a * cs_p;
+        for b in 1..(cs_p - 1) {
+            let column_bits = mesh.opaque_mask[(a * cs_p) + b] & p_mask;
+            let ba_index = (b - 1) + (a - 1) * cs;
+            let ab_index = (a - 1) + (b - 1) * cs;
+
+            mesh.face_masks[ba_index + 0 * cs_2] = (column_bits & !mesh.opaque_mask[a_cs_p + cs_p + b]) >> 1;
+            mesh.face_masks[ba_index + 1 * cs_2] = (column_bits & !mesh.opaque_mask[a_cs_p - cs_p + b]) >> 1;
+
+            mesh.face_masks[ab_index + 2 * cs_2] = (column_bits & !mesh.opaque_mask[a_cs_p + (b + 1)]) >> 1;
+            mesh.face_masks[ab_index + 3 * cs_2] = (column_bits & !mesh.opaque_mask[a_cs_p + (b - 1)]) >> 1;
+
+            mesh.face_masks[ba_index + 4 * cs_2] = column_bits & !(mesh.opaque_mask[a_cs_p + b] >> 1);
+            mesh.face_masks[ba_index + 5 * cs_2] = column_bits & !(mesh.opaque_mask[a_cs_p + b] << 1);
+        }
+    }
+}
 
-            mesh.face_masks[ba_index + 0 * CS_2] = (column_bits & !mesh.opaque_mask[a_cs_p + CS_P + b]) >> 1;
-            mesh.face_masks[ba_index + 1 * CS_2] = (column_bits & !mesh.opaque_mask[a_cs_p - CS_P + b]) >> 1;
+fn cull_transparent_faces<C: ColumnMask>(voxels: &[u8], mesh: &mut MeshData<C>) {
+    let (cs, cs_p, cs_2) = (C::CS, C::CS_P, C::CS_2);
+    let p_mask = p_mask::<C>();
+    for a in 1..(cs_p - 1) {
+        let a_cs_p = a * cs_p;
+        for b in 1..(cs_p - 1) {
+            let column = a_cs_p + b;
+            let column_bits = mesh.transparent_mask[column] & p_mask;
+            let ba_index = (b - 1) + (a - 1) * cs;
+            let ab_index = (a - 1) + (b - 1) * cs;
+
+            // Neighbour masks aligned so bit z describes the voxel next to bit z of this column.
+            let neighbour_columns = [column + cs_p, column - cs_p, column + 1, column - 1];
+            for face in 0..6usize {
+                let (opaque, transparent) = match neighbour_columns.get(face) {
+                    Some(&neighbour) => (mesh.opaque_mask[neighbour], mesh.transparent_mask[neighbour]),
+                    None if face == 4 => (mesh.opaque_mask[column] >> 1, mesh.transparent_mask[column] >> 1),
+                    None => (mesh.opaque_mask[column] << 1, mesh.transparent_mask[column] << 1),
+                };
 
-            mesh.face_masks[ab_index + 2 * CS_2] = (column_bits & !mesh.opaque_mask[a_cs_p + (b + 1)]) >> 1;
-            mesh.face_masks[ab_index + 3 * CS_2] = (column_bits & !mesh.opaque_mask[a_cs_p + (b - 1)]) >> 1;
+                let mut bits = column_bits & !opaque & !transparent;
+
+                // Transparent next to transparent: only keep the face between different types.
+                let mut shared = column_bits & transparent;
+                while shared != C::ZERO {
+                    let z = shared.trailing_zeros() as usize;
+                    shared &= shared - C::ONE;
+                    let index = column * cs_p + z;
+                    let neighbour = (index as isize + face_neighbour_offset::<C>(face)) as usize;
+                    if voxels[index] != voxels[neighbour] {
+                        bits |= C::ONE << z;
+                    }
+                }
 
-            mesh.face_masks[ba_index + 4 * CS_2] = column_bits & !(mesh.opaque_mask[a_cs_p + b] >> 1);
-            mesh.face_masks[ba_index + 5 * CS_2] = column_bits & !(mesh.opaque_mask[a_cs_p + b] << 1);
+                match face {
+                    0 | 1 => mesh.face_masks[ba_index + face * cs_2] = bits >> 1,
+                    2 | 3 => mesh.face_masks[ab_index + face * cs_2] = bits >> 1,
+                    _ => mesh.face_masks[ba_index + face * cs_2] = bits,
+                }
+            }
         }
     }
+}
+
+/// Greedily merges the current `face_masks` into quads starting at `vertex_i`, recording the
+/// per-face ranges in `begin`/`length`. Returns the next free vertex index.
+fn merge_faces<C: ColumnMask>(
+    voxels: &[u8],
+    mesh: &mut MeshData<C>,
+    mut vertex_i: usize,
+    begin: &mut [usize; 6],
+    length: &mut [usize; 6],
+) -> usize {
+    let (cs, cs_2) = (C::CS, C::CS_2);
+    let ambient_occlusion = mesh.ambient_occlusion;
 
     // Faces 0-3
     for face in 0..4usize {
         let axis = face / 2;
         let face_vertex_begin = vertex_i;
 
-        for layer in 0..CS {
-            let bits_location = layer * CS + face * CS_2;
+        for layer in 0..cs {
+            let bits_location = layer * cs + face * cs_2;
 
-            for forward in 0..CS {
+            for forward in 0..cs {
                 let mut bits_here = mesh.face_masks[forward + bits_location];
-                if bits_here == 0 {
+                if bits_here == C::ZERO {
                     continue;
                 }
 
-                let bits_next = if forward + 1 < CS {
+                let bits_next = if forward + 1 < cs {
                     mesh.face_masks[(forward + 1) + bits_location]
                 } else {
-                    0
+                    C::ZERO
                 };
 
                 let mut right_merged_run: u8 = 1;
-                while bits_here != 0 {
+                while bits_here != C::ZERO {
                     let bit_pos = bits_here.trailing_zeros() as usize;
 
-                    let ty = voxels[get_axis_index(axis, forward + 1, bit_pos + 1, layer + 1)] as u32;
+                    let index = get_axis_index::<C>(axis, forward + 1, bit_pos + 1, layer + 1);
+                    let ty = voxels[index] as u32;
+                    let ao = if ambient_occlusion { voxel_ao(&mesh.opaque_mask, index, face) } else { 0 };
                     let mut forward_merged_val = mesh.forward_merged[bit_pos];
 
-                    if ((bits_next >> bit_pos) & 1) == 1
-                        && ty == voxels[get_axis_index(axis, forward + 2, bit_pos + 1, layer + 1)] as u32
+                    let next_index = get_axis_index::<C>(axis, forward + 2, bit_pos + 1, layer + 1);
+                    if ((bits_next >> bit_pos) & C::ONE) == C::ONE
+                        && ty == voxels[next_index] as u32
+                        && (!ambient_occlusion || ao == voxel_ao(&mesh.opaque_mask, next_index, face))
                     {
                         forward_merged_val = forward_merged_val.saturating_add(1);
                         mesh.forward_merged[bit_pos] = forward_merged_val;
-                        bits_here &= !(1u64 << bit_pos);
+                        bits_here &= !(C::ONE << bit_pos);
                         continue;
                     }
 
-                    for right in (bit_pos + 1)..CS {
-                        if ((bits_here >> right) & 1) == 0 {
+                    for right in (bit_pos + 1)..cs {
+                        if ((bits_here >> right) & C::ONE) == C::ZERO {
                             break;
                         }
                         if forward_merged_val != mesh.forward_merged[right] {
                             break;
                         }
-                        if ty != voxels[get_axis_index(axis, forward + 1, right + 1, layer + 1)] as u32 {
+                        let right_index = get_axis_index::<C>(axis, forward + 1, right + 1, layer + 1);
+                        if ty != voxels[right_index] as u32 {
+                            break;
+                        }
+                        if ambient_occlusion && ao != voxel_ao(&mesh.opaque_mask, right_index, face) {
                             break;
                         }
                         mesh.forward_merged[right] = 0;
                         right_merged_run = right_merged_run.saturating_add(1);
                     }
 
-                    bits_here &= !((1u64 << (bit_pos + right_merged_run as usize)) - 1);
+                    bits_here &= !((C::ONE << (bit_pos + right_merged_run as usize)) - C::ONE);
 
                     let mesh_front = (forward as i32) - (forward_merged_val as i32);
                     let mesh_left = bit_pos as i32;
@@ -157,6 +495,7 @@ pub fn mesh(voxels: &[u8], mesh: &mut MeshData) {
                             mesh_length,
                             mesh_width,
                             ty,
+                            ao,
                         ),
                         2 | 3 => get_quad(
                             mesh_up as u32,
@@ -165,6 +504,7 @@ pub fn mesh(voxels: &[u8], mesh: &mut MeshData) {
                             mesh_length,
                             mesh_width,
                             ty,
+                            ao,
                         ),
                         _ => unreachable!(),
                     };
@@ -178,9 +518,8 @@ pub fn mesh(voxels: &[u8], mesh: &mut MeshData) {
             }
         }
 
-        let face_vertex_length = vertex_i - face_vertex_begin;
-        mesh.face_vertex_begin[face] = face_vertex_begin;
-        mesh.face_vertex_length[face] = face_vertex_length;
+        begin[face] = face_vertex_begin;
+        length[face] = vertex_i - face_vertex_begin;
     }
 
     // Faces 4-5
@@ -188,58 +527,64 @@ pub fn mesh(voxels: &[u8], mesh: &mut MeshData) {
         let axis = face / 2;
         let face_vertex_begin = vertex_i;
 
-        for forward in 0..CS {
-            let bits_location = forward * CS + face * CS_2;
-            let bits_forward_location = (forward + 1) * CS + face * CS_2;
+        for forward in 0..cs {
+            let bits_location = forward * cs + face * cs_2;
+            let bits_forward_location = (forward + 1) * cs + face * cs_2;
 
-            for right in 0..CS {
+            for right in 0..cs {
                 let mut bits_here = mesh.face_masks[right + bits_location];
-                if bits_here == 0 {
+                if bits_here == C::ZERO {
                     continue;
                 }
 
-                let bits_forward = if forward < CS - 1 {
+                let bits_forward = if forward < cs - 1 {
                     mesh.face_masks[right + bits_forward_location]
                 } else {
-                    0
+                    C::ZERO
                 };
 
-                let bits_right = if right < CS - 1 {
+                let bits_right = if right < cs - 1 {
                     mesh.face_masks[right + 1 + bits_location]
                 } else {
-                    0
+                    C::ZERO
                 };
 
-                let right_cs = right * CS;
+                let right_cs = right * cs;
 
-                while bits_here != 0 {
+                while bits_here != C::ZERO {
                     let bit_pos = bits_here.trailing_zeros() as usize;
-                    bits_here &= !(1u64 << bit_pos);
+                    bits_here &= !(C::ONE << bit_pos);
 
-                    let ty = voxels[get_axis_index(axis, right + 1, forward + 1, bit_pos)] as u32;
+                    let index = get_axis_index::<C>(axis, right + 1, forward + 1, bit_pos);
+                    let ty = voxels[index] as u32;
+                    let ao = if ambient_occlusion { voxel_ao(&mesh.opaque_mask, index, face) } else { 0 };
 
                     let f_idx = right_cs + (bit_pos - 1);
                     let mut forward_merged_val = mesh.forward_merged[f_idx];
                     let mut right_merged_val = mesh.right_merged[bit_pos - 1];
 
+                    let forward_index = get_axis_index::<C>(axis, right + 1, forward + 2, bit_pos);
                     if right_merged_val == 0
-                        && ((bits_forward >> bit_pos) & 1) == 1
-                        && ty == voxels[get_axis_index(axis, right + 1, forward + 2, bit_pos)] as u32
+                        && ((bits_forward >> bit_pos) & C::ONE) == C::ONE
+                        && ty == voxels[forward_index] as u32
+                        && (!ambient_occlusion || ao == voxel_ao(&mesh.opaque_mask, forward_index, face))
                     {
                         forward_merged_val = forward_merged_val.saturating_add(1);
                         mesh.forward_merged[f_idx] = forward_merged_val;
                         continue;
                     }
 
-                    let next_forward_merged = if right + 1 < CS {
-                        mesh.forward_merged[(right_cs + CS) + (bit_pos - 1)]
+                    let next_forward_merged = if right + 1 < cs {
+                        mesh.forward_merged[(right_cs + cs) + (bit_pos - 1)]
                     } else {
                         0
                     };
 
-                    if ((bits_right >> bit_pos) & 1) == 1
+                    let right_index = get_axis_index::<C>(axis, right + 2, forward + 1, bit_pos);
+                    if ((bits_right >> bit_pos) & C::ONE) == C::ONE
                         && forward_merged_val == next_forward_merged
-                        && ty == voxels[get_axis_index(axis, right + 2, forward + 1, bit_pos)] as u32
+                        && ty == voxels[right_index] as u32
+                        && (!ambient_occlusion || ao == voxel_ao(&mesh.opaque_mask, right_index, face))
                     {
                         mesh.forward_merged[f_idx] = 0;
                         right_merged_val = right_merged_val.saturating_add(1);
@@ -267,6 +612,7 @@ pub fn mesh(voxels: &[u8], mesh: &mut MeshData) {
                             mesh_width,
                             mesh_length,
                             ty,
+                            ao,
                         ),
                         _ => unreachable!(),
                     };
@@ -280,11 +626,183 @@ pub fn mesh(voxels: &[u8], mesh: &mut MeshData) {
             }
         }
 
-        let face_vertex_length = vertex_i - face_vertex_begin;
-        mesh.face_vertex_begin[face] = face_vertex_begin;
-        mesh.face_vertex_length[face] = face_vertex_length;
+        begin[face] = face_vertex_begin;
+        length[face] = vertex_i - face_vertex_begin;
     }
 
     // Shrink visible slice markers (we keep allocated capacity in vertices vec)
     // Caller uses face ranges to decide what to upload.
+    vertex_i
+}
+
+/// Downsampling factor for distant chunks. Quads meshed at a coarser level carry
+/// `log2(scale)` in bits 16..18 of `quad_data2`; positions and sizes are in cells of `scale`
+/// voxels.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Lod {
+    #[default]
+    Full,
+    Half,
+    Quarter,
+    Eighth,
+}
+
+impl Lod {
+    pub fn shift(self) -> u32 {
+        match self {
+            Lod::Full => 0,
+            Lod::Half => 1,
+            Lod::Quarter => 2,
+            Lod::Eighth => 3,
+        }
+    }
+
+    pub fn scale(self) -> usize {
+        1 << self.shift()
+    }
+}
+
+/// Meshes `voxels` at the given level of detail.
+///
+/// For anything coarser than [`Lod::Full`] the chunk is downsampled into `mesh.lod_voxels`: each
+/// cell becomes the most common non-air type among its voxels (or air if it has none), and
+/// `mesh.opaque_mask` is rebuilt from the result. At [`Lod::Full`] this is just [`mesh`].
+pub fn mesh_lod<C: ColumnMask>(voxels: &[u8], lod: Lod, mesh_data: &mut MeshData<C>) {
+    if lod == Lod::Full {
+        mesh(voxels, mesh_data);
+        return;
+    }
+    mesh_coarse(voxels, lod, None, mesh_data);
+}
+
+/// [`mesh_lod`] with the types `transparency` marks kept in the transparent ranges, as
+/// [`mesh_with_transparency`] does. At [`Lod::Full`] this is just [`mesh_with_transparency`].
+pub fn mesh_lod_with_transparency<C: ColumnMask>(
+    voxels: &[u8],
+    lod: Lod,
+    transparency: &TransparencyTable,
+    mesh_data: &mut MeshData<C>,
+) {
+    if lod == Lod::Full {
+        mesh_with_transparency(voxels, transparency, mesh_data);
+        return;
+    }
+    mesh_coarse(voxels, lod, Some(transparency), mesh_data);
+}
+
+fn mesh_coarse<C: ColumnMask>(
+    voxels: &[u8],
+    lod: Lod,
+    transparency: Option<&TransparencyTable>,
+    mesh_data: &mut MeshData<C>,
+) {
+    debug_assert_eq!(voxels.len(), C::CS_P3);
+
+    let mut lod_voxels = std::mem::take(&mut mesh_data.lod_voxels);
+    lod_voxels.clear();
+    lod_voxels.resize(C::CS_P3, 0);
+    downsample::<C>(voxels, lod.scale(), &mut lod_voxels);
+    mesh_data.fill_opaque_mask(&lod_voxels);
+    if let Some(transparency) = transparency {
+        fill_column_mask(&lod_voxels, &mut mesh_data.transparent_mask, |ty| ty != 0 && transparency[ty as usize]);
+        for (opaque, transparent) in mesh_data.opaque_mask.iter_mut().zip(&mesh_data.transparent_mask) {
+            *opaque &= !*transparent;
+        }
+    }
+
+    cull_opaque_faces(mesh_data);
+    clip_to_cells(mesh_data, lod);
+
+    let mut begin = [0usize; 6];
+    let mut length = [0usize; 6];
+    let mut vertex_i = merge_faces(&lod_voxels, mesh_data, 0, &mut begin, &mut length);
+    mesh_data.face_vertex_begin = begin;
+    mesh_data.face_vertex_length = length;
+    mesh_data.transparent_face_vertex_begin = [vertex_i; 6];
+    mesh_data.transparent_face_vertex_length = [0; 6];
+
+    if transparency.is_some() {
+        cull_transparent_faces(&lod_voxels, mesh_data);
+        clip_to_cells(mesh_data, lod);
+        vertex_i = merge_faces(&lod_voxels, mesh_data, vertex_i, &mut begin, &mut length);
+        mesh_data.transparent_face_vertex_begin = begin;
+        mesh_data.transparent_face_vertex_length = length;
+    }
+    mesh_data.lod_voxels = lod_voxels;
+
+    for quad in &mut mesh_data.vertices[..vertex_i] {
+        quad.quad_data2 |= lod.shift() << 16;
+    }
+}
+
+// Drops faces outside the cells a coarse LOD spans. The far border cells sit inside the meshed
+// range; only cull against them.
+fn clip_to_cells<C: ColumnMask>(mesh_data: &mut MeshData<C>, lod: Lod) {
+    let cells = C::CS.div_ceil(lod.scale());
+    let cell_bits = (C::ONE << cells) - C::ONE;
+    for face in 0..6usize {
+        let bits = if face < 4 { cell_bits } else { cell_bits << 1 };
+        for layer in 0..C::CS {
+            for row in 0..C::CS {
+                let face_bits = &mut mesh_data.face_masks[row + layer * C::CS + face * C::CS_2];
+                if layer < cells && row < cells {
+                    *face_bits &= bits;
+                } else {
+                    *face_bits = C::ZERO;
+                }
+            }
+        }
+    }
+}
+
+// Original padded-voxel range covered by padded cell `c` when `cells` cells span the chunk.
+// The border cells map onto the 1-voxel padding so neighbour culling still works.
+fn cell_range(c: usize, cells: usize, scale: usize, cs: usize) -> std::ops::Range<usize> {
+    if c == 0 {
+        0..1
+    } else if c == cells + 1 {
+        cs + 1..cs + 2
+    } else {
+        1 + (c - 1) * scale..(1 + c * scale).min(cs + 1)
+    }
+}
+
+fn downsample<C: ColumnMask>(voxels: &[u8], scale: usize, out: &mut [u8]) {
+    let cells = C::CS.div_ceil(scale);
+    let mut counts = [0u16; 256];
+    let mut seen: Vec<u8> = Vec::with_capacity(scale * scale * scale);
+
+    for cy in 0..cells + 2 {
+        for cx in 0..cells + 2 {
+            for cz in 0..cells + 2 {
+                for y in cell_range(cy, cells, scale, C::CS) {
+                    for x in cell_range(cx, cells, scale, C::CS) {
+                        for z in cell_range(cz, cells, scale, C::CS) {
+                            let ty = voxels[C::zxy_index(x, y, z)];
+                            if ty == 0 {
+                                continue;
+                            }
+                            if counts[ty as usize] == 0 {
+                                seen.push(ty);
+                            }
+                            counts[ty as usize] += 1;
+                        }
+                    }
+                }
+
+                let mut dominant = 0u8;
+                let mut dominant_count = 0u16;
+                for &ty in &seen {
+                    if counts[ty as usize] > dominant_count {
+                        dominant = ty;
+                        dominant_count = counts[ty as usize];
+                    }
+                    counts[ty as usize] = 0;
+                }
+                seen.clear();
+
+                out[C::zxy_index(cx, cy, cz)] = dominant;
+            }
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use glam::{Mat4, Vec3};
+use glam::{IVec3, Mat4, Vec3, Vec4};
 
 pub struct Camera {
     pub position: Vec3,
@@ -49,16 +49,23 @@ impl Camera {
         Mat4::look_at_rh(intra, intra + self.front, self.up)
     }
 
+    /// Integer part of the position. The view matrix (and so [`Self::frustum`]) works relative to
+    /// it, which keeps vertex coordinates small far from the origin.
+    pub fn eye_position_int(&self) -> IVec3 {
+        self.position.floor().as_ivec3()
+    }
+
+    /// View frustum in camera-relative space: subtract [`Self::eye_position_int`] from world
+    /// positions before testing them.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.projection * self.get_view_matrix())
+    }
+
     pub fn process_mouse_movement(&mut self, x_offset: f32, y_offset: f32) {
         self.yaw += x_offset * self.mouse_sensitivity;
         self.pitch += y_offset * self.mouse_sensitivity;
 
-        if self.pitch > 89.9 {
-            self.pitch = 89.9;
-        }
-        if self.pitch < -89.9 {
-            self.pitch = -89.9;
-        }
+        self.pitch = self.pitch.clamp(-89.9, 89.9);
         self.update_camera_vectors();
     }
 
@@ -72,3 +79,37 @@ impl Camera {
         self.up = self.right.cross(self.front).normalize();
     }
 }
+
+/// The six clip planes of a view-projection matrix, as `(normal, distance)` with the normals
+/// pointing inwards, so a point is inside when `normal.dot(p) + distance >= 0` for every plane.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from an OpenGL-style (`-1..1` depth) clip matrix (Gribb & Hartmann).
+    /// Planes come out in the order left, right, bottom, top, near, far and are normalised, so
+    /// plane equations give signed distances.
+    pub fn from_matrix(view_projection: Mat4) -> Self {
+        let [r0, r1, r2, r3] = [0, 1, 2, 3].map(|i| view_projection.row(i));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2]
+            .map(|plane| plane / plane.truncate().length());
+        Self { planes }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+    }
+
+    /// Conservative box test: false only if the box is fully outside one of the planes, so a few
+    /// boxes near the frustum's corners are kept even though they're off screen.
+    pub fn intersects_aabb(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            // The corner furthest along the plane normal.
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), max, min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
@@ -62,6 +62,43 @@ impl ShaderProgram {
         }
     }
 
+    pub fn new_compute(gl: &Rc<glow::Context>, compute_src: &str) -> Result<Self> {
+        unsafe {
+            let program = gl
+                .create_program()
+                .map_err(|e| anyhow!("create_program failed: {e}"))?;
+
+            let cs = gl
+                .create_shader(glow::COMPUTE_SHADER)
+                .map_err(|e| anyhow!("create compute shader failed: {e}"))?;
+            gl.shader_source(cs, compute_src);
+            gl.compile_shader(cs);
+            if !gl.get_shader_compile_status(cs) {
+                let log = gl.get_shader_info_log(cs);
+                gl.delete_shader(cs);
+                gl.delete_program(program);
+                return Err(anyhow!("Compute shader compile failed: {log}"));
+            }
+
+            gl.attach_shader(program, cs);
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                let log = gl.get_program_info_log(program);
+                gl.delete_shader(cs);
+                gl.delete_program(program);
+                return Err(anyhow!("Program link failed: {log}"));
+            }
+
+            gl.detach_shader(program, cs);
+            gl.delete_shader(cs);
+
+            Ok(Self {
+                gl: Rc::clone(gl),
+                id: program,
+            })
+        }
+    }
+
     pub fn bind(&self) {
         unsafe {
             self.gl.use_program(Some(self.id));
@@ -80,6 +117,15 @@ impl ShaderProgram {
         unsafe { self.gl.uniform_3_f32(Some(loc), v.x, v.y, v.z) }
     }
 
+    pub fn set_vec4_array(&self, loc: &glow::NativeUniformLocation, v: &[glam::Vec4]) {
+        let flat: Vec<f32> = v.iter().flat_map(|v| v.to_array()).collect();
+        unsafe { self.gl.uniform_4_f32_slice(Some(loc), &flat) }
+    }
+
+    pub fn set_uint(&self, loc: &glow::NativeUniformLocation, v: u32) {
+        unsafe { self.gl.uniform_1_u32(Some(loc), v) }
+    }
+
     pub fn set_ivec3(&self, loc: &glow::NativeUniformLocation, x: i32, y: i32, z: i32) {
         unsafe { self.gl.uniform_3_i32(Some(loc), x, y, z) }
     }
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+/// A block that moved during [`FreeListAllocator::compact`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    pub from: u32,
+    pub to: u32,
+    pub len: u32,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllocatorStats {
+    pub capacity: u32,
+    pub used: u32,
+    pub allocations: usize,
+    pub free_blocks: usize,
+    pub largest_free_block: u32,
+}
+
+impl AllocatorStats {
+    pub fn free(&self) -> u32 {
+        self.capacity - self.used
+    }
+
+    /// Share of free space that is not part of the largest free block (0 = one contiguous hole).
+    pub fn fragmentation(&self) -> f32 {
+        match self.free() {
+            0 => 0.0,
+            free => 1.0 - self.largest_free_block as f32 / free as f32,
+        }
+    }
+}
+
+/// Best-fit free-list allocator over a fixed range of `capacity` units.
+///
+/// It only does the bookkeeping, the caller owns the storage: the chunk renderer uses it to place
+/// quads in its SSBO, with one unit per quad.
+#[derive(Clone, Debug)]
+pub struct FreeListAllocator {
+    capacity: u32,
+    // Both maps are keyed by offset and store the block length.
+    free_blocks: BTreeMap<u32, u32>,
+    allocations: BTreeMap<u32, u32>,
+    used: u32,
+}
+
+impl FreeListAllocator {
+    pub fn new(capacity: u32) -> Self {
+        let mut free_blocks = BTreeMap::new();
+        if capacity > 0 {
+            free_blocks.insert(0, capacity);
+        }
+        Self {
+            capacity,
+            free_blocks,
+            allocations: BTreeMap::new(),
+            used: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Returns the offset of a new block of `len` units, or `None` if no free block is large
+    /// enough (or `len` is 0). Picks the smallest fitting block, lowest offset on ties.
+    pub fn alloc(&mut self, len: u32) -> Option<u32> {
+        if len == 0 {
+            return None;
+        }
+        let (&offset, &block_len) = self
+            .free_blocks
+            .iter()
+            .filter(|&(_, &block_len)| block_len >= len)
+            .min_by_key(|&(&offset, &block_len)| (block_len, offset))?;
+
+        self.free_blocks.remove(&offset);
+        if block_len > len {
+            self.free_blocks.insert(offset + len, block_len - len);
+        }
+        self.allocations.insert(offset, len);
+        self.used += len;
+        Some(offset)
+    }
+
+    /// Releases the block starting at `offset`, merging it with adjacent free blocks.
+    /// Returns its length, or `None` if no allocation starts there.
+    pub fn free(&mut self, offset: u32) -> Option<u32> {
+        let len = self.allocations.remove(&offset)?;
+        self.used -= len;
+
+        let mut start = offset;
+        let mut end = offset + len;
+        if let Some((&prev, &prev_len)) = self.free_blocks.range(..offset).next_back()
+            && prev + prev_len == offset
+        {
+            self.free_blocks.remove(&prev);
+            start = prev;
+        }
+        if let Some(next_len) = self.free_blocks.remove(&end) {
+            end += next_len;
+        }
+        self.free_blocks.insert(start, end - start);
+        Some(len)
+    }
+
+    /// Length of the allocation starting at `offset`.
+    pub fn allocation_len(&self, offset: u32) -> Option<u32> {
+        self.allocations.get(&offset).copied()
+    }
+
+    /// Slides every allocation down to close the gaps, keeping their order, and leaves a single
+    /// free block at the end. Returns the moved blocks in ascending order; since blocks only move
+    /// down, copying them in that order never overwrites data that has yet to be moved.
+    pub fn compact(&mut self) -> Vec<Relocation> {
+        let mut relocations = Vec::new();
+        let mut next = 0;
+        let allocations = std::mem::take(&mut self.allocations);
+        for (offset, len) in allocations {
+            if offset != next {
+                relocations.push(Relocation {
+                    from: offset,
+                    to: next,
+                    len,
+                });
+            }
+            self.allocations.insert(next, len);
+            next += len;
+        }
+
+        self.free_blocks.clear();
+        if next < self.capacity {
+            self.free_blocks.insert(next, self.capacity - next);
+        }
+        relocations
+    }
+
+    pub fn stats(&self) -> AllocatorStats {
+        AllocatorStats {
+            capacity: self.capacity,
+            used: self.used,
+            allocations: self.allocations.len(),
+            free_blocks: self.free_blocks.len(),
+            largest_free_block: self.free_blocks.values().copied().max().unwrap_or(0),
+        }
+    }
+}
@@ -1,8 +1,13 @@
 use crate::CS;
+use crate::data::blocks::{BlockRegistry, BlockType, BLOCK_TYPE_COUNT};
 use crate::mesher::QuadData;
+use crate::rendering::allocator::{AllocatorStats, FreeListAllocator, Relocation};
+use crate::rendering::gpu_culling::GpuCuller;
+use crate::rendering::multi_draw::{DrawPath, MultiDraw};
 use anyhow::{anyhow, Result};
 use bytemuck::{Pod, Zeroable};
 use glow::HasContext;
+use std::ffi::{c_void, CStr};
 use std::rc::Rc;
 
 pub const BUFFER_SIZE_BYTES: usize = 512 * 1024 * 1024; // 512MB
@@ -18,25 +23,68 @@ pub struct DrawElementsIndirectCommand {
     pub base_instance: u32,
 }
 
+/// `GpuBlock::flags` bit for emissive blocks.
+pub const BLOCK_FLAG_EMISSIVE: u32 = 1;
+
+/// One entry of the block palette SSBO (binding 1), matching the shader's std430 `Block`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct GpuBlock {
+    /// sRGB colour and opacity.
+    pub color: [f32; 4],
+    pub flags: u32,
+    /// Texture layer, or -1 for none.
+    pub texture: i32,
+    pub _pad: [u32; 2],
+}
+
+impl From<&BlockType> for GpuBlock {
+    fn from(block: &BlockType) -> Self {
+        let [r, g, b] = block.color;
+        Self {
+            color: [r, g, b, block.opacity],
+            flags: if block.emissive { BLOCK_FLAG_EMISSIVE } else { 0 },
+            texture: block.texture.map_or(-1, |t| t as i32),
+            _pad: [0; 2],
+        }
+    }
+}
+
+/// The palette SSBO contents for `blocks`, indexed by voxel type.
+pub fn gpu_blocks(blocks: &BlockRegistry) -> Vec<GpuBlock> {
+    (0..BLOCK_TYPE_COUNT).map(|ty| GpuBlock::from(blocks.get(ty as u8))).collect()
+}
+
 pub struct ChunkRenderer {
     gl: Rc<glow::Context>,
     vao: glow::NativeVertexArray,
     ibo: glow::NativeBuffer,
     ssbo: glow::NativeBuffer,
+    // Per-type colours and flags, see `GpuBlock`.
+    block_buffer: glow::NativeBuffer,
     command_buffer: glow::NativeBuffer,
+    multi_draw: MultiDraw,
+    draw_path: DrawPath,
 
     pub draw_commands: Vec<DrawElementsIndirectCommand>,
-    allocation_end_bytes: usize,
+    // Quad slots of the SSBO.
+    allocator: FreeListAllocator,
 }
 
 impl ChunkRenderer {
-    pub fn new(gl: &Rc<glow::Context>) -> Result<Self> {
+    /// `get_proc_address` is used to load the multi-draw-indirect entry points that glow lacks.
+    pub fn new(gl: &Rc<glow::Context>, get_proc_address: impl FnMut(&CStr) -> *const c_void) -> Result<Self> {
+        let multi_draw = MultiDraw::load(gl, get_proc_address);
+        let draw_path = multi_draw.best_path();
         unsafe {
             let vao = gl
                 .create_vertex_array()
                 .map_err(|e| anyhow!("create VAO failed: {e}"))?;
             let ibo = gl.create_buffer().map_err(|e| anyhow!("create IBO failed: {e}"))?;
             let ssbo = gl.create_buffer().map_err(|e| anyhow!("create SSBO failed: {e}"))?;
+            let block_buffer = gl
+                .create_buffer()
+                .map_err(|e| anyhow!("create block palette buffer failed: {e}"))?;
             let command_buffer = gl
                 .create_buffer()
                 .map_err(|e| anyhow!("create indirect buffer failed: {e}"))?;
@@ -46,6 +94,12 @@ impl ChunkRenderer {
             // SSBO
             gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(ssbo));
             gl.buffer_data_size(glow::SHADER_STORAGE_BUFFER, BUFFER_SIZE_BYTES as i32, glow::DYNAMIC_DRAW);
+            gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(block_buffer));
+            gl.buffer_data_u8_slice(
+                glow::SHADER_STORAGE_BUFFER,
+                bytemuck::cast_slice(&gpu_blocks(&BlockRegistry::default())),
+                glow::STATIC_DRAW,
+            );
             gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, None);
 
             // IBO indices (enough for worst-case number of quads in a face: CS^3)
@@ -53,7 +107,7 @@ impl ChunkRenderer {
             let mut indices: Vec<u32> = Vec::with_capacity(max_quads * 6);
             for i in 0..(max_quads as u32) {
                 indices.push((i << 2) | 2);
-                indices.push((i << 2) | 0);
+                indices.push(i << 2);
                 indices.push((i << 2) | 1);
                 indices.push((i << 2) | 1);
                 indices.push((i << 2) | 3);
@@ -83,31 +137,119 @@ impl ChunkRenderer {
                 vao,
                 ibo,
                 ssbo,
+                block_buffer,
                 command_buffer,
+                multi_draw,
+                draw_path,
                 draw_commands: Vec::new(),
-                allocation_end_bytes: 0,
+                allocator: FreeListAllocator::new((BUFFER_SIZE_BYTES / QUAD_SIZE_BYTES) as u32),
             })
         }
     }
 
+    /// Replaces the block palette the vertex shader colours quads with.
+    pub fn set_blocks(&self, blocks: &BlockRegistry) {
+        unsafe {
+            self.gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(self.block_buffer));
+            self.gl
+                .buffer_sub_data_u8_slice(glow::SHADER_STORAGE_BUFFER, 0, bytemuck::cast_slice(&gpu_blocks(blocks)));
+            self.gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, None);
+        }
+    }
+
     pub fn upload_quads(&mut self, quads: &[QuadData]) -> Result<u32> {
         // Returns base_vertex (in vertices, i.e. quad_index*4)
-        let bytes = quads.len() * QUAD_SIZE_BYTES;
-        anyhow::ensure!(self.allocation_end_bytes + bytes <= BUFFER_SIZE_BYTES, "SSBO out of space");
+        anyhow::ensure!(!quads.is_empty(), "cannot upload an empty quad list");
+        let base_quad = self
+            .allocator
+            .alloc(quads.len() as u32)
+            .ok_or_else(|| anyhow!("SSBO out of space"))?;
 
-        let base_quad = self.allocation_end_bytes / QUAD_SIZE_BYTES;
         unsafe {
             self.gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(self.ssbo));
             self.gl.buffer_sub_data_u8_slice(
                 glow::SHADER_STORAGE_BUFFER,
-                self.allocation_end_bytes as i32,
+                (base_quad as usize * QUAD_SIZE_BYTES) as i32,
                 bytemuck::cast_slice(quads),
             );
             self.gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, None);
         }
 
-        self.allocation_end_bytes += bytes;
-        Ok((base_quad as u32) << 2)
+        Ok(base_quad << 2)
+    }
+
+    /// Releases quads uploaded by [`Self::upload_quads`]. Returns false if `base_vertex` was not
+    /// the start of a live allocation.
+    pub fn free_quads(&mut self, base_vertex: u32) -> bool {
+        self.allocator.free(base_vertex >> 2).is_some()
+    }
+
+    /// Usage of the SSBO, in quads.
+    pub fn allocator_stats(&self) -> AllocatorStats {
+        self.allocator.stats()
+    }
+
+    /// Moves all uploaded quads to the start of the SSBO so the free space is contiguous again.
+    /// Returns the moved allocations with `from`/`to` as base vertices (`len` stays in quads); draw
+    /// commands using an old base vertex must be updated before the next render.
+    pub fn compact(&mut self) -> Result<Vec<Relocation>> {
+        let relocations = self.allocator.compact();
+        let Some(max_len) = relocations.iter().map(|r| r.len).max() else {
+            return Ok(relocations);
+        };
+
+        // Source and destination may overlap, which glCopyBufferSubData does not allow within one
+        // buffer, so every block goes through a scratch buffer.
+        unsafe {
+            let scratch = self
+                .gl
+                .create_buffer()
+                .map_err(|e| anyhow!("create scratch buffer failed: {e}"))?;
+            self.gl.bind_buffer(glow::COPY_READ_BUFFER, Some(self.ssbo));
+            self.gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(scratch));
+            self.gl.buffer_data_size(
+                glow::COPY_WRITE_BUFFER,
+                (max_len as usize * QUAD_SIZE_BYTES) as i32,
+                glow::STREAM_COPY,
+            );
+
+            for r in &relocations {
+                let size = (r.len as usize * QUAD_SIZE_BYTES) as i32;
+                let from = (r.from as usize * QUAD_SIZE_BYTES) as i32;
+                let to = (r.to as usize * QUAD_SIZE_BYTES) as i32;
+                self.gl
+                    .copy_buffer_sub_data(glow::COPY_READ_BUFFER, glow::COPY_WRITE_BUFFER, from, 0, size);
+                self.gl
+                    .copy_buffer_sub_data(glow::COPY_WRITE_BUFFER, glow::COPY_READ_BUFFER, 0, to, size);
+            }
+
+            self.gl.bind_buffer(glow::COPY_READ_BUFFER, None);
+            self.gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
+            self.gl.delete_buffer(scratch);
+        }
+
+        Ok(relocations
+            .into_iter()
+            .map(|r| Relocation {
+                from: r.from << 2,
+                to: r.to << 2,
+                len: r.len,
+            })
+            .collect())
+    }
+
+    pub fn draw_path(&self) -> DrawPath {
+        self.draw_path
+    }
+
+    /// Switches how commands are submitted. Returns false (and keeps the current path) if the
+    /// context doesn't support `path`.
+    pub fn set_draw_path(&mut self, path: DrawPath) -> bool {
+        let supported = self.multi_draw.supports(path);
+        if supported {
+            self.draw_path = path;
+        }
+        supported
     }
 
     pub fn add_draw_command(&mut self, cmd: DrawElementsIndirectCommand) {
@@ -119,6 +261,7 @@ impl ChunkRenderer {
             return;
         }
 
+        let draw_count = self.draw_commands.len() as i32;
         unsafe {
             self.gl.bind_buffer(glow::DRAW_INDIRECT_BUFFER, Some(self.command_buffer));
             self.gl.buffer_data_u8_slice(
@@ -127,23 +270,90 @@ impl ChunkRenderer {
                 glow::DYNAMIC_DRAW,
             );
 
+            // The count is known here, so even `DrawPath::MultiDrawCount` submits it directly.
+            self.submit(self.command_buffer, draw_count, None);
+        }
+
+        self.draw_commands.clear();
+    }
+
+    /// Draws `draw_commands` alpha-blended over what is already in the framebuffer, testing against
+    /// but not writing depth. Meant for transparent quads after the opaque pass; the commands
+    /// should be added back to front.
+    pub fn render_transparent(&mut self) {
+        if self.draw_commands.is_empty() {
+            return;
+        }
+
+        unsafe {
+            self.gl.enable(glow::BLEND);
+            self.gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+            self.gl.depth_mask(false);
+        }
+        self.render();
+        unsafe {
+            self.gl.depth_mask(true);
+            self.gl.disable(glow::BLEND);
+        }
+    }
+
+    /// Draws the commands left by [`GpuCuller::dispatch`] instead of `draw_commands`. Compacted
+    /// commands need `DrawPath::MultiDrawCount` to pick up their count on the GPU; on any other
+    /// path the count has to be read back, which stalls until the culling pass is done, so
+    /// dispatch without compaction there and all slots are drawn in place.
+    pub fn render_culled(&mut self, culler: &GpuCuller) {
+        let (draw_count, count_buffer) = match (culler.compacted(), self.draw_path) {
+            (true, DrawPath::MultiDrawCount) => (culler.max_draw_count() as i32, Some(culler.count_buffer())),
+            (true, _) => (culler.read_stats().draw_count as i32, None),
+            (false, _) => (culler.max_draw_count() as i32, None),
+        };
+        if draw_count == 0 {
+            return;
+        }
+        unsafe { self.submit(culler.command_buffer(), draw_count, count_buffer) };
+    }
+
+    /// Issues the draws for `command_buffer` with the current draw path. With a `count_buffer`,
+    /// `draw_count` is only the upper bound and the real count is read from it on the GPU.
+    unsafe fn submit(
+        &self,
+        command_buffer: glow::NativeBuffer,
+        draw_count: i32,
+        count_buffer: Option<glow::NativeBuffer>,
+    ) {
+        unsafe {
+            self.gl.bind_buffer(glow::DRAW_INDIRECT_BUFFER, Some(command_buffer));
             self.gl.bind_vertex_array(Some(self.vao));
             self.gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(self.ibo));
             self.gl.bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 0, Some(self.ssbo));
+            self.gl.bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 1, Some(self.block_buffer));
 
-            // Draw one indirect command at a time for broad compatibility.
-            for i in 0..self.draw_commands.len() {
-                let offset = (i * std::mem::size_of::<DrawElementsIndirectCommand>()) as i32;
-                self.gl
-                    .draw_elements_indirect_offset(glow::TRIANGLES, glow::UNSIGNED_INT, offset);
+            match (self.draw_path, count_buffer) {
+                (DrawPath::MultiDrawCount, Some(count_buffer)) => {
+                    self.gl.bind_buffer(glow::PARAMETER_BUFFER, Some(count_buffer));
+                    self.multi_draw
+                        .draw_elements_indirect_count(glow::TRIANGLES, glow::UNSIGNED_INT, 0, 0, draw_count);
+                    self.gl.bind_buffer(glow::PARAMETER_BUFFER, None);
+                }
+                (DrawPath::MultiDraw | DrawPath::MultiDrawCount, _) => {
+                    self.multi_draw
+                        .draw_elements_indirect(glow::TRIANGLES, glow::UNSIGNED_INT, 0, draw_count);
+                }
+                (DrawPath::PerCommand, _) => {
+                    // Fallback: one indirect command at a time.
+                    for i in 0..draw_count as usize {
+                        let offset = (i * std::mem::size_of::<DrawElementsIndirectCommand>()) as i32;
+                        self.gl
+                            .draw_elements_indirect_offset(glow::TRIANGLES, glow::UNSIGNED_INT, offset);
+                    }
+                }
             }
 
             self.gl.bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 0, None);
+            self.gl.bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 1, None);
             self.gl.bind_vertex_array(None);
             self.gl.bind_buffer(glow::DRAW_INDIRECT_BUFFER, None);
         }
-
-        self.draw_commands.clear();
     }
 }
 
@@ -152,6 +362,7 @@ impl Drop for ChunkRenderer {
         unsafe {
             self.gl.delete_buffer(self.ibo);
             self.gl.delete_buffer(self.ssbo);
+            self.gl.delete_buffer(self.block_buffer);
             self.gl.delete_buffer(self.command_buffer);
             self.gl.delete_vertex_array(self.vao);
         }
@@ -0,0 +1,475 @@
+use crate::misc::camera::Frustum;
+use crate::misc::shader::ShaderProgram;
+use crate::rendering::chunk_renderer::DrawElementsIndirectCommand;
+use crate::rendering::hiz::HiZFrame;
+use anyhow::{anyhow, Context, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::IVec3;
+use glow::HasContext;
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+// One invocation per chunk slot. Chunks outside the frustum, or (optionally) hidden behind last
+// frame's depth pyramid, are dropped and counted; of the rest, faces that can face the camera
+// (see `facing_faces`) are counted in `drawCount`. With `compact` they are appended to
+// `commands`; without it every slot writes its six commands in place, culled ones with zero
+// instances, so the draw count never has to be read back.
+const CULL_SRC: &str = r#"#version 460 core
+
+layout(local_size_x = 64) in;
+
+struct DrawCommand {
+  uint indexCount;
+  uint instanceCount;
+  uint firstIndex;
+  uint baseVertex;
+  uint baseInstance;
+};
+
+struct ChunkSlot {
+  DrawCommand faces[6];
+  int aabbMin[3];
+  int aabbMax[3];
+};
+
+layout(std430, binding = 0) readonly buffer Slots {
+  ChunkSlot slots[];
+};
+
+layout(std430, binding = 1) writeonly buffer Commands {
+  DrawCommand commands[];
+};
+
+layout(std430, binding = 2) buffer Counters {
+  uint drawCount;
+  uint frustumCulled;
+  uint occluded;
+};
+
+layout(binding = 0) uniform sampler2D hiz;
+
+uniform vec4 planes[6];
+uniform ivec3 eye_position_int;
+uniform uint slot_count;
+uniform bool occlusion;
+uniform bool compact;
+uniform mat4 prev_view_proj;
+uniform ivec3 prev_eye_position_int;
+
+bool inFrustum(vec3 relMin, vec3 relMax) {
+  for (int i = 0; i < 6; i++) {
+    vec3 corner = mix(relMin, relMax, greaterThanEqual(planes[i].xyz, vec3(0)));
+    if (dot(planes[i].xyz, corner) + planes[i].w < 0.0) return false;
+  }
+  return true;
+}
+
+// The box is hidden if its nearest depth is behind the farthest depth stored for every
+// pyramid texel its screen rectangle touches. The level is picked so that's at most 2x2 texels.
+// Mirrored on the CPU by `hiz::ndc_bounds`, `hiz::footprint` and `DepthPyramid::occludes`.
+bool occludedByHiZ(vec3 relMin, vec3 relMax) {
+  vec3 ndcMin = vec3(1.0);
+  vec3 ndcMax = vec3(-1.0);
+  for (int i = 0; i < 8; i++) {
+    vec3 corner = mix(relMin, relMax, bvec3(i & 1, i & 2, i & 4));
+    vec4 clip = prev_view_proj * vec4(corner, 1.0);
+    // Boxes reaching behind the camera can't be projected; keep them.
+    if (clip.w <= 0.0) return false;
+    vec3 ndc = clip.xyz / clip.w;
+    ndcMin = min(ndcMin, ndc);
+    ndcMax = max(ndcMax, ndc);
+  }
+  if (ndcMin.z < -1.0) return false;
+
+  vec2 uvMin = clamp(ndcMin.xy * 0.5 + 0.5, 0.0, 1.0);
+  vec2 uvMax = clamp(ndcMax.xy * 0.5 + 0.5, 0.0, 1.0);
+  vec2 extent = (uvMax - uvMin) * vec2(textureSize(hiz, 0));
+  int level = clamp(int(ceil(log2(max(max(extent.x, extent.y), 1.0)))), 0, textureQueryLevels(hiz) - 1);
+
+  ivec2 size = textureSize(hiz, level);
+  ivec2 t0 = clamp(ivec2(uvMin * vec2(size)), ivec2(0), size - 1);
+  ivec2 t1 = clamp(ivec2(uvMax * vec2(size)), ivec2(0), size - 1);
+  float occluderDepth = 0.0;
+  for (int y = t0.y; y <= t1.y; y++) {
+    for (int x = t0.x; x <= t1.x; x++) {
+      occluderDepth = max(occluderDepth, texelFetch(hiz, ivec2(x, y), level).r);
+    }
+  }
+  return ndcMin.z * 0.5 + 0.5 > occluderDepth;
+}
+
+void main() {
+  uint slot = gl_GlobalInvocationID.x;
+  if (slot >= slot_count) return;
+
+  bool facing[6];
+  ivec3 mn = ivec3(slots[slot].aabbMin[0], slots[slot].aabbMin[1], slots[slot].aabbMin[2]);
+  ivec3 mx = ivec3(slots[slot].aabbMax[0], slots[slot].aabbMax[1], slots[slot].aabbMax[2]);
+  ivec3 eye = eye_position_int;
+  facing[0] = eye.y >= mn.y;
+  facing[1] = eye.y < mx.y;
+  facing[2] = eye.x >= mn.x;
+  facing[3] = eye.x < mx.x;
+  facing[4] = eye.z >= mn.z;
+  facing[5] = eye.z < mx.z;
+
+  uint visibleFaces = 0u;
+  bool empty = true;
+  for (int face = 0; face < 6; face++) {
+    bool present = slots[slot].faces[face].instanceCount != 0u;
+    empty = empty && !present;
+    facing[face] = facing[face] && present;
+    visibleFaces += uint(facing[face]);
+  }
+
+  // Free slots and chunks without quads aren't counted.
+  bool visible = !empty;
+  if (visible && !inFrustum(vec3(mn - eye), vec3(mx - eye))) {
+    atomicAdd(frustumCulled, 1u);
+    visible = false;
+  } else if (visible && occlusion && occludedByHiZ(vec3(mn - prev_eye_position_int), vec3(mx - prev_eye_position_int))) {
+    atomicAdd(occluded, 1u);
+    visible = false;
+  }
+  if (!visible) visibleFaces = 0u;
+
+  if (compact) {
+    if (visibleFaces == 0u) return;
+    uint next = atomicAdd(drawCount, visibleFaces);
+    for (int face = 0; face < 6; face++) {
+      if (facing[face]) {
+        commands[next++] = slots[slot].faces[face];
+      }
+    }
+  } else {
+    if (visibleFaces != 0u) atomicAdd(drawCount, visibleFaces);
+    for (int face = 0; face < 6; face++) {
+      DrawCommand command = slots[slot].faces[face];
+      if (!visible || !facing[face]) command.instanceCount = 0u;
+      commands[slot * 6u + uint(face)] = command;
+    }
+  }
+}
+"#;
+
+/// Which faces of a chunk spanning `aabb_min..aabb_max` can face a camera at
+/// `eye_position_int`, in face order (+Y, -Y, +X, -X, +Z, -Z). Matches `facing` in the culling
+/// shader; for whole chunks it's the same as comparing the camera's chunk with the chunk's.
+pub fn facing_faces(eye_position_int: IVec3, aabb_min: IVec3, aabb_max: IVec3) -> [bool; 6] {
+    let eye = eye_position_int;
+    [
+        eye.y >= aabb_min.y,
+        eye.y < aabb_max.y,
+        eye.x >= aabb_min.x,
+        eye.x < aabb_max.x,
+        eye.z >= aabb_min.z,
+        eye.z < aabb_max.z,
+    ]
+}
+
+/// CPU version of the culling shader without occlusion: `None` if the chunk is outside
+/// `frustum` (relative to `eye_position_int`, see `Camera::frustum`), otherwise the faces to
+/// draw as given by [`facing_faces`].
+pub fn cull_chunk(frustum: &Frustum, eye_position_int: IVec3, aabb_min: IVec3, aabb_max: IVec3) -> Option<[bool; 6]> {
+    let eye = eye_position_int;
+    frustum
+        .intersects_aabb((aabb_min - eye).as_vec3(), (aabb_max - eye).as_vec3())
+        .then(|| facing_faces(eye, aabb_min, aabb_max))
+}
+
+/// The six face commands of one chunk and its world-space bounds, as laid out in the slot buffer.
+/// Faces without quads have `instance_count == 0` and are skipped by the shader.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+pub struct ChunkCullSlot {
+    pub faces: [DrawElementsIndirectCommand; 6],
+    pub aabb_min: [i32; 3],
+    pub aabb_max: [i32; 3],
+}
+
+/// Per-dispatch counters, read back with [`GpuCuller::read_stats`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct CullStats {
+    /// Face commands that passed culling; after a compacting dispatch, the number written to
+    /// [`GpuCuller::command_buffer`].
+    pub draw_count: u32,
+    /// Chunks entirely outside the view frustum.
+    pub frustum_culled: u32,
+    /// Chunks inside the frustum but hidden behind the Hi-Z pyramid.
+    pub occluded: u32,
+    _pad: u32,
+}
+
+/// Compute-shader culling of chunk draw commands.
+///
+/// Keeps a persistent GPU buffer with a slot per chunk; [`Self::dispatch`] frustum-, occlusion-
+/// and face-culls every chunk and writes the commands to [`Self::command_buffer`]. Compacted,
+/// only the surviving commands are written, with their number in [`Self::count_buffer`] for
+/// `glMultiDrawElementsIndirectCount`; otherwise all [`Self::max_draw_count`] commands are
+/// written in slot order and the culled ones draw nothing.
+pub struct GpuCuller {
+    gl: Rc<glow::Context>,
+    program: ShaderProgram,
+    u_planes: glow::NativeUniformLocation,
+    u_eye_int: glow::NativeUniformLocation,
+    u_slot_count: glow::NativeUniformLocation,
+    u_occlusion: glow::NativeUniformLocation,
+    u_compact: glow::NativeUniformLocation,
+    u_prev_view_proj: glow::NativeUniformLocation,
+    u_prev_eye_int: glow::NativeUniformLocation,
+
+    slot_buffer: glow::NativeBuffer,
+    command_buffer: glow::NativeBuffer,
+    count_buffer: glow::NativeBuffer,
+    // Number of slots the GPU buffers currently have room for.
+    gpu_capacity: usize,
+    // Whether the last dispatch compacted its output.
+    compacted: bool,
+
+    // CPU mirror of the slot buffer.
+    slots: Vec<ChunkCullSlot>,
+    slot_of: HashMap<u32, u32>,
+    free_slots: Vec<u32>,
+    dirty: BTreeSet<u32>,
+}
+
+impl GpuCuller {
+    pub fn new(gl: &Rc<glow::Context>) -> Result<Self> {
+        let program = ShaderProgram::new_compute(gl, CULL_SRC).context("compile culling shader")?;
+        let u_planes = program.uniform_location("planes").context("missing planes")?;
+        let u_eye_int = program
+            .uniform_location("eye_position_int")
+            .context("missing eye_position_int")?;
+        let u_slot_count = program.uniform_location("slot_count").context("missing slot_count")?;
+        let u_occlusion = program.uniform_location("occlusion").context("missing occlusion")?;
+        let u_compact = program.uniform_location("compact").context("missing compact")?;
+        let u_prev_view_proj = program
+            .uniform_location("prev_view_proj")
+            .context("missing prev_view_proj")?;
+        let u_prev_eye_int = program
+            .uniform_location("prev_eye_position_int")
+            .context("missing prev_eye_position_int")?;
+
+        unsafe {
+            let create = |what| {
+                gl.create_buffer()
+                    .map_err(|e| anyhow!("create {what} buffer failed: {e}"))
+            };
+            let slot_buffer = create("cull slot")?;
+            let command_buffer = create("culled command")?;
+            let count_buffer = create("draw count")?;
+
+            gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(count_buffer));
+            gl.buffer_data_size(
+                glow::SHADER_STORAGE_BUFFER,
+                std::mem::size_of::<CullStats>() as i32,
+                glow::DYNAMIC_COPY,
+            );
+            gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, None);
+
+            Ok(Self {
+                gl: Rc::clone(gl),
+                program,
+                u_planes,
+                u_eye_int,
+                u_slot_count,
+                u_occlusion,
+                u_compact,
+                u_prev_view_proj,
+                u_prev_eye_int,
+                slot_buffer,
+                command_buffer,
+                count_buffer,
+                gpu_capacity: 0,
+                compacted: false,
+                slots: Vec::new(),
+                slot_of: HashMap::new(),
+                free_slots: Vec::new(),
+                dirty: BTreeSet::new(),
+            })
+        }
+    }
+
+    /// Adds or replaces the commands of chunk `key` (any stable id, e.g. the chunk's xyz key).
+    /// The upload happens on the next [`Self::dispatch`].
+    pub fn set_chunk(
+        &mut self,
+        key: u32,
+        faces: &[Option<DrawElementsIndirectCommand>; 6],
+        aabb_min: IVec3,
+        aabb_max: IVec3,
+    ) {
+        let slot = match self.slot_of.get(&key) {
+            Some(&slot) => slot,
+            None => {
+                let slot = self.free_slots.pop().unwrap_or_else(|| {
+                    self.slots.push(ChunkCullSlot::default());
+                    (self.slots.len() - 1) as u32
+                });
+                self.slot_of.insert(key, slot);
+                slot
+            }
+        };
+
+        self.slots[slot as usize] = ChunkCullSlot {
+            faces: faces.map(Option::unwrap_or_default),
+            aabb_min: aabb_min.to_array(),
+            aabb_max: aabb_max.to_array(),
+        };
+        self.dirty.insert(slot);
+    }
+
+    pub fn remove_chunk(&mut self, key: u32) {
+        if let Some(slot) = self.slot_of.remove(&key) {
+            self.slots[slot as usize] = ChunkCullSlot::default();
+            self.free_slots.push(slot);
+            self.dirty.insert(slot);
+        }
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.slot_of.len()
+    }
+
+    /// Upper bound on the number of commands [`Self::dispatch`] can output.
+    pub fn max_draw_count(&self) -> usize {
+        self.slots.len() * 6
+    }
+
+    /// Commands written by the last dispatch, compacted or in slot order.
+    pub fn command_buffer(&self) -> glow::NativeBuffer {
+        self.command_buffer
+    }
+
+    /// Whether the last dispatch compacted [`Self::command_buffer`].
+    pub fn compacted(&self) -> bool {
+        self.compacted
+    }
+
+    /// Holds [`CullStats`]; its first `u32` is the number of commands in [`Self::command_buffer`].
+    pub fn count_buffer(&self) -> glow::NativeBuffer {
+        self.count_buffer
+    }
+
+    /// Reads the counters of the last dispatch back to the CPU. This waits for the dispatch to
+    /// finish, so it's only meant for occasional stats.
+    pub fn read_stats(&self) -> CullStats {
+        let mut stats = CullStats::default();
+        unsafe {
+            self.gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(self.count_buffer));
+            self.gl
+                .get_buffer_sub_data(glow::SHADER_STORAGE_BUFFER, 0, bytemuck::bytes_of_mut(&mut stats));
+            self.gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, None);
+        }
+        stats
+    }
+
+    /// Uploads pending slot changes and culls every chunk against `frustum`, which must be
+    /// relative to `eye_position_int` (see `Camera::frustum`). With `occlusion`, chunks are also
+    /// tested against that frame's depth pyramid. `compact` should be set only when drawing with
+    /// `glMultiDrawElementsIndirectCount`, which is the only path that can use the GPU-side count.
+    pub fn dispatch(&mut self, frustum: &Frustum, eye_position_int: IVec3, occlusion: Option<HiZFrame>, compact: bool) {
+        self.upload_slots();
+        self.compacted = compact;
+        if self.slots.is_empty() {
+            return;
+        }
+
+        unsafe {
+            self.gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(self.count_buffer));
+            self.gl.buffer_sub_data_u8_slice(
+                glow::SHADER_STORAGE_BUFFER,
+                0,
+                bytemuck::bytes_of(&CullStats::default()),
+            );
+            self.gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, None);
+
+            self.program.bind();
+            self.program.set_vec4_array(&self.u_planes, &frustum.planes);
+            self.program
+                .set_ivec3(&self.u_eye_int, eye_position_int.x, eye_position_int.y, eye_position_int.z);
+            self.program.set_uint(&self.u_slot_count, self.slots.len() as u32);
+            self.program.set_uint(&self.u_occlusion, occlusion.is_some() as u32);
+            self.program.set_uint(&self.u_compact, compact as u32);
+            if let Some(frame) = occlusion {
+                self.program.set_mat4(&self.u_prev_view_proj, &frame.view_projection);
+                let eye = frame.eye_position_int;
+                self.program.set_ivec3(&self.u_prev_eye_int, eye.x, eye.y, eye.z);
+                self.gl.active_texture(glow::TEXTURE0);
+                self.gl.bind_texture(glow::TEXTURE_2D, Some(frame.pyramid));
+            }
+
+            self.gl
+                .bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 0, Some(self.slot_buffer));
+            self.gl
+                .bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 1, Some(self.command_buffer));
+            self.gl
+                .bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 2, Some(self.count_buffer));
+
+            self.gl
+                .dispatch_compute((self.slots.len() as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+            self.gl.memory_barrier(
+                glow::COMMAND_BARRIER_BIT | glow::SHADER_STORAGE_BARRIER_BIT | glow::BUFFER_UPDATE_BARRIER_BIT,
+            );
+
+            for binding in 0..3 {
+                self.gl
+                    .bind_buffer_base(glow::SHADER_STORAGE_BUFFER, binding, None);
+            }
+            self.gl.bind_texture(glow::TEXTURE_2D, None);
+            self.gl.use_program(None);
+        }
+    }
+
+    fn upload_slots(&mut self) {
+        let slot_size = std::mem::size_of::<ChunkCullSlot>();
+        unsafe {
+            if self.slots.len() > self.gpu_capacity {
+                // Grow both buffers and re-upload everything.
+                self.gpu_capacity = self.slots.len().next_power_of_two();
+                self.gl
+                    .bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(self.command_buffer));
+                self.gl.buffer_data_size(
+                    glow::SHADER_STORAGE_BUFFER,
+                    (self.gpu_capacity * 6 * std::mem::size_of::<DrawElementsIndirectCommand>()) as i32,
+                    glow::DYNAMIC_COPY,
+                );
+
+                let mut contents = vec![ChunkCullSlot::default(); self.gpu_capacity];
+                contents[..self.slots.len()].copy_from_slice(&self.slots);
+                self.gl
+                    .bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(self.slot_buffer));
+                self.gl.buffer_data_u8_slice(
+                    glow::SHADER_STORAGE_BUFFER,
+                    bytemuck::cast_slice(&contents),
+                    glow::DYNAMIC_DRAW,
+                );
+            } else if !self.dirty.is_empty() {
+                self.gl
+                    .bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(self.slot_buffer));
+                for &slot in &self.dirty {
+                    self.gl.buffer_sub_data_u8_slice(
+                        glow::SHADER_STORAGE_BUFFER,
+                        (slot as usize * slot_size) as i32,
+                        bytemuck::bytes_of(&self.slots[slot as usize]),
+                    );
+                }
+            }
+            self.gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, None);
+        }
+        self.dirty.clear();
+    }
+}
+
+impl Drop for GpuCuller {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_buffer(self.slot_buffer);
+            self.gl.delete_buffer(self.command_buffer);
+            self.gl.delete_buffer(self.count_buffer);
+        }
+    }
+}
@@ -0,0 +1,492 @@
+use crate::misc::shader::ShaderProgram;
+use anyhow::{anyhow, ensure, Context, Result};
+use glam::{BVec3, IVec3, Mat4, UVec2, Vec2, Vec3};
+use glow::HasContext;
+use std::rc::Rc;
+
+const WORKGROUP_SIZE: u32 = 8;
+
+// Level 0 of the pyramid is a straight copy of the scene depth.
+const COPY_SRC: &str = r#"#version 460 core
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0) uniform sampler2D depth_texture;
+layout(r32f, binding = 1) uniform writeonly image2D dst_level;
+
+void main() {
+  ivec2 dst = ivec2(gl_GlobalInvocationID.xy);
+  if (any(greaterThanEqual(dst, imageSize(dst_level)))) return;
+  imageStore(dst_level, dst, vec4(texelFetch(depth_texture, dst, 0).r));
+}
+"#;
+
+// Multisampled variant of `COPY_SRC`: each texel keeps its farthest sample, so a pixel on a
+// silhouette only hides what is behind all of its samples.
+const COPY_MULTISAMPLE_SRC: &str = r#"#version 460 core
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0) uniform sampler2DMS depth_texture;
+layout(r32f, binding = 1) uniform writeonly image2D dst_level;
+
+void main() {
+  ivec2 dst = ivec2(gl_GlobalInvocationID.xy);
+  if (any(greaterThanEqual(dst, imageSize(dst_level)))) return;
+
+  float depth = 0.0;
+  for (int i = 0; i < textureSamples(depth_texture); i++) {
+    depth = max(depth, texelFetch(depth_texture, dst, i).r);
+  }
+  imageStore(dst_level, dst, vec4(depth));
+}
+"#;
+
+// Every other level keeps the farthest depth of the texels it covers, so a box that is nearer
+// than a texel's value may be visible and one that is farther is hidden everywhere under it.
+const DOWNSAMPLE_SRC: &str = r#"#version 460 core
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(r32f, binding = 0) uniform readonly image2D src_level;
+layout(r32f, binding = 1) uniform writeonly image2D dst_level;
+
+void main() {
+  ivec2 dst = ivec2(gl_GlobalInvocationID.xy);
+  ivec2 dstSize = imageSize(dst_level);
+  if (any(greaterThanEqual(dst, dstSize))) return;
+
+  // With an odd source size the last row/column also covers the leftover source texel; see
+  // `downsample_extent`.
+  ivec2 srcSize = imageSize(src_level);
+  ivec2 extent = ivec2(2) + ivec2(equal(dst, dstSize - 1)) * (srcSize & 1);
+
+  float depth = 0.0;
+  for (int y = 0; y < extent.y; y++) {
+    for (int x = 0; x < extent.x; x++) {
+      ivec2 src = min(dst * 2 + ivec2(x, y), srcSize - 1);
+      depth = max(depth, imageLoad(src_level, src).r);
+    }
+  }
+  imageStore(dst_level, dst, vec4(depth));
+}
+"#;
+
+/// Number of pyramid levels for a `width` x `height` depth buffer, down to 1x1.
+pub fn level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Size of pyramid `level` for a `size` depth buffer; each level halves, rounding down.
+pub fn level_size(size: UVec2, level: u32) -> UVec2 {
+    (size >> level).max(UVec2::ONE)
+}
+
+/// Source texels (along one axis) that destination texel `dst` of a `dst_size` level reduces
+/// from a `src_size` level. Matches `extent` in the downsample shader: the last texel also
+/// takes the leftover one when `src_size` is odd, so no source depth is dropped.
+pub fn downsample_extent(dst: u32, dst_size: u32, src_size: u32) -> u32 {
+    2 + (dst == dst_size - 1) as u32 * (src_size & 1)
+}
+
+/// Depth buffer value for an NDC depth, with the default `glDepthRange(0, 1)`.
+pub fn ndc_to_depth(ndc_z: f32) -> f32 {
+    ndc_z * 0.5 + 0.5
+}
+
+/// Pyramid level and inclusive texel rectangle read for the screen rectangle `uv_min..uv_max`
+/// (0..1) of a pyramid over a `size` depth buffer. Matches `occludedByHiZ` in the culling
+/// shader: the level is the first where the rectangle spans at most one texel, so at most 2x2
+/// texels are read.
+pub fn footprint(size: UVec2, levels: u32, uv_min: Vec2, uv_max: Vec2) -> (u32, UVec2, UVec2) {
+    let extent = (uv_max - uv_min) * size.as_vec2();
+    let level = (extent.max_element().max(1.0).log2().ceil() as i32).clamp(0, levels as i32 - 1) as u32;
+    let level_size = level_size(size, level);
+    let texel = |uv: Vec2| (uv * level_size.as_vec2()).as_uvec2().min(level_size - 1);
+    (level, texel(uv_min), texel(uv_max))
+}
+
+/// NDC bounds of a box relative to the eye position `view_projection` was built for, or `None`
+/// if the box reaches behind the camera or the near plane and can't be tested.
+pub fn ndc_bounds(view_projection: Mat4, rel_min: Vec3, rel_max: Vec3) -> Option<(Vec3, Vec3)> {
+    let (mut ndc_min, mut ndc_max) = (Vec3::ONE, -Vec3::ONE);
+    for i in 0..8 {
+        let corner = Vec3::select(BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0), rel_max, rel_min);
+        let clip = view_projection * corner.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        ndc_min = ndc_min.min(ndc);
+        ndc_max = ndc_max.max(ndc);
+    }
+    (ndc_min.z >= -1.0).then_some((ndc_min, ndc_max))
+}
+
+/// CPU reference of the depth pyramid built by [`HiZ::end_frame`], with the occlusion test the
+/// culling shader runs against it. The viewer doesn't use it (reading depth back each frame would
+/// stall); it documents and tests what the shaders compute.
+#[derive(Clone, Debug)]
+pub struct DepthPyramid {
+    size: UVec2,
+    levels: Vec<Vec<f32>>,
+}
+
+impl DepthPyramid {
+    /// Reduces a row-major `width` x `height` depth buffer, keeping the farthest depth per texel.
+    pub fn build(width: u32, height: u32, depth: &[f32]) -> Self {
+        assert_eq!(depth.len(), (width * height) as usize, "depth buffer size");
+        let size = UVec2::new(width, height);
+        let mut levels = vec![depth.to_vec()];
+        for level in 1..level_count(width, height) {
+            let (src_size, dst_size) = (level_size(size, level - 1), level_size(size, level));
+            let src = &levels[level as usize - 1];
+            let mut dst = vec![0.0f32; (dst_size.x * dst_size.y) as usize];
+            for y in 0..dst_size.y {
+                for x in 0..dst_size.x {
+                    let mut texel = 0.0f32;
+                    for sy in 0..downsample_extent(y, dst_size.y, src_size.y) {
+                        for sx in 0..downsample_extent(x, dst_size.x, src_size.x) {
+                            let (sx, sy) = ((x * 2 + sx).min(src_size.x - 1), (y * 2 + sy).min(src_size.y - 1));
+                            texel = texel.max(src[(sy * src_size.x + sx) as usize]);
+                        }
+                    }
+                    dst[(y * dst_size.x + x) as usize] = texel;
+                }
+            }
+            levels.push(dst);
+        }
+        Self { size, levels }
+    }
+
+    pub fn level_count(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    pub fn level_size(&self, level: u32) -> UVec2 {
+        level_size(self.size, level)
+    }
+
+    pub fn depth(&self, level: u32, texel: UVec2) -> f32 {
+        self.levels[level as usize][(texel.y * self.level_size(level).x + texel.x) as usize]
+    }
+
+    /// Whether a box (relative to the eye position `view_projection` was built for) is behind
+    /// everything stored in the pyramid under its screen rectangle.
+    pub fn occludes(&self, view_projection: Mat4, rel_min: Vec3, rel_max: Vec3) -> bool {
+        let Some((ndc_min, ndc_max)) = ndc_bounds(view_projection, rel_min, rel_max) else {
+            return false;
+        };
+        let uv = |ndc: Vec3| (ndc.truncate() * 0.5 + 0.5).clamp(Vec2::ZERO, Vec2::ONE);
+        let (level, t0, t1) = footprint(self.size, self.level_count(), uv(ndc_min), uv(ndc_max));
+        let mut occluder_depth = 0.0f32;
+        for y in t0.y..=t1.y {
+            for x in t0.x..=t1.x {
+                occluder_depth = occluder_depth.max(self.depth(level, UVec2::new(x, y)));
+            }
+        }
+        ndc_to_depth(ndc_min.z) > occluder_depth
+    }
+}
+
+/// What [`HiZ::end_frame`] recorded: the depth pyramid and the camera it was rendered with.
+#[derive(Copy, Clone, Debug)]
+pub struct HiZFrame {
+    pub pyramid: glow::NativeTexture,
+    /// Camera-relative view-projection, as from `Camera::projection * Camera::get_view_matrix()`.
+    pub view_projection: Mat4,
+    pub eye_position_int: IVec3,
+}
+
+/// Hierarchical-Z buffer for occlusion culling.
+///
+/// While it's in use the scene is drawn into an offscreen framebuffer (between
+/// [`Self::begin_frame`] and [`Self::end_frame`]) whose colour is then blitted to the window and
+/// whose depth is reduced into a max-depth mip pyramid for the next frame's culling pass. The
+/// framebuffer has the window's sample count; multisampled colour is resolved on the way to the
+/// window and the pyramid starts from the farthest sample of each pixel.
+///
+/// Culling tests against the previous frame's depth and nothing re-tests the chunks it hid, so
+/// geometry that becomes visible (turning quickly, or an occluder being removed) can show up one
+/// frame late.
+///
+/// The pyramid stays on the GPU and is only read by [`GpuCuller`](super::gpu_culling::GpuCuller),
+/// so occlusion culling needs GPU culling; the CPU culling path would have to read depth back
+/// every frame. [`DepthPyramid`] is a CPU reference of the same computation.
+pub struct HiZ {
+    gl: Rc<glow::Context>,
+    copy_program: ShaderProgram,
+    downsample_program: ShaderProgram,
+
+    samples: i32,
+    framebuffer: glow::NativeFramebuffer,
+    color: glow::NativeRenderbuffer,
+    depth: glow::NativeTexture,
+    // Single-sample target the multisampled colour is resolved into before it goes to the window.
+    resolve: Option<(glow::NativeFramebuffer, glow::NativeRenderbuffer)>,
+    pyramid: glow::NativeTexture,
+    width: u32,
+    height: u32,
+    levels: u32,
+
+    last_frame: Option<HiZFrame>,
+}
+
+impl HiZ {
+    /// `samples` should match the window's framebuffer; 0 or 1 means no multisampling.
+    pub fn new(gl: &Rc<glow::Context>, width: u32, height: u32, samples: u8) -> Result<Self> {
+        let samples = samples.max(1) as i32;
+        let copy_src = if samples > 1 { COPY_MULTISAMPLE_SRC } else { COPY_SRC };
+        let copy_program = ShaderProgram::new_compute(gl, copy_src).context("compile Hi-Z copy shader")?;
+        let downsample_program =
+            ShaderProgram::new_compute(gl, DOWNSAMPLE_SRC).context("compile Hi-Z downsample shader")?;
+
+        unsafe {
+            let framebuffer = gl
+                .create_framebuffer()
+                .map_err(|e| anyhow!("create Hi-Z framebuffer failed: {e}"))?;
+            let color = gl
+                .create_renderbuffer()
+                .map_err(|e| anyhow!("create Hi-Z colour buffer failed: {e}"))?;
+            let depth = gl
+                .create_texture()
+                .map_err(|e| anyhow!("create Hi-Z depth texture failed: {e}"))?;
+            let pyramid = gl
+                .create_texture()
+                .map_err(|e| anyhow!("create Hi-Z pyramid failed: {e}"))?;
+            let resolve = if samples > 1 {
+                let framebuffer = gl
+                    .create_framebuffer()
+                    .map_err(|e| anyhow!("create Hi-Z resolve framebuffer failed: {e}"))?;
+                let color = gl
+                    .create_renderbuffer()
+                    .map_err(|e| anyhow!("create Hi-Z resolve colour buffer failed: {e}"))?;
+                Some((framebuffer, color))
+            } else {
+                None
+            };
+
+            let mut hiz = Self {
+                gl: Rc::clone(gl),
+                copy_program,
+                downsample_program,
+                samples,
+                framebuffer,
+                color,
+                depth,
+                resolve,
+                pyramid,
+                width: 0,
+                height: 0,
+                levels: 0,
+                last_frame: None,
+            };
+            hiz.resize(width, height)?;
+            Ok(hiz)
+        }
+    }
+
+    /// Reallocates the framebuffer and pyramid for a new window size, dropping the last frame.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        let (width, height) = (width.max(1), height.max(1));
+        self.last_frame = None;
+        if (width, height) == (self.width, self.height) {
+            return Ok(());
+        }
+
+        let gl = &self.gl;
+        unsafe {
+            // Texture storage is immutable, so size changes need fresh textures.
+            gl.delete_texture(self.depth);
+            gl.delete_texture(self.pyramid);
+            self.depth = gl
+                .create_texture()
+                .map_err(|e| anyhow!("create Hi-Z depth texture failed: {e}"))?;
+            self.pyramid = gl
+                .create_texture()
+                .map_err(|e| anyhow!("create Hi-Z pyramid failed: {e}"))?;
+
+            let levels = level_count(width, height);
+            let depth_target = self.depth_target();
+            gl.bind_texture(depth_target, Some(self.depth));
+            if self.samples > 1 {
+                // Multisampled textures have no sampler state; the copy shader uses texelFetch.
+                gl.tex_storage_2d_multisample(
+                    depth_target,
+                    self.samples,
+                    glow::DEPTH_COMPONENT32F,
+                    width as i32,
+                    height as i32,
+                    true,
+                );
+            } else {
+                gl.tex_storage_2d(depth_target, 1, glow::DEPTH_COMPONENT32F, width as i32, height as i32);
+                gl.tex_parameter_i32(depth_target, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+                gl.tex_parameter_i32(depth_target, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+                gl.tex_parameter_i32(depth_target, glow::TEXTURE_COMPARE_MODE, glow::NONE as i32);
+            }
+            gl.bind_texture(depth_target, None);
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.pyramid));
+            gl.tex_storage_2d(glow::TEXTURE_2D, levels as i32, glow::R32F, width as i32, height as i32);
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST_MIPMAP_NEAREST as i32,
+            );
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(self.color));
+            gl.renderbuffer_storage_multisample(
+                glow::RENDERBUFFER,
+                self.samples,
+                glow::RGBA8,
+                width as i32,
+                height as i32,
+            );
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::RENDERBUFFER,
+                Some(self.color),
+            );
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_ATTACHMENT,
+                depth_target,
+                Some(self.depth),
+                0,
+            );
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            ensure!(
+                status == glow::FRAMEBUFFER_COMPLETE,
+                "Hi-Z framebuffer incomplete: {status:#X}"
+            );
+
+            if let Some((framebuffer, color)) = self.resolve {
+                gl.bind_renderbuffer(glow::RENDERBUFFER, Some(color));
+                gl.renderbuffer_storage(glow::RENDERBUFFER, glow::RGBA8, width as i32, height as i32);
+                gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+                gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+                gl.framebuffer_renderbuffer(
+                    glow::FRAMEBUFFER,
+                    glow::COLOR_ATTACHMENT0,
+                    glow::RENDERBUFFER,
+                    Some(color),
+                );
+                let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+                gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+                ensure!(
+                    status == glow::FRAMEBUFFER_COMPLETE,
+                    "Hi-Z resolve framebuffer incomplete: {status:#X}"
+                );
+            }
+
+            self.width = width;
+            self.height = height;
+            self.levels = levels;
+        }
+        Ok(())
+    }
+
+    fn depth_target(&self) -> u32 {
+        if self.samples > 1 { glow::TEXTURE_2D_MULTISAMPLE } else { glow::TEXTURE_2D }
+    }
+
+    /// The pyramid built at the end of the previous frame, if it's still valid.
+    pub fn last_frame(&self) -> Option<HiZFrame> {
+        self.last_frame
+    }
+
+    /// Forgets the last frame, e.g. when occlusion culling is switched off and the pyramid stops
+    /// being updated.
+    pub fn invalidate(&mut self) {
+        self.last_frame = None;
+    }
+
+    /// Redirects drawing to the offscreen framebuffer. Call before clearing.
+    pub fn begin_frame(&self) {
+        unsafe { self.gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer)) };
+    }
+
+    /// Copies the frame to the window and builds the depth pyramid from it. The camera arguments
+    /// describe the frame just drawn and are handed back by [`Self::last_frame`].
+    pub fn end_frame(&mut self, view_projection: Mat4, eye_position_int: IVec3) {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let gl = &self.gl;
+        unsafe {
+            let blit_color = |from, to| {
+                gl.bind_framebuffer(glow::READ_FRAMEBUFFER, from);
+                gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, to);
+                gl.blit_framebuffer(0, 0, width, height, 0, 0, width, height, glow::COLOR_BUFFER_BIT, glow::NEAREST);
+            };
+            // The window may be multisampled with a different colour format, so the offscreen
+            // samples are resolved into a plain buffer first.
+            match self.resolve {
+                Some((resolve, _)) => {
+                    blit_color(Some(self.framebuffer), Some(resolve));
+                    blit_color(Some(resolve), None);
+                }
+                None => blit_color(Some(self.framebuffer), None),
+            }
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+            let depth_target = self.depth_target();
+            self.copy_program.bind();
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(depth_target, Some(self.depth));
+            gl.bind_image_texture(1, Some(self.pyramid), 0, false, 0, glow::WRITE_ONLY, glow::R32F);
+            gl.dispatch_compute(
+                self.width.div_ceil(WORKGROUP_SIZE),
+                self.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+            gl.bind_texture(depth_target, None);
+
+            self.downsample_program.bind();
+            for level in 1..self.levels {
+                gl.memory_barrier(glow::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+                let level_size = level_size(UVec2::new(self.width, self.height), level);
+                gl.bind_image_texture(0, Some(self.pyramid), level as i32 - 1, false, 0, glow::READ_ONLY, glow::R32F);
+                gl.bind_image_texture(1, Some(self.pyramid), level as i32, false, 0, glow::WRITE_ONLY, glow::R32F);
+                gl.dispatch_compute(
+                    level_size.x.div_ceil(WORKGROUP_SIZE),
+                    level_size.y.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+            }
+            gl.memory_barrier(glow::TEXTURE_FETCH_BARRIER_BIT);
+
+            gl.bind_image_texture(0, None, 0, false, 0, glow::READ_ONLY, glow::R32F);
+            gl.bind_image_texture(1, None, 0, false, 0, glow::WRITE_ONLY, glow::R32F);
+            gl.use_program(None);
+        }
+
+        self.last_frame = Some(HiZFrame {
+            pyramid: self.pyramid,
+            view_projection,
+            eye_position_int,
+        });
+    }
+}
+
+impl Drop for HiZ {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_framebuffer(self.framebuffer);
+            self.gl.delete_renderbuffer(self.color);
+            self.gl.delete_texture(self.depth);
+            if let Some((framebuffer, color)) = self.resolve {
+                self.gl.delete_framebuffer(framebuffer);
+                self.gl.delete_renderbuffer(color);
+            }
+            self.gl.delete_texture(self.pyramid);
+        }
+    }
+}
@@ -1 +1,5 @@
+pub mod allocator;
 pub mod chunk_renderer;
+pub mod gpu_culling;
+pub mod hiz;
+pub mod multi_draw;
@@ -0,0 +1,110 @@
+use glow::HasContext;
+use std::ffi::{c_void, CStr};
+
+// glow 0.16 does not expose the multi-draw entry points, so they are loaded by hand.
+type MultiDrawElementsIndirectFn =
+    unsafe extern "system" fn(mode: u32, ty: u32, indirect: *const c_void, draw_count: i32, stride: i32);
+type MultiDrawElementsIndirectCountFn = unsafe extern "system" fn(
+    mode: u32,
+    ty: u32,
+    indirect: *const c_void,
+    draw_count: isize,
+    max_draw_count: i32,
+    stride: i32,
+);
+
+/// How [`super::chunk_renderer::ChunkRenderer`] submits its indirect commands.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DrawPath {
+    /// One `glDrawElementsIndirect` per command.
+    PerCommand,
+    /// A single `glMultiDrawElementsIndirect` (GL 4.3 / `GL_ARB_multi_draw_indirect`).
+    MultiDraw,
+    /// `glMultiDrawElementsIndirectCount`, with the draw count read from a `GL_PARAMETER_BUFFER`
+    /// (GL 4.6 / `GL_ARB_indirect_parameters`). Draws whose count is known on the CPU still use
+    /// `glMultiDrawElementsIndirect`, so this path needs both entry points.
+    MultiDrawCount,
+}
+
+/// Multi-draw-indirect entry points that the current context supports.
+pub struct MultiDraw {
+    draw: Option<MultiDrawElementsIndirectFn>,
+    draw_count: Option<MultiDrawElementsIndirectCountFn>,
+}
+
+impl MultiDraw {
+    /// Checks the context version/extensions and loads the matching functions. An entry point is
+    /// only used if it is advertised *and* the loader returns a non-null pointer.
+    pub fn load(gl: &glow::Context, mut get_proc_address: impl FnMut(&CStr) -> *const c_void) -> Self {
+        let version = gl.version();
+        let at_least = |major, minor| !version.is_embedded && (version.major, version.minor) >= (major, minor);
+        let extensions = gl.supported_extensions();
+
+        let mut load = |name: &CStr| {
+            let ptr = get_proc_address(name);
+            (!ptr.is_null()).then_some(ptr)
+        };
+
+        let draw = (at_least(4, 3) || extensions.contains("GL_ARB_multi_draw_indirect"))
+            .then(|| load(c"glMultiDrawElementsIndirect"))
+            .flatten()
+            .map(|ptr| unsafe { std::mem::transmute::<*const c_void, MultiDrawElementsIndirectFn>(ptr) });
+
+        let draw_count = if at_least(4, 6) {
+            load(c"glMultiDrawElementsIndirectCount")
+        } else if extensions.contains("GL_ARB_indirect_parameters") {
+            load(c"glMultiDrawElementsIndirectCountARB")
+        } else {
+            None
+        }
+        .map(|ptr| unsafe { std::mem::transmute::<*const c_void, MultiDrawElementsIndirectCountFn>(ptr) });
+
+        Self { draw, draw_count }
+    }
+
+    /// The fastest supported path.
+    pub fn best_path(&self) -> DrawPath {
+        [DrawPath::MultiDrawCount, DrawPath::MultiDraw]
+            .into_iter()
+            .find(|&path| self.supports(path))
+            .unwrap_or(DrawPath::PerCommand)
+    }
+
+    pub fn supports(&self, path: DrawPath) -> bool {
+        match path {
+            DrawPath::PerCommand => true,
+            DrawPath::MultiDraw => self.draw.is_some(),
+            DrawPath::MultiDrawCount => self.draw.is_some() && self.draw_count.is_some(),
+        }
+    }
+
+    /// Draws `draw_count` tightly packed commands starting at byte `offset` of the bound
+    /// `GL_DRAW_INDIRECT_BUFFER`.
+    ///
+    /// # Safety
+    /// The GL context must be current and [`Self::supports`] `MultiDraw` (or `MultiDrawCount`)
+    /// must hold.
+    pub unsafe fn draw_elements_indirect(&self, mode: u32, ty: u32, offset: usize, draw_count: i32) {
+        let draw = self.draw.expect("glMultiDrawElementsIndirect not loaded");
+        unsafe { draw(mode, ty, offset as *const c_void, draw_count, 0) };
+    }
+
+    /// Like [`Self::draw_elements_indirect`], but the number of commands is read from byte
+    /// `count_offset` of the bound `GL_PARAMETER_BUFFER`, capped at `max_draw_count`.
+    ///
+    /// # Safety
+    /// The GL context must be current and [`Self::supports`] `MultiDrawCount` must hold.
+    pub unsafe fn draw_elements_indirect_count(
+        &self,
+        mode: u32,
+        ty: u32,
+        offset: usize,
+        count_offset: usize,
+        max_draw_count: i32,
+    ) {
+        let draw_count = self
+            .draw_count
+            .expect("glMultiDrawElementsIndirectCount not loaded");
+        unsafe { draw_count(mode, ty, offset as *const c_void, count_offset as isize, max_draw_count, 0) };
+    }
+}
@@ -0,0 +1,329 @@
+use crate::data::mapped_level::MappedLevel;
+use crate::mesher::{ChunkMesh, Lod, MeshData};
+use crate::world::{mesh_assembled, unpad_chunk, World};
+use crate::{get_xyz_key, parse_xyz_key, CS_P2, CS_P3, MAX_CHUNK};
+use anyhow::{anyhow, Context, Result};
+use glam::{IVec3, Vec3};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StreamingConfig {
+    /// Chunks whose centre is within this many chunks of the camera are loaded.
+    pub view_radius: f32,
+    /// How far past `view_radius` a chunk may get before it's unloaded, so chunks on the edge
+    /// don't bounce in and out as the camera moves back and forth.
+    pub unload_margin: f32,
+    /// Maximum number of chunks being decoded at once.
+    pub max_in_flight: usize,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            view_radius: 24.0,
+            unload_margin: 2.0,
+            max_in_flight: 64,
+        }
+    }
+}
+
+/// What changed in the world during [`ChunkStreamer::update`].
+#[derive(Debug, Default)]
+pub struct StreamingUpdate {
+    /// Chunks inserted into the world; they (and their stored neighbours) are now dirty.
+    pub loaded: Vec<u32>,
+    /// Chunks removed from the world; their meshes should be dropped.
+    pub unloaded: Vec<u32>,
+    /// Chunks that failed to decode. They are not retried.
+    pub errors: Vec<anyhow::Error>,
+}
+
+/// Load order of a chunk, lower first: its distance from the camera, stretched up to 3x for
+/// chunks behind it. Positions are in chunks.
+pub fn load_priority(camera_chunk: Vec3, front: Vec3, chunk_pos: IVec3) -> f32 {
+    let to_chunk = chunk_pos.as_vec3() + 0.5 - camera_chunk;
+    let distance = to_chunk.length();
+    if distance <= f32::EPSILON {
+        return 0.0;
+    }
+    let facing = to_chunk.dot(front) / distance;
+    distance * (2.0 - facing)
+}
+
+/// How far (in chunks) the camera may move before [`ChunkStreamer::update`] scans for chunks to
+/// load again. Scans reach this much past the view radius so nothing is missed in between.
+pub const RESCAN_DISTANCE: f32 = 0.25;
+
+type Decoded = (u32, Result<Box<[u8]>>);
+
+/// Keeps the chunks of a level that are near the camera loaded in a [`World`].
+///
+/// Chunks are decoded on the rayon pool and inserted by [`Self::update`], which also unloads the
+/// ones that fell out of range. Chunks the user edited (see [`Self::mark_edited`]) are kept in
+/// memory while unloaded and come back with their edits instead of being decoded again.
+pub struct ChunkStreamer {
+    level: Arc<MappedLevel>,
+    config: StreamingConfig,
+    sender: Sender<Decoded>,
+    receiver: Receiver<Decoded>,
+    in_flight: HashSet<u32>,
+    failed: HashSet<u32>,
+    // Edited chunks; `Some` holds the voxels while the chunk is unloaded.
+    edited: HashMap<u32, Option<Box<[u8]>>>,
+    // Camera position (in chunks) of the last scan for chunks to load, unless chunks were left
+    // waiting.
+    scanned_from: Option<Vec3>,
+}
+
+impl ChunkStreamer {
+    pub fn new(level: Arc<MappedLevel>, config: StreamingConfig) -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            level,
+            config,
+            sender,
+            receiver,
+            in_flight: HashSet::new(),
+            failed: HashSet::new(),
+            edited: HashMap::new(),
+            scanned_from: None,
+        }
+    }
+
+    pub fn config(&self) -> &StreamingConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: StreamingConfig) {
+        self.config = config;
+        self.scanned_from = None;
+    }
+
+    /// Number of chunks currently being decoded.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Records that the chunk at `key` was changed in the world, so its voxels are kept rather
+    /// than reloaded from the level if it gets unloaded.
+    pub fn mark_edited(&mut self, key: u32) {
+        self.edited.entry(key).or_insert(None);
+    }
+
+    /// Whether voxels in chunk `key` can be edited now: true if it's loaded or there's nothing to
+    /// stream in for it. Editing a chunk that is still to be loaded would create it empty in the
+    /// world, and the loaded chunk would then be dropped in favour of the edited one.
+    pub fn can_edit(&self, world: &World, key: u32) -> bool {
+        world.chunk(key).is_some() || !self.has_source(key)
+    }
+
+    fn has_source(&self, key: u32) -> bool {
+        matches!(self.edited.get(&key), Some(Some(_))) || self.level.chunk(key).is_some()
+    }
+
+    /// Inserts the chunks decoded since the last call, unloads chunks out of range and queues
+    /// the nearest missing ones (in [`load_priority`] order) for decoding. `camera_chunk` is the
+    /// camera position in chunks (world position / `CS`).
+    pub fn update(&mut self, world: &mut World, camera_chunk: Vec3, front: Vec3) -> StreamingUpdate {
+        let mut update = StreamingUpdate::default();
+        // Never unload what a scan may still request.
+        let keep_radius = self.config.view_radius + self.config.unload_margin.max(RESCAN_DISTANCE);
+        let distance = |key: u32| {
+            let (x, y, z) = parse_xyz_key(key);
+            (Vec3::new(x as f32, y as f32, z as f32) + 0.5 - camera_chunk).length()
+        };
+
+        while let Ok((key, result)) = self.receiver.try_recv() {
+            self.in_flight.remove(&key);
+            match result {
+                // Skip chunks that went out of range meanwhile, or were edited into existence.
+                Ok(voxels) if distance(key) <= keep_radius && world.chunk(key).is_none() => {
+                    world.insert_chunk(key, voxels);
+                    update.loaded.push(key);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    self.failed.insert(key);
+                    update.errors.push(e);
+                }
+            }
+        }
+
+        let out_of_range: Vec<u32> = world.keys().filter(|&key| distance(key) > keep_radius).collect();
+        for key in out_of_range {
+            let voxels = world.remove_chunk(key).expect("key comes from the world");
+            if let Some(stash) = self.edited.get_mut(&key) {
+                *stash = Some(voxels);
+            }
+            update.unloaded.push(key);
+        }
+
+        self.request_chunks(world, camera_chunk, front, &mut update);
+        update
+    }
+
+    fn request_chunks(&mut self, world: &mut World, camera_chunk: Vec3, front: Vec3, update: &mut StreamingUpdate) {
+        let free = self.config.max_in_flight.saturating_sub(self.in_flight.len());
+        let moved = self.scanned_from.is_none_or(|from| from.distance(camera_chunk) > RESCAN_DISTANCE);
+        if free == 0 || !moved {
+            return;
+        }
+
+        let centre = camera_chunk.floor().as_ivec3();
+        let radius = self.config.view_radius + RESCAN_DISTANCE;
+        let reach = radius.ceil() as i32;
+        let mut candidates = Vec::new();
+        for z in (centre.z - reach).max(0)..=(centre.z + reach).min(MAX_CHUNK as i32) {
+            for y in (centre.y - reach).max(0)..=(centre.y + reach).min(MAX_CHUNK as i32) {
+                for x in (centre.x - reach).max(0)..=(centre.x + reach).min(MAX_CHUNK as i32) {
+                    let chunk_pos = IVec3::new(x, y, z);
+                    if (chunk_pos.as_vec3() + 0.5 - camera_chunk).length() > radius {
+                        continue;
+                    }
+                    let key = get_xyz_key(x as u8, y as u8, z as u8);
+                    if world.chunk(key).is_some()
+                        || self.in_flight.contains(&key)
+                        || self.failed.contains(&key)
+                        || !self.has_source(key)
+                    {
+                        continue;
+                    }
+                    candidates.push((load_priority(camera_chunk, front, chunk_pos), key));
+                }
+            }
+        }
+        candidates.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Scan again next time if some chunks had to wait for a free slot.
+        self.scanned_from = (candidates.len() <= free).then_some(camera_chunk);
+        for (_, key) in candidates.into_iter().take(free) {
+            // Edited chunks are already in memory.
+            if let Some(stash) = self.edited.get_mut(&key).and_then(Option::take) {
+                world.insert_chunk(key, stash);
+                update.loaded.push(key);
+                continue;
+            }
+
+            self.in_flight.insert(key);
+            let level = Arc::clone(&self.level);
+            let sender = self.sender.clone();
+            rayon::spawn(move || {
+                // The streamer may be gone by now, in which case nobody wants the chunk.
+                let _ = sender.send((key, decode_chunk(&level, key)));
+            });
+        }
+    }
+}
+
+/// A chunk meshed by [`MeshQueue`], with the level of detail it was meshed at.
+#[derive(Debug)]
+pub struct MeshedChunk {
+    pub key: u32,
+    pub lod: Lod,
+    pub mesh: ChunkMesh,
+}
+
+type Meshed = (u64, MeshedChunk);
+
+/// Meshes dirty chunks on the rayon pool so the frame doesn't wait for them.
+///
+/// [`Self::submit`] assembles each dirty chunk's padded voxels from the world (which needs the
+/// neighbours, so it runs on the caller's thread) and hands the meshing to a worker;
+/// [`Self::poll`] collects the finished meshes. A chunk submitted again before its mesh arrived,
+/// or cancelled in between, only yields the mesh of its last submission.
+pub struct MeshQueue {
+    sender: Sender<Meshed>,
+    receiver: Receiver<Meshed>,
+    // Generation of the latest submission for each chunk with a mesh still to come.
+    pending: HashMap<u32, u64>,
+    next_generation: u64,
+}
+
+impl Default for MeshQueue {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver,
+            pending: HashMap::new(),
+            next_generation: 0,
+        }
+    }
+}
+
+impl MeshQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of chunks whose mesh hasn't been collected yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_pending(&self, key: u32) -> bool {
+        self.pending.contains_key(&key)
+    }
+
+    /// Takes the world's dirty chunks and queues them for meshing at the LOD `lod` picks from
+    /// each chunk position, with the world's transparency, superseding earlier submissions of the
+    /// same chunks.
+    pub fn submit(&mut self, world: &mut World, ambient_occlusion: bool, lod: impl Fn(IVec3) -> Lod) {
+        let transparency = world.transparency().copied();
+        for key in world.take_dirty() {
+            let Some(neighbourhood) = world.neighbourhood(key) else {
+                continue;
+            };
+            let mut voxels = vec![0u8; CS_P3];
+            let mut opaque_mask = vec![0u64; CS_P2];
+            neighbourhood.assemble(&mut voxels, &mut opaque_mask);
+
+            let (x, y, z) = parse_xyz_key(key);
+            let chunk_pos = IVec3::new(x as i32, y as i32, z as i32);
+            let lod = lod(chunk_pos);
+            let generation = self.next_generation;
+            self.next_generation += 1;
+            self.pending.insert(key, generation);
+
+            let sender = self.sender.clone();
+            rayon::spawn(move || {
+                let mut mesh_data = MeshData::new(10_000);
+                mesh_data.opaque_mask = opaque_mask;
+                mesh_data.ambient_occlusion = ambient_occlusion;
+                mesh_assembled(&voxels, lod, transparency.as_ref(), &mut mesh_data);
+                let mesh = ChunkMesh::from_mesh_data(chunk_pos, &mesh_data);
+                // The queue may be gone by now, in which case nobody wants the mesh.
+                let _ = sender.send((generation, MeshedChunk { key, lod, mesh }));
+            });
+        }
+    }
+
+    /// Drops the mesh still to come for `key`, e.g. because the chunk was unloaded.
+    pub fn cancel(&mut self, key: u32) {
+        self.pending.remove(&key);
+    }
+
+    /// Meshes finished since the last call, skipping superseded and cancelled ones.
+    pub fn poll(&mut self) -> Vec<MeshedChunk> {
+        let mut meshed = Vec::new();
+        while let Ok((generation, chunk)) = self.receiver.try_recv() {
+            if self.pending.get(&chunk.key) == Some(&generation) {
+                self.pending.remove(&chunk.key);
+                meshed.push(chunk);
+            }
+        }
+        meshed
+    }
+}
+
+fn decode_chunk(level: &MappedLevel, key: u32) -> Result<Box<[u8]>> {
+    let mut voxels = vec![0u8; CS_P3];
+    let mut opaque_mask = vec![0u64; CS_P2];
+    level
+        .decode_chunk(key, &mut voxels, &mut opaque_mask)
+        .ok_or_else(|| anyhow!("Chunk {:?} is not in the level", parse_xyz_key(key)))?
+        .with_context(|| format!("Failed to decode chunk {:?}", parse_xyz_key(key)))?;
+    Ok(unpad_chunk(&voxels))
+}
@@ -0,0 +1,420 @@
+use crate::data::level_file::LevelFile;
+use crate::data::mapped_level::MappedLevel;
+use crate::data::rle;
+use crate::mesher::{mesh_lod, mesh_lod_with_transparency, ChunkMesh, ColumnMask, Lod, MeshData, TransparencyTable};
+use crate::{get_xyz_key, get_zxy_index, parse_xyz_key, CS, CS_P2, CS_P3, MAX_CHUNK};
+use anyhow::{Context, Result};
+use glam::{IVec3, Vec3};
+use rayon::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+
+const CHUNK_VOLUME: usize = CS * CS * CS;
+
+/// Offsets of the six chunks sharing a face with the centre; enough for face culling.
+pub const FACE_NEIGHBOURS: [[i32; 3]; 6] = [[0, 1, 0], [0, -1, 0], [1, 0, 0], [-1, 0, 0], [0, 0, 1], [0, 0, -1]];
+
+/// Index of an unpadded voxel in a `CS³` chunk buffer, in the same z-fastest order as
+/// [`crate::get_zxy_index`].
+pub fn get_local_index(x: usize, y: usize, z: usize) -> usize {
+    local_index(CS, x, y, z)
+}
+
+fn local_index(cs: usize, x: usize, y: usize, z: usize) -> usize {
+    z + (x * cs) + (y * cs * cs)
+}
+
+/// Copies the interior of a padded `CS_P3` buffer (as decoded from a level) into an unpadded
+/// `CS³` chunk.
+pub fn unpad_chunk(padded: &[u8]) -> Box<[u8]> {
+    assert_eq!(padded.len(), CS_P3);
+    let mut voxels = vec![0u8; CHUNK_VOLUME].into_boxed_slice();
+    for y in 0..CS {
+        for x in 0..CS {
+            let start = get_zxy_index(x + 1, y + 1, 1);
+            let local = get_local_index(x, y, 0);
+            voxels[local..local + CS].copy_from_slice(&padded[start..start + CS]);
+        }
+    }
+    voxels
+}
+
+/// Meshes an assembled padded chunk at `lod`, with [`mesh_lod_with_transparency`] if there's a
+/// transparency table.
+pub fn mesh_assembled(voxels: &[u8], lod: Lod, transparency: Option<&TransparencyTable>, mesh_data: &mut MeshData) {
+    match transparency {
+        Some(transparency) => mesh_lod_with_transparency(voxels, lod, transparency, mesh_data),
+        None => mesh_lod(voxels, lod, mesh_data),
+    }
+}
+
+/// A chunk and the unpadded voxels of up to 26 chunks around it.
+///
+/// Chunks are edited and stored without padding; [`ChunkNeighbourhood::assemble`] builds the
+/// padded buffer the mesher needs, copying the border from the neighbours so faces between
+/// chunks are culled. Set the six [`FACE_NEIGHBOURS`] for culling alone; edges and corners only
+/// matter for ambient occlusion. Missing neighbours read as air.
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkNeighbourhood<'a> {
+    chunks: [Option<&'a [u8]>; 27],
+}
+
+impl<'a> ChunkNeighbourhood<'a> {
+    pub fn new(centre: &'a [u8]) -> Self {
+        let mut chunks = [None; 27];
+        chunks[Self::slot([0, 0, 0])] = Some(centre);
+        Self { chunks }
+    }
+
+    /// Sets the neighbour at `offset` (each component in `-1..=1`).
+    pub fn set(&mut self, offset: [i32; 3], chunk: Option<&'a [u8]>) {
+        assert!(offset != [0, 0, 0], "the centre chunk is set by ChunkNeighbourhood::new");
+        self.chunks[Self::slot(offset)] = chunk;
+    }
+
+    pub fn get(&self, offset: [i32; 3]) -> Option<&'a [u8]> {
+        self.chunks[Self::slot(offset)]
+    }
+
+    fn slot([x, y, z]: [i32; 3]) -> usize {
+        assert!([x, y, z].iter().all(|c| (-1..=1).contains(c)), "neighbour offset out of range: {:?}", [x, y, z]);
+        ((x + 1) + (y + 1) * 3 + (z + 1) * 9) as usize
+    }
+
+    /// Writes the padded `C::CS_P3` voxel buffer and matching `C::CS_P2` opaque mask, ready for
+    /// [`crate::mesher::mesh`]. Every chunk must hold `C::CS³` voxels.
+    pub fn assemble<C: ColumnMask>(&self, voxels: &mut [u8], opaque_mask: &mut [C]) {
+        let cs = C::CS;
+        assert_eq!(voxels.len(), C::CS_P3);
+        assert_eq!(opaque_mask.len(), C::CS_P2);
+        for chunk in self.chunks.iter().flatten() {
+            assert_eq!(chunk.len(), cs * cs * cs, "chunks must be CS³ unpadded voxels");
+        }
+
+        // Padded coordinate -> (neighbour offset, coordinate inside that chunk).
+        let source = |p: usize| match p {
+            0 => (-1, cs - 1),
+            p if p > cs => (1, 0),
+            p => (0, p - 1),
+        };
+
+        for y in 0..C::CS_P {
+            let (dy, sy) = source(y);
+            for x in 0..C::CS_P {
+                let (dx, sx) = source(x);
+                let column = x + y * C::CS_P;
+                let out = &mut voxels[column * C::CS_P..(column + 1) * C::CS_P];
+
+                // The unpadded layout is z-fastest too, so the interior is one contiguous copy.
+                match self.get([dx, dy, 0]) {
+                    Some(chunk) => {
+                        let start = local_index(cs, sx, sy, 0);
+                        out[1..=cs].copy_from_slice(&chunk[start..start + cs]);
+                    }
+                    None => out[1..=cs].fill(0),
+                }
+                out[0] = self.get([dx, dy, -1]).map_or(0, |chunk| chunk[local_index(cs, sx, sy, cs - 1)]);
+                out[cs + 1] = self.get([dx, dy, 1]).map_or(0, |chunk| chunk[local_index(cs, sx, sy, 0)]);
+
+                let mut bits = C::ZERO;
+                for (z, &ty) in out.iter().enumerate() {
+                    if ty != 0 {
+                        bits |= C::ONE << z;
+                    }
+                }
+                opaque_mask[column] = bits;
+            }
+        }
+    }
+}
+
+/// First solid voxel along a ray, from [`World::raycast`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub voxel: IVec3,
+    /// Normal of the face the ray entered through, so `voxel + normal` is the empty cell in
+    /// front of it. Zero when the ray starts inside a solid voxel.
+    pub normal: IVec3,
+    pub distance: f32,
+}
+
+/// Voxels of a whole level kept in memory, one unpadded `CS³` buffer per chunk keyed by
+/// [`get_xyz_key`].
+///
+/// Edits go through [`World::set_voxel`], which marks the edited chunk dirty along with every
+/// stored neighbour whose padding shows the voxel. [`World::mesh_dirty`] re-meshes exactly
+/// those chunks with padding assembled from the current neighbours.
+#[derive(Debug, Default)]
+pub struct World {
+    chunks: HashMap<u32, Box<[u8]>>,
+    dirty: BTreeSet<u32>,
+    // `None` when every type is opaque, so chunks take the single-pass mesher.
+    transparency: Option<TransparencyTable>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes every chunk of `level`. All chunks start dirty.
+    pub fn from_level(level: &LevelFile) -> Result<Self> {
+        let mut world = Self::new();
+        let mut voxels = vec![0u8; CS_P3];
+        let mut opaque_mask = vec![0u64; CS_P2];
+        for entry in &level.chunk_table {
+            rle::try_decompress_to_voxels_and_opaque_mask(level.chunk_data(entry)?, &mut voxels, &mut opaque_mask)
+                .with_context(|| format!("Failed to decode chunk {:?}", parse_xyz_key(entry.key)))?;
+            world.insert_padded_chunk(entry.key, &voxels);
+        }
+        Ok(world)
+    }
+
+    /// Decodes every chunk of a mapped level. All chunks start dirty.
+    pub fn from_mapped_level(level: &MappedLevel) -> Result<Self> {
+        let mut world = Self::new();
+        let mut voxels = vec![0u8; CS_P3];
+        let mut opaque_mask = vec![0u64; CS_P2];
+        for key in level.keys() {
+            level
+                .decode_chunk(key, &mut voxels, &mut opaque_mask)
+                .expect("key comes from the level's own table")
+                .with_context(|| format!("Failed to decode chunk {:?}", parse_xyz_key(key)))?;
+            world.insert_padded_chunk(key, &voxels);
+        }
+        Ok(world)
+    }
+
+    /// Stores an unpadded `CS³` chunk under `key`, replacing any existing one, and marks it and
+    /// its stored neighbours dirty.
+    pub fn insert_chunk(&mut self, key: u32, voxels: Box<[u8]>) {
+        assert_eq!(voxels.len(), CHUNK_VOLUME, "chunks must be CS³ unpadded voxels");
+        self.chunks.insert(key, voxels);
+        self.mark_with_neighbours(key, [-1..=1, -1..=1, -1..=1]);
+    }
+
+    /// Stores the interior of a padded `CS_P3` buffer (as decoded from a level) under `key`.
+    pub fn insert_padded_chunk(&mut self, key: u32, padded: &[u8]) {
+        self.insert_chunk(key, unpad_chunk(padded));
+    }
+
+    /// Removes the chunk at `key` and marks its stored neighbours dirty, since their padding
+    /// turns to air.
+    pub fn remove_chunk(&mut self, key: u32) -> Option<Box<[u8]>> {
+        let voxels = self.chunks.remove(&key)?;
+        self.dirty.remove(&key);
+        self.mark_with_neighbours(key, [-1..=1, -1..=1, -1..=1]);
+        Some(voxels)
+    }
+
+    /// Types meshed as transparent (see [`crate::mesher::mesh_with_transparency`]); `None`, the
+    /// default, treats every type as opaque. Changing it marks every chunk dirty.
+    pub fn set_transparency(&mut self, transparency: Option<TransparencyTable>) {
+        if transparency != self.transparency {
+            self.transparency = transparency;
+            self.dirty.extend(self.chunks.keys().copied());
+        }
+    }
+
+    pub fn transparency(&self) -> Option<&TransparencyTable> {
+        self.transparency.as_ref()
+    }
+
+    pub fn chunk(&self, key: u32) -> Option<&[u8]> {
+        self.chunks.get(&key).map(|c| &c[..])
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = u32> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Key of the chunk holding a world voxel position, or `None` outside chunks
+    /// `0..=MAX_CHUNK` on each axis.
+    pub fn chunk_key(pos: IVec3) -> Option<u32> {
+        Self::locate(pos).map(|(key, _)| key)
+    }
+
+    /// Chunk key and local index of a world voxel position, or `None` outside chunks
+    /// `0..=MAX_CHUNK` on each axis.
+    fn locate(pos: IVec3) -> Option<(u32, [usize; 3])> {
+        let cs = CS as i32;
+        let chunk = pos.div_euclid(IVec3::splat(cs));
+        if chunk.min_element() < 0 || chunk.max_element() > MAX_CHUNK as i32 {
+            return None;
+        }
+        let local = pos.rem_euclid(IVec3::splat(cs));
+        let key = get_xyz_key(chunk.x as u8, chunk.y as u8, chunk.z as u8);
+        Some((key, [local.x as usize, local.y as usize, local.z as usize]))
+    }
+
+    /// Voxel type at a world position; air outside stored chunks.
+    pub fn get_voxel(&self, pos: IVec3) -> u8 {
+        Self::locate(pos)
+            .and_then(|(key, [x, y, z])| self.chunks.get(&key).map(|c| c[get_local_index(x, y, z)]))
+            .unwrap_or(0)
+    }
+
+    /// Sets the voxel at a world position, creating its chunk if needed, and returns the
+    /// previous type. Returns `None` for positions outside chunks `0..=MAX_CHUNK`, which a level
+    /// file could not save.
+    ///
+    /// Changing a voxel marks its chunk dirty, plus every stored neighbour (faces, edges and
+    /// corners) whose padding includes it.
+    pub fn set_voxel(&mut self, pos: IVec3, ty: u8) -> Option<u8> {
+        let (key, [x, y, z]) = Self::locate(pos)?;
+        let chunk = match self.chunks.get_mut(&key) {
+            Some(chunk) => chunk,
+            None if ty == 0 => return Some(0),
+            None => self.chunks.entry(key).or_insert_with(|| vec![0u8; CHUNK_VOLUME].into_boxed_slice()),
+        };
+
+        let voxel = &mut chunk[get_local_index(x, y, z)];
+        let previous = std::mem::replace(voxel, ty);
+        if previous != ty {
+            let border = |c: usize| match c {
+                0 => -1..=0,
+                c if c == CS - 1 => 0..=1,
+                _ => 0..=0,
+            };
+            self.mark_with_neighbours(key, [border(x), border(y), border(z)]);
+        }
+        Some(previous)
+    }
+
+    // Marks `key` and every stored chunk at the given offsets dirty.
+    fn mark_with_neighbours(&mut self, key: u32, [xs, ys, zs]: [std::ops::RangeInclusive<i32>; 3]) {
+        let (x, y, z) = parse_xyz_key(key);
+        for dz in zs {
+            for dy in ys.clone() {
+                for dx in xs.clone() {
+                    let neighbour = [x as i32 + dx, y as i32 + dy, z as i32 + dz];
+                    if neighbour.iter().any(|c| !(0..=MAX_CHUNK as i32).contains(c)) {
+                        continue;
+                    }
+                    let neighbour = get_xyz_key(neighbour[0] as u8, neighbour[1] as u8, neighbour[2] as u8);
+                    if self.chunks.contains_key(&neighbour) {
+                        self.dirty.insert(neighbour);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn mark_dirty(&mut self, key: u32) {
+        if self.chunks.contains_key(&key) {
+            self.dirty.insert(key);
+        }
+    }
+
+    pub fn is_dirty(&self, key: u32) -> bool {
+        self.dirty.contains(&key)
+    }
+
+    /// Dirty chunk keys in ascending order.
+    pub fn dirty_chunks(&self) -> impl Iterator<Item = u32> + '_ {
+        self.dirty.iter().copied()
+    }
+
+    /// Returns the dirty chunk keys in ascending order and clears the set.
+    pub fn take_dirty(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.dirty).into_iter().collect()
+    }
+
+    /// The stored chunk at `key` together with its 26 stored neighbours.
+    pub fn neighbourhood(&self, key: u32) -> Option<ChunkNeighbourhood<'_>> {
+        let mut neighbourhood = ChunkNeighbourhood::new(self.chunk(key)?);
+        let (x, y, z) = parse_xyz_key(key);
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let n = [x as i32 + dx, y as i32 + dy, z as i32 + dz];
+                    if [dx, dy, dz] == [0, 0, 0] || n.iter().any(|c| !(0..=MAX_CHUNK as i32).contains(c)) {
+                        continue;
+                    }
+                    neighbourhood.set([dx, dy, dz], self.chunk(get_xyz_key(n[0] as u8, n[1] as u8, n[2] as u8)));
+                }
+            }
+        }
+        Some(neighbourhood)
+    }
+
+    /// Walks the voxel grid from `origin` along `direction` (Amanatides & Woo) and returns the
+    /// first non-air voxel within `max_distance`.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+        let direction = direction.normalize_or_zero();
+        if direction == Vec3::ZERO {
+            return None;
+        }
+
+        let mut voxel = origin.floor().as_ivec3();
+        let step = IVec3::from_array(direction.to_array().map(|d| if d > 0.0 { 1 } else if d < 0.0 { -1 } else { 0 }));
+        let t_delta = direction.abs().recip();
+        let mut t_max = Vec3::from_array(std::array::from_fn(|axis| match step[axis] {
+            1 => (voxel[axis] as f32 + 1.0 - origin[axis]) * t_delta[axis],
+            -1 => (origin[axis] - voxel[axis] as f32) * t_delta[axis],
+            _ => f32::INFINITY,
+        }));
+
+        let mut normal = IVec3::ZERO;
+        let mut distance = 0.0;
+        loop {
+            if self.get_voxel(voxel) != 0 {
+                return Some(RayHit { voxel, normal, distance });
+            }
+
+            let axis = if t_max.x < t_max.y {
+                if t_max.x < t_max.z { 0 } else { 2 }
+            } else if t_max.y < t_max.z {
+                1
+            } else {
+                2
+            };
+            distance = t_max[axis];
+            if distance > max_distance {
+                return None;
+            }
+            voxel[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+        }
+    }
+
+    /// Assembles the padded chunk at `key` into `voxels` and `mesh_data.opaque_mask` and meshes
+    /// it at `lod` with the world's transparency. Returns `false` if no chunk is stored there.
+    pub fn mesh_chunk(&self, key: u32, lod: Lod, voxels: &mut [u8], mesh_data: &mut MeshData) -> bool {
+        let Some(neighbourhood) = self.neighbourhood(key) else {
+            return false;
+        };
+        neighbourhood.assemble(voxels, &mut mesh_data.opaque_mask);
+        mesh_assembled(voxels, lod, self.transparency(), mesh_data);
+        true
+    }
+
+    /// Meshes every dirty chunk on the rayon pool and clears the dirty set. `lod` picks each
+    /// chunk's level of detail from its chunk position. Only stored chunks are meshed; a removed
+    /// chunk never shows up here, so its draws have to be dropped by whoever removed it.
+    pub fn mesh_dirty(&mut self, ambient_occlusion: bool, lod: impl Fn(IVec3) -> Lod + Sync) -> Vec<ChunkMesh> {
+        let dirty = self.take_dirty();
+        let world = &*self;
+        dirty
+            .into_par_iter()
+            .map_init(
+                || (vec![0u8; CS_P3], MeshData::new(10_000)),
+                |(voxels, mesh_data), key| {
+                    let (x, y, z) = parse_xyz_key(key);
+                    let chunk_pos = IVec3::new(x as i32, y as i32, z as i32);
+                    mesh_data.ambient_occlusion = ambient_occlusion;
+                    // `mesh_data` still holds the previous chunk's quads if nothing was meshed.
+                    world
+                        .mesh_chunk(key, lod(chunk_pos), voxels, mesh_data)
+                        .then(|| ChunkMesh::from_mesh_data(chunk_pos, mesh_data))
+                },
+            )
+            .flatten()
+            .collect()
+    }
+}
@@ -0,0 +1,136 @@
+use binary_greedy_mesher_demo_rs::rendering::allocator::{FreeListAllocator, Relocation};
+use proptest::prelude::*;
+
+#[test]
+fn freed_blocks_are_reused_and_coalesced() {
+    let mut allocator = FreeListAllocator::new(100);
+    let a = allocator.alloc(10).unwrap();
+    let b = allocator.alloc(20).unwrap();
+    let c = allocator.alloc(30).unwrap();
+    assert_eq!((a, b, c), (0, 10, 30));
+    assert_eq!(allocator.alloc(0), None);
+    assert_eq!(allocator.alloc(41), None);
+
+    assert_eq!(allocator.free(b), Some(20));
+    assert_eq!(allocator.free(b), None, "double free is rejected");
+    assert_eq!(allocator.free(5), None, "only allocation starts can be freed");
+    assert_eq!(allocator.stats().free_blocks, 2);
+
+    // Best fit: the 20-unit hole is preferred over the 40-unit tail.
+    assert_eq!(allocator.alloc(15), Some(10));
+    assert_eq!(allocator.free(10), Some(15));
+
+    // Freeing the neighbours on both sides merges everything back into one block.
+    allocator.free(a);
+    allocator.free(c);
+    let stats = allocator.stats();
+    assert_eq!(stats.used, 0);
+    assert_eq!(stats.free_blocks, 1);
+    assert_eq!(stats.largest_free_block, 100);
+    assert_eq!(allocator.alloc(100), Some(0));
+}
+
+#[test]
+fn compaction_closes_gaps_in_order() {
+    let mut allocator = FreeListAllocator::new(64);
+    let blocks: Vec<u32> = (0..8).map(|_| allocator.alloc(8).unwrap()).collect();
+    for &offset in blocks.iter().step_by(2) {
+        allocator.free(offset);
+    }
+    assert_eq!(allocator.alloc(16), None, "free space is fragmented");
+    assert_eq!(allocator.stats().fragmentation(), 0.75);
+
+    let relocations = allocator.compact();
+    assert_eq!(
+        relocations,
+        vec![
+            Relocation { from: 8, to: 0, len: 8 },
+            Relocation { from: 24, to: 8, len: 8 },
+            Relocation { from: 40, to: 16, len: 8 },
+            Relocation { from: 56, to: 24, len: 8 },
+        ]
+    );
+    assert_eq!(allocator.allocation_len(24), Some(8));
+    assert_eq!(allocator.allocation_len(56), None);
+
+    let stats = allocator.stats();
+    assert_eq!((stats.used, stats.allocations, stats.free_blocks), (32, 4, 1));
+    assert_eq!(stats.fragmentation(), 0.0);
+    assert_eq!(allocator.alloc(32), Some(32));
+    assert!(allocator.compact().is_empty(), "already compact");
+}
+
+#[test]
+fn remeshing_in_place_does_not_grow_usage() {
+    // Three chunks of six face allocations each, re-meshed over and over the way the viewer does
+    // it: free the chunk's old faces, then allocate the new ones, compacting once if that fails.
+    const MAX_FACE_LEN: u32 = 12;
+    let mut allocator = FreeListAllocator::new(3 * 6 * MAX_FACE_LEN);
+    let mut chunks: Vec<Vec<(u32, u32)>> = vec![Vec::new(); 3];
+
+    let mut state = 0x9e37_79b9u32;
+    for cycle in 0..2_000 {
+        let chunk = cycle % chunks.len();
+        for (offset, len) in chunks[chunk].drain(..) {
+            assert_eq!(allocator.free(offset), Some(len));
+        }
+
+        for _ in 0..6 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let len = 1 + state % MAX_FACE_LEN;
+            let offset = allocator.alloc(len).or_else(|| {
+                for r in allocator.compact() {
+                    let face = chunks.iter_mut().flatten().find(|(offset, _)| *offset == r.from).unwrap();
+                    face.0 = r.to;
+                }
+                allocator.alloc(len)
+            });
+            chunks[chunk].push((offset.unwrap_or_else(|| panic!("cycle {cycle}: out of space for {len}")), len));
+        }
+
+        let live: u32 = chunks.iter().flatten().map(|&(_, len)| len).sum();
+        let stats = allocator.stats();
+        assert_eq!((stats.used, stats.allocations), (live, chunks.iter().map(Vec::len).sum()), "cycle {cycle}");
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn allocations_never_overlap(ops in prop::collection::vec((any::<bool>(), 1u32..200, any::<prop::sample::Index>()), 1..200)) {
+        const CAPACITY: u32 = 4096;
+        let mut allocator = FreeListAllocator::new(CAPACITY);
+        let mut live: Vec<(u32, u32)> = Vec::new();
+
+        for (i, (alloc, len, pick)) in ops.into_iter().enumerate() {
+            if alloc || live.is_empty() {
+                if let Some(offset) = allocator.alloc(len) {
+                    live.push((offset, len));
+                }
+            } else {
+                let (offset, len) = live.swap_remove(pick.index(live.len()));
+                prop_assert_eq!(allocator.free(offset), Some(len));
+            }
+
+            if i % 50 == 49 {
+                for r in allocator.compact() {
+                    let block = live.iter_mut().find(|(offset, _)| *offset == r.from).unwrap();
+                    prop_assert_eq!(block.1, r.len);
+                    block.0 = r.to;
+                }
+            }
+
+            live.sort_unstable();
+            prop_assert!(live.windows(2).all(|w| w[0].0 + w[0].1 <= w[1].0));
+            prop_assert!(live.last().is_none_or(|&(offset, len)| offset + len <= CAPACITY));
+
+            let stats = allocator.stats();
+            prop_assert_eq!(stats.used, live.iter().map(|&(_, len)| len).sum::<u32>());
+            prop_assert_eq!(stats.allocations, live.len());
+            prop_assert!(stats.largest_free_block <= stats.free());
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use binary_greedy_mesher_demo_rs::data::blocks::{BlockRegistry, CLASSIC_COLORS};
+use binary_greedy_mesher_demo_rs::rendering::chunk_renderer::{gpu_blocks, GpuBlock, BLOCK_FLAG_EMISSIVE};
+use std::mem::{offset_of, size_of};
+
+#[test]
+fn default_registry_covers_every_type() {
+    let blocks = BlockRegistry::default();
+    assert_eq!(blocks.iter().count(), 255);
+    for (i, (name, color)) in CLASSIC_COLORS.into_iter().enumerate() {
+        assert_eq!(blocks.get(i as u8 + 1).name, name);
+        assert_eq!(blocks.color(i as u8 + 1), color);
+    }
+
+    // Undefined types still get a usable colour, different from their neighbours.
+    for ty in 9..=255u8 {
+        assert!(blocks.color(ty).iter().all(|c| (0.0..=1.0).contains(c)), "type {ty}");
+        assert_ne!(blocks.color(ty), blocks.color(ty - 1), "type {ty}");
+    }
+    assert_eq!(blocks.transparency_table(), [false; 256]);
+}
+
+#[test]
+fn json_overrides_listed_types() {
+    let blocks = BlockRegistry::from_json(
+        r#"{ "blocks": [
+            { "id": 2, "name": "stone", "color": [0.5, 0.5, 0.5], "texture": 4 },
+            { "id": 200, "name": "glass", "color": [0.8, 0.9, 1.0], "opacity": 0.25 },
+            { "id": 201, "color": [1.0, 0.5, 0.0], "emissive": true }
+        ] }"#,
+    )
+    .unwrap();
+
+    assert_eq!(blocks.find("stone"), Some(2));
+    assert_eq!(blocks.get(2).texture, Some(4));
+    assert_eq!(blocks.get(1), BlockRegistry::default().get(1), "unlisted types keep their default");
+    assert_eq!(blocks.get(201).name, "block_201");
+    assert!(blocks.get(201).emissive);
+
+    let transparency = blocks.transparency_table();
+    assert!(transparency[200]);
+    assert_eq!(transparency.iter().filter(|&&t| t).count(), 1);
+}
+
+#[test]
+fn json_rejects_invalid_entries() {
+    for (json, reason) in [
+        (r#"{ "blocks": [{ "id": 0, "color": [1, 1, 1] }] }"#, "air"),
+        (r#"{ "blocks": [{ "id": 256, "color": [1, 1, 1] }] }"#, "id out of range"),
+        (r#"{ "blocks": [{ "id": 3 }] }"#, "missing colour"),
+        (r#"{ "blocks": [{ "id": 3, "color": [1, 1] }] }"#, "short colour"),
+        (r#"{ "blocks": [{ "id": 3, "color": [1, 2, 1] }] }"#, "colour out of range"),
+        (r#"{ "blocks": [{ "id": 3, "color": [1, 1, 1], "opacity": 1.5 }] }"#, "opacity out of range"),
+        (r#"{ "blocks": [{ "id": 3, "color": [1, 1, 1] }, { "id": 3, "color": [0, 0, 0] }] }"#, "duplicate id"),
+        (r#"{ "types": [] }"#, "missing blocks array"),
+    ] {
+        assert!(BlockRegistry::from_json(json).is_err(), "{reason}");
+    }
+}
+
+#[test]
+fn example_registry_file_loads() {
+    let blocks = BlockRegistry::load(concat!(env!("CARGO_MANIFEST_DIR"), "/blocks.json")).unwrap();
+    for (i, (name, color)) in CLASSIC_COLORS.into_iter().enumerate() {
+        assert_eq!((blocks.get(i as u8 + 1).name.as_str(), blocks.color(i as u8 + 1)), (name, color));
+    }
+    assert!(blocks.has_transparent_types());
+}
+
+#[test]
+fn gpu_palette_matches_the_std430_shader_struct() {
+    // `struct Block { vec4 colorOpacity; uint flags; int texture; }` rounds up to vec4 alignment.
+    assert_eq!(offset_of!(GpuBlock, flags), 16);
+    assert_eq!(offset_of!(GpuBlock, texture), 20);
+    assert_eq!(size_of::<GpuBlock>(), 32);
+
+    let blocks = BlockRegistry::from_json(r#"{ "blocks": [{ "id": 5, "color": [1, 0, 0], "emissive": true }] }"#).unwrap();
+    let palette = gpu_blocks(&blocks);
+    assert_eq!(palette.len(), 256);
+    assert_eq!(palette[5].color, [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(palette[5].flags, BLOCK_FLAG_EMISSIVE);
+    assert_eq!(palette[5].texture, -1);
+    assert_eq!(palette[1].flags, 0);
+}
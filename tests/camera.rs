@@ -0,0 +1,54 @@
+use binary_greedy_mesher_demo_rs::misc::camera::{Camera, Frustum};
+use glam::{IVec3, Mat4, Vec3};
+
+#[test]
+fn planes_are_normalised_signed_distances() {
+    // Orthographic box x in [-2, 2], y in [-1, 1], looking down -z from z = 0, depth 1..11.
+    let frustum = Frustum::from_matrix(Mat4::orthographic_rh_gl(-2.0, 2.0, -1.0, 1.0, 1.0, 11.0));
+    let distances = frustum
+        .planes
+        .map(|plane| plane.truncate().dot(Vec3::new(0.5, 0.25, -3.0)) + plane.w);
+    let expected = [2.5, 1.5, 1.25, 0.75, 2.0, 8.0];
+    for (distance, expected) in distances.into_iter().zip(expected) {
+        assert!((distance - expected).abs() < 1e-5, "{distances:?}");
+    }
+
+    assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -5.0)));
+    assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.5)), "in front of the near plane");
+    assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -12.0)), "past the far plane");
+    assert!(!frustum.contains_point(Vec3::new(2.5, 0.0, -5.0)));
+}
+
+#[test]
+fn aabb_test_keeps_boxes_crossing_a_plane() {
+    let frustum = Frustum::from_matrix(Mat4::orthographic_rh_gl(-2.0, 2.0, -1.0, 1.0, 1.0, 11.0));
+    let visible = |min: [f32; 3], max: [f32; 3]| frustum.intersects_aabb(Vec3::from(min), Vec3::from(max));
+
+    assert!(visible([-1.0, -0.5, -4.0], [1.0, 0.5, -2.0]), "fully inside");
+    assert!(visible([1.5, -0.5, -4.0], [3.0, 0.5, -2.0]), "crosses the right plane");
+    assert!(visible([-10.0, -10.0, -20.0], [10.0, 10.0, 10.0]), "encloses the frustum");
+    assert!(!visible([2.5, -0.5, -4.0], [3.0, 0.5, -2.0]), "right of the frustum");
+    assert!(!visible([-1.0, 1.5, -4.0], [1.0, 2.0, -2.0]), "above the frustum");
+    assert!(!visible([-1.0, -0.5, 0.0], [1.0, 0.5, 5.0]), "behind the camera");
+}
+
+#[test]
+fn camera_frustum_is_relative_to_the_integer_eye_position() {
+    // Far from the origin, facing +x (yaw 0).
+    let camera = Camera::new(Vec3::new(10_000.5, 200.25, -3_000.75), 1280, 720);
+    assert!(camera.front.abs_diff_eq(Vec3::X, 1e-6));
+    let eye_int = camera.eye_position_int();
+    assert_eq!(eye_int, IVec3::new(10_000, 200, -3_001));
+
+    let frustum = camera.frustum();
+    let chunk_visible = |world_min: IVec3| {
+        let min = (world_min - eye_int).as_vec3();
+        frustum.intersects_aabb(min, min + Vec3::splat(62.0))
+    };
+
+    assert!(chunk_visible(IVec3::new(10_100, 170, -3_030)), "ahead of the camera");
+    assert!(chunk_visible(IVec3::new(9_990, 190, -3_010)), "contains the camera");
+    assert!(!chunk_visible(IVec3::new(9_800, 170, -3_030)), "behind the camera");
+    assert!(!chunk_visible(IVec3::new(10_100, 170, -2_700)), "far off to the side");
+    assert!(!chunk_visible(IVec3::new(30_000, 170, -3_030)), "beyond the far plane");
+}
@@ -0,0 +1,17 @@
+// Shared by several test binaries; each uses only some of these.
+#![allow(dead_code)]
+
+use binary_greedy_mesher_demo_rs::{get_zxy_index, CS_P3};
+use std::path::PathBuf;
+
+// A path in the temp directory unique to this test binary and process.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{}_{}_{name}", env!("CARGO_CRATE_NAME"), std::process::id()))
+}
+
+// A chunk holding a single voxel of type `ty` at padded (x, 1, 1).
+pub fn one_voxel(x: usize, ty: u8) -> Vec<u8> {
+    let mut voxels = vec![0u8; CS_P3];
+    voxels[get_zxy_index(x, 1, 1)] = ty;
+    voxels
+}
@@ -0,0 +1,209 @@
+use binary_greedy_mesher_demo_rs::data::blocks::BlockRegistry;
+use binary_greedy_mesher_demo_rs::data::level_file::LevelFile;
+use binary_greedy_mesher_demo_rs::export::{gltf, mesh_level, obj};
+use binary_greedy_mesher_demo_rs::mesher::ChunkMesh;
+use binary_greedy_mesher_demo_rs::{get_xyz_key, get_zxy_index, CS_P3};
+use serde_json::Value;
+
+// Padded voxel position and type.
+type Voxel = ([usize; 3], u8);
+
+// Meshes a level whose chunks are given as (chunk x, voxels).
+fn mesh_chunks(chunks: &[(u8, &[Voxel])], blocks: &BlockRegistry) -> Vec<ChunkMesh> {
+    let mut level = LevelFile::default();
+    for &(x, voxels) in chunks {
+        let mut padded = vec![0u8; CS_P3];
+        for &([vx, vy, vz], ty) in voxels {
+            padded[get_zxy_index(vx, vy, vz)] = ty;
+        }
+        level.insert_chunk(get_xyz_key(x, 0, 0), &padded).unwrap();
+    }
+    mesh_level(&level, blocks).unwrap()
+}
+
+fn lines_starting_with<'a>(text: &'a str, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+    text.lines().filter(move |line| line.starts_with(prefix))
+}
+
+#[test]
+fn obj_of_one_voxel_is_a_cube() {
+    let blocks = BlockRegistry::default();
+    // One voxel of type 3 in the second chunk, at its local origin.
+    let chunks = mesh_chunks(&[(1, &[([1, 1, 1], 3)])], &blocks);
+
+    let mut out = Vec::new();
+    obj::write_obj(&mut out, &chunks, Some("cube.mtl")).unwrap();
+    let text = String::from_utf8(out).unwrap();
+
+    assert_eq!(lines_starting_with(&text, "mtllib ").collect::<Vec<_>>(), ["mtllib cube.mtl"]);
+    assert_eq!(lines_starting_with(&text, "vn ").count(), 6);
+    assert_eq!(lines_starting_with(&text, "usemtl ").collect::<Vec<_>>(), ["usemtl voxel_3"]);
+    assert_eq!(lines_starting_with(&text, "f ").count(), 12);
+
+    // 4 vertices per face, all on the corners of the unit cube at world (62, 0, 0).
+    let vertices: Vec<[i32; 3]> = lines_starting_with(&text, "v ")
+        .map(|line| {
+            let coords: Vec<i32> = line[2..].split(' ').map(|c| c.parse().unwrap()).collect();
+            [coords[0], coords[1], coords[2]]
+        })
+        .collect();
+    assert_eq!(vertices.len(), 24);
+    assert!(vertices.iter().all(|&[x, y, z]| (62..=63).contains(&x) && (0..=1).contains(&y) && (0..=1).contains(&z)));
+
+    // Faces reference existing vertices and the normal of their face.
+    for line in lines_starting_with(&text, "f ") {
+        let corners: Vec<(usize, usize)> = line[2..]
+            .split(' ')
+            .map(|corner| {
+                let (v, n) = corner.split_once("//").unwrap();
+                (v.parse().unwrap(), n.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(corners.len(), 3, "{line}");
+        assert!(corners.iter().all(|&(v, n)| (1..=24).contains(&v) && n == corners[0].1 && (1..=6).contains(&n)));
+    }
+}
+
+#[test]
+fn mtl_lists_each_type_once_with_its_properties() {
+    let blocks = BlockRegistry::from_json(
+        r#"{ "blocks": [
+            { "id": 9, "name": "glass", "color": [0.5, 0.75, 1.0], "opacity": 0.25 },
+            { "id": 10, "name": "lava", "color": [1.0, 0.5, 0.0], "emissive": true }
+        ] }"#,
+    )
+    .unwrap();
+    let chunks = mesh_chunks(&[(0, &[([1, 1, 1], 2), ([5, 1, 1], 9), ([9, 1, 1], 10)]), (1, &[([1, 1, 1], 2)])], &blocks);
+
+    let mut out = Vec::new();
+    obj::write_mtl(&mut out, &chunks, &blocks).unwrap();
+    let text = String::from_utf8(out).unwrap();
+
+    let materials: Vec<&str> = text.split("\n\n").filter(|m| !m.trim().is_empty()).collect();
+    assert_eq!(
+        materials,
+        [
+            "# grey\nnewmtl voxel_2\nKd 0.302 0.302 0.302\nKa 0 0 0\nillum 1",
+            "# glass\nnewmtl voxel_9\nKd 0.5 0.75 1\nKa 0 0 0\nd 0.25\nillum 1",
+            "# lava\nnewmtl voxel_10\nKd 1 0.5 0\nKa 0 0 0\nKe 1 0.5 0\nillum 1",
+        ]
+    );
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+// Splits a GLB into its JSON document and BIN chunk, checking the container along the way.
+fn parse_glb(bytes: &[u8]) -> (Value, &[u8]) {
+    assert_eq!(&bytes[0..4], b"glTF");
+    assert_eq!(read_u32(bytes, 4), 2, "container version");
+    assert_eq!(read_u32(bytes, 8) as usize, bytes.len(), "total length");
+
+    let json_len = read_u32(bytes, 12) as usize;
+    assert_eq!(&bytes[16..20], b"JSON");
+    assert_eq!(json_len % 4, 0, "JSON chunk padding");
+    let json = &bytes[20..20 + json_len];
+    assert!(json.iter().rev().take_while(|&&b| b == b' ').count() < 4);
+    let document: Value = serde_json::from_slice(json).unwrap();
+
+    let bin_start = 20 + json_len;
+    if bin_start == bytes.len() {
+        return (document, &[]);
+    }
+    let bin_len = read_u32(bytes, bin_start) as usize;
+    assert_eq!(&bytes[bin_start + 4..bin_start + 8], b"BIN\0");
+    assert_eq!(bin_len % 4, 0, "BIN chunk padding");
+    assert_eq!(bin_start + 8 + bin_len, bytes.len(), "BIN is the last chunk");
+    (document, &bytes[bin_start + 8..])
+}
+
+fn as_usize(value: &Value) -> usize {
+    value.as_u64().unwrap() as usize
+}
+
+#[test]
+fn glb_container_and_accessors_are_consistent() {
+    let blocks = BlockRegistry::from_json(r#"{ "blocks": [{ "id": 9, "color": [0.5, 0.75, 1.0], "opacity": 0.25 }] }"#).unwrap();
+    // Two chunks sharing type 2, one of them with a glass voxel as well; the third is empty.
+    let chunks = mesh_chunks(
+        &[(0, &[([1, 1, 1], 2), ([2, 1, 1], 2), ([5, 1, 1], 9)]), (1, &[([1, 1, 1], 2)]), (2, &[])],
+        &blocks,
+    );
+
+    let mut out = Vec::new();
+    gltf::write_glb(&mut out, &chunks, &blocks).unwrap();
+    let (document, bin) = parse_glb(&out);
+
+    let buffer_len = as_usize(&document["buffers"][0]["byteLength"]);
+    assert!(buffer_len <= bin.len() && bin.len() - buffer_len < 4, "buffer {buffer_len} in BIN of {}", bin.len());
+
+    let views = document["bufferViews"].as_array().unwrap();
+    for view in views {
+        let (offset, len) = (as_usize(&view["byteOffset"]), as_usize(&view["byteLength"]));
+        assert!(len > 0 && offset + len <= buffer_len, "{view}");
+    }
+
+    // One material per type, shared between chunks; glass is blended.
+    let materials = document["materials"].as_array().unwrap();
+    assert_eq!(materials.iter().map(|m| m["name"].as_str().unwrap()).collect::<Vec<_>>(), ["voxel_2", "voxel_9"]);
+    assert_eq!(materials[1]["alphaMode"], "BLEND");
+    assert_eq!(materials[1]["pbrMetallicRoughness"]["baseColorFactor"][3], 0.25);
+
+    let nodes = document["nodes"].as_array().unwrap();
+    assert_eq!(nodes.len(), 3);
+    assert!(nodes[2].get("mesh").is_none(), "empty chunks get no mesh");
+    assert_eq!(nodes[1]["translation"], serde_json::json!([62.0, 0.0, 0.0]));
+
+    let accessors = document["accessors"].as_array().unwrap();
+    let accessor_data = |index: usize| -> (&Value, &[u8]) {
+        let accessor = &accessors[index];
+        let view = &views[as_usize(&accessor["bufferView"])];
+        let component_size = 4;
+        let components = if accessor["type"] == "VEC3" { 3 } else { 1 };
+        let begin = as_usize(&view["byteOffset"]) + as_usize(&accessor["byteOffset"]);
+        let len = as_usize(&accessor["count"]) * components * component_size;
+        assert!(as_usize(&accessor["byteOffset"]) + len <= as_usize(&view["byteLength"]), "{accessor}");
+        (accessor, &bin[begin..begin + len])
+    };
+
+    let mut primitive_types = Vec::new();
+    for mesh in document["meshes"].as_array().unwrap() {
+        for primitive in mesh["primitives"].as_array().unwrap() {
+            primitive_types.push(as_usize(&primitive["material"]));
+
+            let (positions, position_bytes) = accessor_data(as_usize(&primitive["attributes"]["POSITION"]));
+            let (normals, _) = accessor_data(as_usize(&primitive["attributes"]["NORMAL"]));
+            let (indices, index_bytes) = accessor_data(as_usize(&primitive["indices"]));
+            let vertex_count = as_usize(&positions["count"]);
+            assert_eq!(vertex_count % 4, 0, "4 vertices per quad");
+            assert_eq!(as_usize(&normals["count"]), vertex_count);
+            assert_eq!(as_usize(&indices["count"]), vertex_count / 4 * 6, "2 triangles per quad");
+
+            // min/max must bound the positions exactly.
+            let floats: Vec<f32> = position_bytes.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect();
+            let points: Vec<&[f32]> = floats.chunks_exact(3).collect();
+            for axis in 0..3 {
+                let min = points.iter().map(|p| p[axis]).fold(f32::MAX, f32::min);
+                let max = points.iter().map(|p| p[axis]).fold(f32::MIN, f32::max);
+                assert_eq!(positions["min"][axis].as_f64().unwrap() as f32, min);
+                assert_eq!(positions["max"][axis].as_f64().unwrap() as f32, max);
+            }
+            assert!(index_bytes.chunks_exact(4).all(|b| (read_u32(b, 0) as usize) < vertex_count));
+        }
+    }
+    // Chunk 0 has a primitive for each type, chunk 1 only one.
+    assert_eq!(primitive_types, [0, 1, 0]);
+}
+
+#[test]
+fn glb_of_an_empty_level_has_no_buffers() {
+    let chunks = mesh_chunks(&[(0, &[])], &BlockRegistry::default());
+    let mut out = Vec::new();
+    gltf::write_glb(&mut out, &chunks, &BlockRegistry::default()).unwrap();
+
+    let (document, bin) = parse_glb(&out);
+    assert!(bin.is_empty());
+    assert!(document.get("buffers").is_none() && document.get("accessors").is_none());
+    assert_eq!(document["nodes"].as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,90 @@
+use binary_greedy_mesher_demo_rs::misc::camera::Camera;
+use binary_greedy_mesher_demo_rs::rendering::chunk_renderer::DrawElementsIndirectCommand;
+use binary_greedy_mesher_demo_rs::rendering::gpu_culling::{cull_chunk, facing_faces, ChunkCullSlot, CullStats};
+use binary_greedy_mesher_demo_rs::CS;
+use glam::{IVec3, Vec3};
+use std::mem::{offset_of, size_of};
+
+// Deterministic xorshift values in `range`.
+fn values(seed: u32, range: std::ops::Range<i32>) -> impl FnMut() -> i32 {
+    let mut state = seed;
+    move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        range.start + (state % (range.end - range.start) as u32) as i32
+    }
+}
+
+#[test]
+fn slot_layout_matches_the_std430_shader_struct() {
+    // std430 packs `DrawCommand faces[6]; int aabbMin[3]; int aabbMax[3];` with 4-byte alignment.
+    assert_eq!(size_of::<DrawElementsIndirectCommand>(), 20);
+    assert_eq!(offset_of!(ChunkCullSlot, aabb_min), 120);
+    assert_eq!(offset_of!(ChunkCullSlot, aabb_max), 132);
+    assert_eq!(size_of::<ChunkCullSlot>(), 144);
+}
+
+#[test]
+fn stats_start_with_the_draw_count() {
+    // The counters buffer doubles as the GL_PARAMETER_BUFFER, read at offset 0.
+    assert_eq!(offset_of!(CullStats, draw_count), 0);
+    assert_eq!(offset_of!(CullStats, frustum_culled), 4);
+    assert_eq!(offset_of!(CullStats, occluded), 8);
+    assert_eq!(size_of::<CullStats>(), 16);
+}
+
+#[test]
+fn facing_faces_match_the_chunk_coordinate_rule() {
+    // The viewer used to compare the camera's chunk with the chunk's; for whole chunks, comparing
+    // the integer eye position with the chunk bounds must give the same faces.
+    let cs = CS as i32;
+    let mut next = values(0x1234_5678, -400..400);
+    for _ in 0..10_000 {
+        let eye = IVec3::new(next(), next(), next());
+        let chunk = IVec3::new(next(), next(), next()).map(|c| c / 40);
+        let camera_chunk = eye.map(|c| c.div_euclid(cs));
+        let expected = [
+            camera_chunk.y >= chunk.y,
+            camera_chunk.y <= chunk.y,
+            camera_chunk.x >= chunk.x,
+            camera_chunk.x <= chunk.x,
+            camera_chunk.z >= chunk.z,
+            camera_chunk.z <= chunk.z,
+        ];
+        let min = chunk * cs;
+        assert_eq!(facing_faces(eye, min, min + IVec3::splat(cs)), expected, "eye {eye} chunk {chunk}");
+    }
+
+    // Inside the chunk every face can be seen.
+    assert_eq!(facing_faces(IVec3::splat(10), IVec3::ZERO, IVec3::splat(cs)), [true; 6]);
+}
+
+#[test]
+fn cull_chunk_matches_the_frustum_test() {
+    // Facing +x from the middle of chunk (5, 1, 5).
+    let camera = Camera::new(Vec3::new(5.5, 1.5, 5.5) * CS as f32, 1280, 720);
+    let (frustum, eye) = (camera.frustum(), camera.eye_position_int());
+    let cull = |chunk: IVec3| {
+        let min = chunk * CS as i32;
+        cull_chunk(&frustum, eye, min, min + IVec3::splat(CS as i32))
+    };
+
+    // Ahead: only the faces pointing back at the camera (-X, and both Y/Z sides it is level with).
+    assert_eq!(cull(IVec3::new(7, 1, 5)), Some([true, true, false, true, true, true]));
+    assert_eq!(cull(IVec3::new(7, 0, 5)), Some([true, false, false, true, true, true]));
+    assert_eq!(cull(IVec3::new(5, 1, 5)), Some([true; 6]), "contains the camera");
+    assert_eq!(cull(IVec3::new(2, 1, 5)), None, "behind the camera");
+
+    let mut next = values(0x9e37_79b9, 0..12);
+    let mut visible = 0;
+    for _ in 0..2_000 {
+        let chunk = IVec3::new(next(), next() / 4, next());
+        let min = chunk * CS as i32;
+        let max = min + IVec3::splat(CS as i32);
+        let inside = frustum.intersects_aabb((min - eye).as_vec3(), (max - eye).as_vec3());
+        assert_eq!(cull(chunk), inside.then(|| facing_faces(eye, min, max)), "chunk {chunk}");
+        visible += inside as usize;
+    }
+    assert!((100..1_900).contains(&visible), "{visible} of 2000 visible");
+}
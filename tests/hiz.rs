@@ -0,0 +1,98 @@
+use binary_greedy_mesher_demo_rs::rendering::hiz::{
+    downsample_extent, footprint, level_count, level_size, ndc_bounds, ndc_to_depth, DepthPyramid,
+};
+use glam::{Mat4, UVec2, Vec2, Vec3, Vec4};
+
+#[test]
+fn levels_halve_down_to_one_texel() {
+    assert_eq!(level_count(1, 1), 1);
+    assert_eq!(level_count(5, 3), 3);
+    assert_eq!(level_count(1280, 720), 11);
+    let size = UVec2::new(1280, 720);
+    assert_eq!(level_size(size, 1), UVec2::new(640, 360));
+    assert_eq!(level_size(size, 10), UVec2::ONE);
+}
+
+#[test]
+fn odd_sizes_fold_the_leftover_texel_into_the_last_one() {
+    assert_eq!(downsample_extent(0, 2, 5), 2);
+    assert_eq!(downsample_extent(1, 2, 5), 3);
+    assert_eq!(downsample_extent(1, 2, 4), 2);
+
+    // Wherever the farthest depth is, every level keeps it somewhere, and the top holds it.
+    for (width, height) in (1..=9).flat_map(|w| (1..=9).map(move |h| (w, h))) {
+        for far in 0..width * height {
+            let mut depth = vec![0.25f32; (width * height) as usize];
+            depth[far as usize] = 1.0;
+            let pyramid = DepthPyramid::build(width, height, &depth);
+            for level in 0..pyramid.level_count() {
+                let size = pyramid.level_size(level);
+                let found = (0..size.y).any(|y| (0..size.x).any(|x| pyramid.depth(level, UVec2::new(x, y)) == 1.0));
+                assert!(found, "{width}x{height}, far texel {far}, level {level}");
+            }
+            assert_eq!(pyramid.level_size(pyramid.level_count() - 1), UVec2::ONE);
+        }
+    }
+}
+
+#[test]
+fn footprints_cover_at_most_two_by_two_texels() {
+    let size = UVec2::new(1280, 720);
+    let levels = level_count(size.x, size.y);
+
+    // The whole screen falls back to the 1x1 top level.
+    assert_eq!(footprint(size, levels, Vec2::ZERO, Vec2::ONE), (10, UVec2::ZERO, UVec2::ZERO));
+    // Up to one texel wide reads level 0; three texels wide needs level 2.
+    let origin = Vec2::new(0.5, 0.5);
+    assert_eq!(footprint(size, levels, origin, origin + Vec2::new(0.5, 0.5) / size.as_vec2()).0, 0);
+    let (level, t0, t1) = footprint(size, levels, origin, origin + Vec2::new(3.0, 1.0) / size.as_vec2());
+    assert_eq!((level, t0, t1), (2, UVec2::new(160, 90), UVec2::new(160, 90)));
+
+    let mut state = 7u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state % 10_000) as f32 / 10_000.0
+    };
+    for _ in 0..2000 {
+        let (a, b) = (Vec2::new(next(), next()), Vec2::new(next(), next()));
+        let (uv_min, uv_max) = (a.min(b), a.max(b));
+        let (level, t0, t1) = footprint(size, levels, uv_min, uv_max);
+        assert!(level < levels);
+        assert!(t1.x <= t0.x + 1 && t1.y <= t0.y + 1, "{uv_min} {uv_max}: {t0} {t1} at {level}");
+        assert!(t1.cmplt(level_size(size, level)).all());
+    }
+}
+
+#[test]
+fn ndc_depth_maps_to_the_depth_range() {
+    assert_eq!(ndc_to_depth(-1.0), 0.0);
+    assert_eq!(ndc_to_depth(0.0), 0.5);
+    assert_eq!(ndc_to_depth(1.0), 1.0);
+}
+
+#[test]
+fn boxes_behind_a_wall_are_occluded() {
+    let projection = Mat4::perspective_rh_gl(90f32.to_radians(), 1.0, 0.1, 1000.0);
+    let (width, height) = (64, 64);
+    // A wall 10 units in front of the camera covering the left half of the screen; the right
+    // half is empty (far plane).
+    let clip = projection * Vec4::new(0.0, 0.0, -10.0, 1.0);
+    let wall = ndc_to_depth(clip.z / clip.w);
+    let depth: Vec<f32> = (0..width * height).map(|i| if i % width < width / 2 { wall } else { 1.0 }).collect();
+    let pyramid = DepthPyramid::build(width, height, &depth);
+
+    let behind_left = (Vec3::new(-6.0, -1.0, -22.0), Vec3::new(-4.0, 1.0, -20.0));
+    let in_front_left = (Vec3::new(-3.0, -1.0, -6.0), Vec3::new(-2.0, 1.0, -5.0));
+    let behind_right = (Vec3::new(4.0, -1.0, -22.0), Vec3::new(6.0, 1.0, -20.0));
+    let around_camera = (Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+    assert!(pyramid.occludes(projection, behind_left.0, behind_left.1));
+    assert!(!pyramid.occludes(projection, in_front_left.0, in_front_left.1));
+    assert!(!pyramid.occludes(projection, behind_right.0, behind_right.1));
+    assert!(ndc_bounds(projection, around_camera.0, around_camera.1).is_none());
+    assert!(!pyramid.occludes(projection, around_camera.0, around_camera.1));
+
+    // Behind the wall but straddling its edge: part of it is visible on the right.
+    assert!(!pyramid.occludes(projection, Vec3::new(-2.0, -1.0, -22.0), Vec3::new(2.0, 1.0, -20.0)));
+}
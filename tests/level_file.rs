@@ -0,0 +1,282 @@
+use binary_greedy_mesher_demo_rs::data::level_file::{
+    ChunkTableEntry, LevelFile, LevelFileError, ValidationIssue, LEVEL_MAGIC, LEVEL_VERSION,
+};
+use binary_greedy_mesher_demo_rs::data::rle::{self, RleError};
+use binary_greedy_mesher_demo_rs::{get_xyz_key, CS_P2, CS_P3};
+use std::path::PathBuf;
+
+mod common;
+use common::{one_voxel, temp_path};
+
+fn decode(level: &LevelFile, key: u32) -> Vec<u8> {
+    let entry = level.chunk_table.iter().find(|e| e.key == key).unwrap();
+    let mut voxels = vec![0u8; CS_P3];
+    let mut mask = vec![0u64; CS_P2];
+    rle::try_decompress_to_voxels_and_opaque_mask(level.chunk_data(entry).unwrap(), &mut voxels, &mut mask).unwrap();
+    voxels
+}
+
+fn reload(path: &PathBuf) -> LevelFile {
+    let mut level = LevelFile::default();
+    level.load_from_file(path).unwrap();
+    level
+}
+
+#[test]
+fn inserted_replaced_and_removed_chunks_round_trip() {
+    let path = temp_path("round_trip");
+    let (a, b, c) = (get_xyz_key(0, 0, 0), get_xyz_key(1, 0, 0), get_xyz_key(0, 2, 1));
+
+    let mut level = LevelFile::default();
+    level.insert_chunk(a, &one_voxel(1, 1)).unwrap();
+    level.insert_chunk(b, &one_voxel(2, 2)).unwrap();
+    level.insert_chunk(c, &one_voxel(3, 3)).unwrap();
+    level.insert_chunk(b, &one_voxel(4, 4)).unwrap();
+    assert!(level.remove_chunk(a).is_some());
+    assert!(level.remove_chunk(a).is_none());
+    level.save_to_file(&path).unwrap();
+
+    let loaded = reload(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.dims(), [2, 3, 2]);
+    assert_eq!(loaded.chunk_table.iter().map(|e| e.key).collect::<Vec<_>>(), [b, c]);
+    assert_eq!(decode(&loaded, b), one_voxel(4, 4));
+    assert_eq!(decode(&loaded, c), one_voxel(3, 3));
+
+    // The saved level stays usable in memory and matches what was written.
+    assert!(level.validate().is_ok());
+    assert_eq!(level.chunk_table.len(), 2);
+    assert_eq!(decode(&level, b), one_voxel(4, 4));
+    assert_eq!(level.buffer, loaded.buffer);
+}
+
+#[test]
+fn saving_drops_replaced_and_removed_data() {
+    let path = temp_path("compact");
+    let (a, b) = (get_xyz_key(0, 0, 0), get_xyz_key(1, 0, 0));
+
+    let mut level = LevelFile::default();
+    level.insert_chunk(a, &one_voxel(1, 1)).unwrap();
+    level.save_to_file(&path).unwrap();
+    let size = std::fs::metadata(&path).unwrap().len();
+
+    // Replacing a chunk with data of the same size never grows the file or the buffer.
+    for ty in 2..20 {
+        level.insert_chunk(a, &one_voxel(1, ty)).unwrap();
+        level.save_to_file(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), size, "after replacing with type {ty}");
+        assert_eq!(level.buffer.len() as u64, size);
+    }
+
+    // Nor does a chunk that was added and removed again.
+    level.insert_chunk(b, &one_voxel(5, 5)).unwrap();
+    level.remove_chunk(b).unwrap();
+    level.save_to_file(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), size);
+    assert_eq!(level.buffer.len() as u64, size);
+
+    let loaded = reload(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(decode(&loaded, a), one_voxel(1, 19));
+}
+
+// Bytes of a saved two-chunk level. Tests run in parallel, so each passes its own `name`.
+fn saved_level(name: &str) -> Vec<u8> {
+    let path = temp_path(&format!("fixture_{name}"));
+    let mut level = LevelFile::default();
+    level.insert_chunk(get_xyz_key(0, 0, 0), &one_voxel(1, 1)).unwrap();
+    level.insert_chunk(get_xyz_key(1, 0, 0), &one_voxel(2, 2)).unwrap();
+    level.save_to_file(&path).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    bytes
+}
+
+fn load_bytes(name: &str, bytes: &[u8]) -> Result<LevelFile, LevelFileError> {
+    let path = temp_path(name);
+    std::fs::write(&path, bytes).unwrap();
+    let mut level = LevelFile::default();
+    let result = level.load_from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    result.map(|()| level)
+}
+
+#[test]
+fn saved_files_start_with_the_versioned_header() {
+    let bytes = saved_level("valid");
+    assert_eq!(bytes[0..4], LEVEL_MAGIC);
+    assert_eq!(bytes[4..6], LEVEL_VERSION.to_le_bytes());
+    assert!(load_bytes("valid", &bytes).is_ok());
+}
+
+#[test]
+fn flipped_bytes_fail_the_checksum() {
+    let bytes = saved_level("checksum");
+    // The stored CRC itself, a metadata byte, a chunk table byte and the last RLE byte.
+    for offset in [18, 22, 40, bytes.len() - 1] {
+        let mut corrupted = bytes.clone();
+        corrupted[offset] ^= 0x40;
+        match load_bytes("checksum", &corrupted) {
+            Err(LevelFileError::Corrupt(reason)) => assert_eq!(reason, "checksum mismatch", "byte {offset}"),
+            other => panic!("byte {offset}: expected a checksum error, got {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn bad_magic_is_the_wrong_format() {
+    // Without the magic the file is read as a legacy level, whose chunk table it can't hold.
+    let mut bytes = saved_level("magic");
+    bytes[0] = b'X';
+    assert!(matches!(load_bytes("magic", &bytes), Err(LevelFileError::WrongFormat(_))));
+
+    assert!(matches!(load_bytes("empty", &[]), Err(LevelFileError::WrongFormat(_))));
+    assert!(matches!(load_bytes("zero_size", &[0]), Err(LevelFileError::WrongFormat(_))));
+}
+
+#[test]
+fn unknown_versions_are_unsupported() {
+    for version in [0u16, 1, LEVEL_VERSION + 1, u16::MAX] {
+        let mut bytes = saved_level("version");
+        bytes[4..6].copy_from_slice(&version.to_le_bytes());
+        assert!(
+            matches!(load_bytes("version", &bytes), Err(LevelFileError::UnsupportedVersion(v)) if v == version),
+            "version {version}"
+        );
+    }
+}
+
+#[test]
+fn truncated_files_are_corrupt() {
+    let bytes = saved_level("truncated");
+    // Inside the version field, the rest of the header, the metadata block, the chunk table and the data.
+    for len in [5, 12, 21, 30, 60, bytes.len() - 1] {
+        assert!(matches!(load_bytes("truncated", &bytes[..len]), Err(LevelFileError::Corrupt(_))), "length {len}");
+    }
+}
+
+#[test]
+fn legacy_levels_load() {
+    // Size byte, a size² table of (key, begin, size) entries for the y=0 chunks, then RLE data.
+    let size = 2u8;
+    let chunks: Vec<(u32, Vec<u8>)> = (0..size)
+        .flat_map(|z| (0..size).map(move |x| (x, z)))
+        .map(|(x, z)| (get_xyz_key(x, 0, z), rle::encode_sparse_trailing_zeros(&one_voxel(1 + x as usize, 1 + z))))
+        .collect();
+
+    let mut bytes = vec![size];
+    let mut data_offset = 1 + chunks.len() * 12;
+    for (key, rle) in &chunks {
+        bytes.extend_from_slice(&key.to_le_bytes());
+        bytes.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&(rle.len() as u32).to_le_bytes());
+        data_offset += rle.len();
+    }
+    for (_, rle) in &chunks {
+        bytes.extend_from_slice(rle);
+    }
+
+    let level = load_bytes("legacy", &bytes).unwrap();
+    assert_eq!(level.dims(), [2, 1, 2]);
+    assert_eq!(level.chunk_table.len(), 4);
+    assert_eq!(decode(&level, get_xyz_key(1, 0, 1)), one_voxel(2, 2));
+    assert_eq!(decode(&level, get_xyz_key(0, 0, 1)), one_voxel(1, 2));
+}
+
+// An in-memory level with a hand-written chunk table over `buffer`, as (key, begin, size).
+fn table_level(buffer: Vec<u8>, table: &[(u32, u32, u32)]) -> LevelFile {
+    let mut level = LevelFile::default();
+    level.chunk_table = table
+        .iter()
+        .map(|&(key, rle_data_begin, rle_data_size)| ChunkTableEntry { key, rle_data_begin, rle_data_size })
+        .collect();
+    level.buffer = buffer;
+    level
+}
+
+#[test]
+fn complete_streams_validate_cleanly() {
+    let rle = rle::encode(&one_voxel(1, 1));
+    let size = rle.len() as u32;
+    let level = table_level(rle, &[(1, 0, size)]);
+    assert_eq!(level.validate().issues, []);
+}
+
+#[test]
+fn short_streams_are_reported_but_valid() {
+    let level = table_level(vec![1, 10, 2, 20], &[(1, 0, 4), (2, 4, 0)]);
+    let report = level.validate();
+    assert_eq!(
+        report.issues,
+        [
+            ValidationIssue::ShortStream { index: 0, key: 1, decoded: 30 },
+            ValidationIssue::ShortStream { index: 1, key: 2, decoded: 0 },
+        ]
+    );
+    assert!(report.is_ok());
+    assert_eq!(report.errors().count(), 0);
+}
+
+#[test]
+fn duplicate_keys_are_reported() {
+    let level = table_level(vec![1, 10, 2, 20], &[(7, 0, 2), (7, 2, 2)]);
+    let report = level.validate();
+    assert_eq!(
+        report.issues,
+        [
+            ValidationIssue::ShortStream { index: 0, key: 7, decoded: 10 },
+            ValidationIssue::DuplicateKey { key: 7, first: 0, second: 1 },
+            ValidationIssue::ShortStream { index: 1, key: 7, decoded: 20 },
+        ]
+    );
+    assert!(!report.is_ok());
+    assert!(report.to_string().starts_with("1 chunk table problem(s)\n  entries 0 and 1 share key"), "{report}");
+}
+
+#[test]
+fn overlapping_ranges_are_reported() {
+    // Entry 2 starts inside entry 0; entry 1 sits after both.
+    let level = table_level(vec![1, 10, 2, 20, 3, 30], &[(1, 0, 4), (2, 4, 2), (3, 2, 2)]);
+    let errors: Vec<_> = level.validate().errors().cloned().collect();
+    assert_eq!(errors, [ValidationIssue::Overlap { first: 0, second: 2 }]);
+}
+
+#[test]
+fn out_of_bounds_ranges_are_reported() {
+    let level = table_level(vec![1, 10, 2, 20], &[(1, 2, 4), (2, u32::MAX, 2)]);
+    let errors: Vec<_> = level.validate().errors().cloned().collect();
+    assert_eq!(
+        errors,
+        [
+            ValidationIssue::OutOfBounds { index: 0, key: 1, begin: 2, size: 4 },
+            ValidationIssue::OutOfBounds { index: 1, key: 2, begin: u32::MAX, size: 2 },
+        ]
+    );
+
+    // Data may not start inside the header or chunk table of a loaded level either.
+    let mut level = load_bytes("bounds", &saved_level("bounds")).unwrap();
+    level.chunk_table[1].rle_data_begin = 0;
+    let errors: Vec<_> = level.validate().errors().cloned().collect();
+    assert!(matches!(errors[..], [ValidationIssue::OutOfBounds { index: 1, begin: 0, .. }]), "{errors:?}");
+}
+
+#[test]
+fn malformed_streams_are_reported() {
+    // 1029 runs of 255 voxels overrun the chunk on the last one; 1028 of them fit.
+    let overrun: Vec<u8> = [1u8, 255].repeat(1029);
+    let level = table_level(overrun, &[(1, 0, 2058)]);
+    assert_eq!(
+        level.validate().issues,
+        [ValidationIssue::Rle {
+            index: 0,
+            key: 1,
+            error: RleError::Overrun { offset: 2056, decoded: 1028 * 255, run_length: 255 },
+        }]
+    );
+
+    let level = table_level(vec![1, 10, 2], &[(1, 0, 3)]);
+    assert_eq!(
+        level.validate().issues,
+        [ValidationIssue::Rle { index: 0, key: 1, error: RleError::TrailingByte { offset: 2, decoded: 10 } }]
+    );
+}
@@ -0,0 +1,106 @@
+use binary_greedy_mesher_demo_rs::data::level_file::{LevelFile, LevelFileError, LevelMetadata, ValidationIssue};
+use binary_greedy_mesher_demo_rs::data::mapped_level::MappedLevel;
+use binary_greedy_mesher_demo_rs::data::rle::RleError;
+use binary_greedy_mesher_demo_rs::{get_xyz_key, get_zxy_index, CS_P, CS_P2, CS_P3};
+use std::path::PathBuf;
+
+mod common;
+use common::{one_voxel, temp_path};
+
+// Two chunks, (0, 0, 0) and (2, 1, 0), saved to `name`; returns the level as saved.
+fn save_level(name: &str) -> (PathBuf, LevelFile) {
+    let path = temp_path(name);
+    let mut level = LevelFile::default();
+    level.metadata = LevelMetadata {
+        generator_seed: 42,
+        entries: vec![("generator".to_string(), "test".to_string())],
+    };
+    level.insert_chunk(get_xyz_key(0, 0, 0), &one_voxel(1, 1)).unwrap();
+    level.insert_chunk(get_xyz_key(2, 1, 0), &one_voxel(3, 7)).unwrap();
+    level.save_to_file(&path).unwrap();
+    (path, level)
+}
+
+// Writes `bytes` to `name` and opens it.
+fn open_bytes(name: &str, bytes: &[u8]) -> Result<MappedLevel, LevelFileError> {
+    let path = temp_path(name);
+    std::fs::write(&path, bytes).unwrap();
+    let mapped = MappedLevel::open(&path);
+    std::fs::remove_file(&path).unwrap();
+    mapped
+}
+
+#[test]
+fn opens_and_decodes_saved_levels() {
+    let (path, level) = save_level("decode");
+    let mapped = MappedLevel::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(mapped.dims(), [3, 2, 1]);
+    assert_eq!(mapped.metadata(), &level.metadata);
+    let (a, b) = (get_xyz_key(0, 0, 0), get_xyz_key(2, 1, 0));
+    assert_eq!(mapped.keys().collect::<Vec<_>>(), [a, b]);
+    assert_eq!(mapped.chunk_table().len(), 2);
+    assert_eq!(mapped.chunk(b), Some(level.chunk_data(&level.chunk_table[1]).unwrap()));
+
+    let mut voxels = vec![0xffu8; CS_P3];
+    let mut mask = vec![u64::MAX; CS_P2];
+    let decoded = mapped.decode_chunk(b, &mut voxels, &mut mask).unwrap().unwrap();
+    assert_eq!(decoded, get_zxy_index(3, 1, 1) + 1, "streams stop after the last solid voxel");
+    assert_eq!(voxels, one_voxel(3, 7));
+    // The mask is rebuilt from scratch: only column (y = 1, x = 3) has its z = 1 bit set.
+    let column = CS_P + 3;
+    assert!(mask.iter().enumerate().all(|(i, &bits)| bits == if i == column { 1 << 1 } else { 0 }));
+
+    let missing = get_xyz_key(1, 0, 0);
+    assert!(mapped.chunk(missing).is_none());
+    assert!(mapped.decode_chunk(missing, &mut voxels, &mut mask).is_none());
+}
+
+#[test]
+fn malformed_streams_fail_when_decoded() {
+    // Opening skips the checksum and the streams, so a bad stream only shows up on decode.
+    let (path, level) = save_level("malformed");
+    let mut bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // Make the first entry's stream one byte shorter, leaving a type byte without a run length.
+    // Data is packed in table order right after the 12-byte (key, begin, size) entries.
+    let size = level.chunk_table[0].rle_data_size;
+    let size_offset = level.chunk_table[0].rle_data_begin as usize - 2 * 12 + 8;
+    bytes[size_offset..size_offset + 4].copy_from_slice(&(size - 1).to_le_bytes());
+
+    let mapped = open_bytes("malformed", &bytes).unwrap();
+    let mut voxels = vec![0u8; CS_P3];
+    let mut mask = vec![0u64; CS_P2];
+    let result = mapped.decode_chunk(get_xyz_key(0, 0, 0), &mut voxels, &mut mask).unwrap();
+    assert!(matches!(result, Err(RleError::TrailingByte { .. })), "{result:?}");
+    assert!(mapped.decode_chunk(get_xyz_key(2, 1, 0), &mut voxels, &mut mask).unwrap().is_ok());
+}
+
+#[test]
+fn open_rejects_broken_files() {
+    let (path, level) = save_level("broken");
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(MappedLevel::open(temp_path("missing")), Err(LevelFileError::Io { .. })));
+
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[0] = b'X';
+    assert!(matches!(open_bytes("magic", &wrong_magic), Err(LevelFileError::WrongFormat(_))));
+
+    // Truncated inside the header, the metadata block and the chunk table.
+    let table_end = level.chunk_table[0].rle_data_begin as usize;
+    for len in [10, 30, table_end - 12] {
+        assert!(matches!(open_bytes("truncated", &bytes[..len]), Err(LevelFileError::Corrupt(_))), "length {len}");
+    }
+
+    // Cutting into the data leaves the last entry pointing past the end of the file.
+    match open_bytes("data", &bytes[..bytes.len() - 1]) {
+        Err(LevelFileError::Invalid(report)) => {
+            assert!(matches!(report.issues[..], [ValidationIssue::OutOfBounds { index: 1, .. }]), "{report}")
+        }
+        other => panic!("expected an out of bounds entry, got {:?}", other.err()),
+    }
+}
@@ -0,0 +1,230 @@
+use binary_greedy_mesher_demo_rs::mesher::{
+    mesh, mesh_lod, mesh_lod_with_transparency, mesh_with_transparency, ChunkMesh, ColumnMask, Lod, MeshData, TransparencyTable,
+};
+use glam::IVec3;
+
+// A padded `C` chunk with `ty(x, y, z)` at every unpadded position, visited in y, x, z order.
+fn fill<C: ColumnMask>(mut ty: impl FnMut(usize, usize, usize) -> u8) -> Vec<u8> {
+    let mut voxels = vec![0u8; C::CS_P3];
+    for y in 1..=C::CS {
+        for x in 1..=C::CS {
+            for z in 1..=C::CS {
+                voxels[C::zxy_index(x, y, z)] = ty(x, y, z);
+            }
+        }
+    }
+    voxels
+}
+
+// Fills the opaque mask for `voxels`, meshes them with `run` and returns the quads per face.
+fn mesh_chunk<C: ColumnMask>(voxels: &[u8], ambient_occlusion: bool, run: impl Fn(&[u8], &mut MeshData<C>)) -> ChunkMesh {
+    let mut mesh_data = MeshData::<C>::new(64);
+    mesh_data.ambient_occlusion = ambient_occlusion;
+    mesh_data.fill_opaque_mask(voxels);
+    run(voxels, &mut mesh_data);
+    ChunkMesh::from_mesh_data(IVec3::ZERO, &mesh_data)
+}
+
+// A deterministic mix of types, holes and a transparent type (3) inside a 28³ block starting
+// at padded (1, 1, 1), so it fits the smallest (u32, 30³) chunk.
+fn sample_voxels<C: ColumnMask>() -> Vec<u8> {
+    let mut state = 0x2545_f491u32;
+    fill::<C>(|x, y, z| {
+        if x > 28 || y > 28 || z > 28 {
+            return 0;
+        }
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        if y < 6 + (x + z) % 7 { 1 + (state % 3) as u8 } else if state.is_multiple_of(11) { 2 } else { 0 }
+    })
+}
+
+#[test]
+fn widths_produce_identical_quads() {
+    let mut transparency: TransparencyTable = [false; 256];
+    transparency[3] = true;
+
+    for lod in [Lod::Full, Lod::Half, Lod::Quarter] {
+        let narrow = mesh_chunk::<u32>(&sample_voxels::<u32>(), true, |v, m| mesh_lod(v, lod, m));
+        let default = mesh_chunk::<u64>(&sample_voxels::<u64>(), true, |v, m| mesh_lod(v, lod, m));
+        let wide = mesh_chunk::<u128>(&sample_voxels::<u128>(), true, |v, m| mesh_lod(v, lod, m));
+        assert!(default.quad_count() > 0);
+        assert_eq!(narrow.faces, default.faces, "u32 vs u64 at {lod:?}");
+        assert_eq!(wide.faces, default.faces, "u128 vs u64 at {lod:?}");
+    }
+
+    let narrow = mesh_chunk::<u32>(&sample_voxels::<u32>(), true, |v, m| mesh_with_transparency(v, &transparency, m));
+    let default = mesh_chunk::<u64>(&sample_voxels::<u64>(), true, |v, m| mesh_with_transparency(v, &transparency, m));
+    let wide = mesh_chunk::<u128>(&sample_voxels::<u128>(), true, |v, m| mesh_with_transparency(v, &transparency, m));
+    assert!(default.transparent_faces.iter().any(|quads| !quads.is_empty()));
+    assert_eq!(narrow.faces, default.faces);
+    assert_eq!(wide.faces, default.faces);
+    assert_eq!(narrow.transparent_faces, default.transparent_faces);
+    assert_eq!(wide.transparent_faces, default.transparent_faces);
+}
+
+// Types 8 and 9 are glass.
+const GLASS: TransparencyTable = {
+    let mut transparency = [false; 256];
+    transparency[8] = true;
+    transparency[9] = true;
+    transparency
+};
+
+// Two voxels side by side along X, `a` at padded (10, 10, 10) and `b` at (11, 10, 10).
+fn pair(a: u8, b: u8) -> Vec<u8> {
+    fill::<u64>(|x, y, z| match (x, y, z) {
+        (10, 10, 10) => a,
+        (11, 10, 10) => b,
+        _ => 0,
+    })
+}
+
+#[test]
+fn opaque_faces_show_through_transparent_neighbours() {
+    // Face 2 is +X and face 3 is -X. The opaque voxel keeps its face behind the glass; the glass
+    // face pressed against the opaque voxel is dropped.
+    let chunk = mesh_chunk::<u64>(&pair(1, 9), false, |v, m| mesh_with_transparency(v, &GLASS, m));
+    assert_eq!(chunk.faces.each_ref().map(Vec::len), [1; 6]);
+    assert_eq!(chunk.transparent_faces.each_ref().map(Vec::len), [1, 1, 1, 0, 1, 1]);
+}
+
+#[test]
+fn faces_between_the_same_transparent_type_are_culled() {
+    // Nothing between the two glass voxels, and the remaining sides merge into one quad each.
+    let chunk = mesh_chunk::<u64>(&pair(9, 9), false, |v, m| mesh_with_transparency(v, &GLASS, m));
+    assert_eq!(chunk.faces.each_ref().map(Vec::len), [0; 6]);
+    assert_eq!(chunk.transparent_faces.each_ref().map(Vec::len), [1; 6]);
+}
+
+#[test]
+fn faces_between_different_transparent_types_are_kept() {
+    // Both faces of the shared side survive, and different types never merge.
+    let chunk = mesh_chunk::<u64>(&pair(8, 9), false, |v, m| mesh_with_transparency(v, &GLASS, m));
+    assert_eq!(chunk.faces.each_ref().map(Vec::len), [0; 6]);
+    assert_eq!(chunk.transparent_faces.each_ref().map(Vec::len), [2; 6]);
+}
+
+#[test]
+fn wide_chunks_encode_positions_past_63() {
+    // A 10×1×1 bar at the far corner of a 126³ chunk.
+    let voxels = fill::<u128>(|x, y, z| if x >= 117 && y == 126 && z == 126 { 5 } else { 0 });
+    let chunk = mesh_chunk::<u128>(&voxels, false, mesh);
+
+    assert_eq!(chunk.quad_count(), 6);
+    for (face, quads) in chunk.faces.iter().enumerate() {
+        let corners = quads[0].vertices_in::<u128>(face);
+        for axis in 0..3 {
+            let (lo, hi) = if axis == 0 { (116, 126) } else { (125, 126) };
+            assert!(corners.iter().all(|c| (lo..=hi).contains(&c[axis])), "face {face}: {corners:?}");
+        }
+        assert_eq!(quads[0].voxel_type(), 5);
+    }
+}
+
+#[test]
+fn ambient_occlusion_darkens_corners_next_to_occluders() {
+    // Above the voxel at (10, 10, 10): one occluder towards +X and one towards +Z. Quad corners
+    // are unpadded, so its top face spans 9..=10 on X and Z at height 10.
+    let solid = [[10, 10, 10], [11, 11, 10], [10, 11, 11]];
+    let voxels = fill::<u64>(|x, y, z| solid.contains(&[x, y, z]) as u8);
+    let chunk = mesh_chunk::<u64>(&voxels, true, mesh);
+    let top = chunk.faces[0].iter().find(|quad| quad.vertices(0)[0][1] == 10).unwrap();
+
+    // The corner touching both occluders is fully dark, those touching one are partly dark.
+    for vertex in 0..4 {
+        let [x, _, z] = top.vertices(0)[vertex];
+        let expected = match (x, z) {
+            (10, 10) => 3,
+            (10, _) | (_, 10) => 1,
+            _ => 0,
+        };
+        assert_eq!(top.ambient_occlusion(vertex), expected, "corner at x={x} z={z}");
+    }
+
+    // Without ambient occlusion every corner stays lit.
+    let chunk = mesh_chunk::<u64>(&voxels, false, mesh);
+    assert!(chunk.faces.iter().flatten().all(|quad| (0..4).all(|v| quad.ambient_occlusion(v) == 0)));
+}
+
+#[test]
+fn quads_with_different_ambient_occlusion_are_not_merged() {
+    // A row of three voxels along X; the occluder above its end only darkens the last voxel's top.
+    let solid = [[10, 10, 10], [11, 10, 10], [12, 10, 10], [13, 11, 10]];
+    let voxels = fill::<u64>(|x, y, z| solid.contains(&[x, y, z]) as u8);
+    let lit = mesh_chunk::<u64>(&voxels, false, mesh);
+    let occluded = mesh_chunk::<u64>(&voxels, true, mesh);
+
+    assert_eq!(lit.faces[0].len(), 2, "the row's top and the occluder's top");
+    assert_eq!(occluded.faces[0].len(), 3, "the darkened voxel gets its own quad");
+    let mut row_tops: Vec<(i32, i32, Vec<u32>)> = occluded.faces[0]
+        .iter()
+        .filter(|quad| quad.vertices(0)[0][1] == 10)
+        .map(|quad| {
+            let xs = quad.vertices(0).map(|c| c[0]);
+            (*xs.iter().min().unwrap(), *xs.iter().max().unwrap(), (0..4).map(|v| quad.ambient_occlusion(v)).collect())
+        })
+        .collect();
+    row_tops.sort();
+    assert_eq!(row_tops, [(9, 11, vec![0; 4]), (11, 12, vec![0, 0, 1, 1])]);
+}
+
+#[test]
+fn coarse_lods_mesh_a_full_chunk_as_six_quads() {
+    let voxels = fill::<u64>(|_, _, _| 4);
+
+    for lod in [Lod::Half, Lod::Quarter, Lod::Eighth] {
+        let chunk = mesh_chunk::<u64>(&voxels, false, |v, m| mesh_lod(v, lod, m));
+
+        assert_eq!(chunk.faces.iter().map(Vec::len).collect::<Vec<_>>(), [1; 6], "{lod:?}");
+        for (face, quads) in chunk.faces.iter().enumerate() {
+            let quad = &quads[0];
+            assert_eq!(quad.lod_shift(), lod.shift(), "{lod:?} face {face}");
+            assert_eq!(quad.voxel_type(), 4);
+            // The last cell overhangs the 62-voxel chunk and is clamped to its edge.
+            let corners = quad.vertices(face);
+            assert!(corners.iter().flatten().all(|&c| c == 0 || c == 62), "{lod:?} face {face}: {corners:?}");
+            assert_ne!(corners[0], corners[3], "{lod:?} face {face}");
+        }
+    }
+}
+
+#[test]
+fn coarse_cells_take_the_most_common_type() {
+    // Three voxels in the first 2³ cell: one of type 2 and two of type 3.
+    let voxels = fill::<u64>(|x, y, z| match (x, y, z) {
+        (1, 1, 1) => 2,
+        (2, 1, 1) | (1, 2, 1) => 3,
+        _ => 0,
+    });
+
+    for (lod, size) in [(Lod::Half, 2), (Lod::Quarter, 4)] {
+        let chunk = mesh_chunk::<u64>(&voxels, false, |v, m| mesh_lod(v, lod, m));
+
+        // One cell, drawn as a cube `size` voxels across.
+        assert_eq!(chunk.faces.iter().map(Vec::len).collect::<Vec<_>>(), [1; 6], "{lod:?}");
+        for (face, quads) in chunk.faces.iter().enumerate() {
+            let quad = &quads[0];
+            assert_eq!((quad.voxel_type(), quad.lod_shift()), (3, lod.shift()), "{lod:?} face {face}");
+            assert!(quad.vertices(face).iter().flatten().all(|&c| c == 0 || c == size), "{lod:?} face {face}");
+        }
+    }
+}
+
+#[test]
+fn coarse_lods_keep_transparent_quads_apart() {
+    // Opaque type 4 below y = 32 and transparent type 9 above, on cell boundaries for every LOD.
+    let voxels = fill::<u64>(|_, y, _| if y <= 32 { 4 } else { 9 });
+
+    for lod in [Lod::Full, Lod::Half, Lod::Quarter, Lod::Eighth] {
+        let chunk = mesh_chunk::<u64>(&voxels, false, |v, m| mesh_lod_with_transparency(v, lod, &GLASS, m));
+
+        // The opaque top shows through the glass; the glass has no face against the opaque half.
+        assert_eq!(chunk.faces.iter().map(Vec::len).collect::<Vec<_>>(), [1; 6], "{lod:?}");
+        assert_eq!(chunk.transparent_faces.iter().map(Vec::len).collect::<Vec<_>>(), [1, 0, 1, 1, 1, 1], "{lod:?}");
+        assert!(chunk.faces.iter().flatten().all(|quad| quad.voxel_type() == 4));
+        assert!(chunk.transparent_faces.iter().flatten().all(|quad| quad.voxel_type() == 9));
+        assert!(chunk.quads().all(|(_, quad)| quad.lod_shift() == lod.shift()), "{lod:?}");
+    }
+}
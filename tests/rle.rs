@@ -0,0 +1,96 @@
+use binary_greedy_mesher_demo_rs::data::rle;
+use binary_greedy_mesher_demo_rs::{CS_P2, CS_P3};
+use proptest::prelude::*;
+
+// Chunks built from runs of mixed lengths, so both long runs (> 255, > 64) and single voxels
+// show up; mostly low types with the occasional arbitrary one.
+fn voxels() -> impl Strategy<Value = Vec<u8>> {
+    let ty = prop_oneof![4 => 0u8..4, 1 => any::<u8>()];
+    prop::collection::vec((ty, 1usize..3000), 1..300).prop_map(|runs| {
+        let mut voxels: Vec<u8> = runs
+            .into_iter()
+            .flat_map(|(ty, len)| std::iter::repeat_n(ty, len))
+            .take(CS_P3)
+            .collect();
+        voxels.resize(CS_P3, 0);
+        voxels
+    })
+}
+
+fn naive_opaque_mask(voxels: &[u8]) -> Vec<u64> {
+    let mut mask = vec![0u64; CS_P2];
+    for (i, &ty) in voxels.iter().enumerate() {
+        if ty != 0 {
+            mask[i / 64] |= 1u64 << (i % 64);
+        }
+    }
+    mask
+}
+
+fn decode(encoded: &[u8]) -> (Vec<u8>, Vec<u64>) {
+    let mut voxels = vec![0u8; CS_P3];
+    let mut mask = vec![0u64; CS_P2];
+    rle::decompress_to_voxels_and_opaque_mask(encoded, &mut voxels, &mut mask);
+    (voxels, mask)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn encode_round_trips(voxels in voxels()) {
+        let encoded = rle::encode(&voxels);
+        prop_assert!(encoded.chunks_exact(2).all(|run| run[1] != 0));
+
+        let (decoded, mask) = decode(&encoded);
+        prop_assert_eq!(&decoded, &voxels);
+        prop_assert_eq!(mask, naive_opaque_mask(&voxels));
+    }
+
+    #[test]
+    fn sparse_encode_round_trips(voxels in voxels()) {
+        let encoded = rle::encode_sparse_trailing_zeros(&voxels);
+        prop_assert!(encoded.len() <= rle::encode(&voxels).len());
+
+        let (decoded, mask) = decode(&encoded);
+        prop_assert_eq!(&decoded, &voxels);
+        prop_assert_eq!(mask, naive_opaque_mask(&voxels));
+    }
+
+    #[test]
+    fn checked_decode_overwrites_stale_data(voxels in voxels(), stale in any::<u8>()) {
+        let encoded = rle::encode_sparse_trailing_zeros(&voxels);
+        let mut decoded = vec![stale; CS_P3];
+        let mut mask = vec![u64::MAX; CS_P2];
+
+        let count = rle::try_decompress_to_voxels_and_opaque_mask(&encoded, &mut decoded, &mut mask).unwrap();
+        let expected_count = voxels.iter().rposition(|&v| v != 0).map_or(0, |i| i + 1);
+        prop_assert_eq!(count, expected_count);
+        prop_assert_eq!(&decoded, &voxels);
+        prop_assert_eq!(mask, naive_opaque_mask(&voxels));
+    }
+}
+
+#[test]
+fn checked_decode_rejects_overrun_and_trailing_byte() {
+    let mut voxels = vec![0u8; CS_P3];
+    let mut mask = vec![0u64; CS_P2];
+
+    let mut overlong = rle::encode(&vec![1u8; CS_P3]);
+    overlong.extend_from_slice(&[2, 1]);
+    let offset = overlong.len() - 2;
+    assert_eq!(
+        rle::try_decompress_to_voxels_and_opaque_mask(&overlong, &mut voxels, &mut mask),
+        Err(rle::RleError::Overrun {
+            offset,
+            decoded: CS_P3,
+            run_length: 1
+        })
+    );
+    assert!(voxels.iter().all(|&v| v == 0), "nothing is written when the stream is rejected");
+
+    assert_eq!(
+        rle::try_decompress_to_voxels_and_opaque_mask(&[1, 10, 2], &mut voxels, &mut mask),
+        Err(rle::RleError::TrailingByte { offset: 2, decoded: 10 })
+    );
+}
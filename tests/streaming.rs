@@ -0,0 +1,235 @@
+use binary_greedy_mesher_demo_rs::data::level_file::LevelFile;
+use binary_greedy_mesher_demo_rs::data::mapped_level::MappedLevel;
+use binary_greedy_mesher_demo_rs::mesher::Lod;
+use binary_greedy_mesher_demo_rs::streaming::{load_priority, ChunkStreamer, MeshQueue, MeshedChunk, StreamingConfig, StreamingUpdate};
+use binary_greedy_mesher_demo_rs::world::World;
+use binary_greedy_mesher_demo_rs::{get_xyz_key, CS, MAX_CHUNK};
+use glam::{IVec3, Vec3};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{one_voxel, temp_path};
+
+// A row of chunks along x; chunk x holds a single voxel of type x + 1 at its local origin.
+fn row_level(name: &str, len: u8) -> Arc<MappedLevel> {
+    chunks_level(name, 0..len)
+}
+
+// Like `row_level`, with only the chunks at `xs`.
+fn chunks_level(name: &str, xs: impl IntoIterator<Item = u8>) -> Arc<MappedLevel> {
+    let mut level = LevelFile::default();
+    for x in xs {
+        level.insert_chunk(get_xyz_key(x, 0, 0), &one_voxel(1, x + 1)).unwrap();
+    }
+    let path = temp_path(name);
+    level.save_to_file(&path).unwrap();
+    let mapped = MappedLevel::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    Arc::new(mapped)
+}
+
+// Runs updates until no chunks are left decoding, collecting what was loaded and unloaded.
+fn settle(streamer: &mut ChunkStreamer, world: &mut World, camera_chunk: Vec3) -> (BTreeSet<u8>, BTreeSet<u8>) {
+    let (mut loaded, mut unloaded) = (BTreeSet::new(), BTreeSet::new());
+    let start = Instant::now();
+    loop {
+        let StreamingUpdate {
+            loaded: l,
+            unloaded: u,
+            errors,
+        } = streamer.update(world, camera_chunk, Vec3::X);
+        assert!(errors.is_empty(), "{errors:?}");
+        loaded.extend(l.into_iter().map(|key| key as u8));
+        unloaded.extend(u.into_iter().map(|key| key as u8));
+        if streamer.in_flight() == 0 {
+            return (loaded, unloaded);
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "streaming did not settle");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn resident(world: &World) -> BTreeSet<u8> {
+    world.keys().map(|key| key as u8).collect()
+}
+
+#[test]
+fn priority_prefers_near_chunks_in_front() {
+    let camera = Vec3::splat(0.5);
+    let ahead = load_priority(camera, Vec3::X, IVec3::new(3, 0, 0));
+    let behind = load_priority(camera, Vec3::X, IVec3::new(-3, 0, 0));
+    let side = load_priority(camera, Vec3::X, IVec3::new(0, 0, 3));
+    let far_ahead = load_priority(camera, Vec3::X, IVec3::new(5, 0, 0));
+    assert!(ahead < side && side < behind);
+    assert!(ahead < far_ahead);
+    assert!(far_ahead < behind, "a chunk behind counts as three times as far");
+    assert_eq!(load_priority(camera, Vec3::X, IVec3::ZERO), 0.0);
+}
+
+#[test]
+fn chunks_stream_in_and_out_around_the_camera() {
+    let config = StreamingConfig {
+        view_radius: 1.5,
+        unload_margin: 1.0,
+        max_in_flight: 2,
+    };
+    let mut streamer = ChunkStreamer::new(row_level("radius", 10), config);
+    let mut world = World::new();
+
+    let (loaded, _) = settle(&mut streamer, &mut world, Vec3::new(0.5, 0.5, 0.5));
+    assert_eq!(loaded, BTreeSet::from([0, 1]));
+    assert_eq!(world.get_voxel(IVec3::new(CS as i32, 0, 0)), 2);
+    assert!(world.is_dirty(get_xyz_key(1, 0, 0)));
+
+    // Within the unload margin nothing is dropped.
+    let (loaded, unloaded) = settle(&mut streamer, &mut world, Vec3::new(2.5, 0.5, 0.5));
+    assert_eq!((loaded, unloaded), (BTreeSet::from([2, 3]), BTreeSet::new()));
+
+    // Loads more chunks than fit in flight at once.
+    let (loaded, unloaded) = settle(&mut streamer, &mut world, Vec3::new(7.5, 0.5, 0.5));
+    assert_eq!(loaded, BTreeSet::from([6, 7, 8]));
+    assert_eq!(unloaded, BTreeSet::from([0, 1, 2, 3]));
+    assert_eq!(resident(&world), BTreeSet::from([6, 7, 8]));
+}
+
+#[test]
+fn edited_chunks_keep_their_edits_when_reloaded() {
+    let config = StreamingConfig {
+        view_radius: 1.0,
+        unload_margin: 0.5,
+        ..Default::default()
+    };
+    let mut streamer = ChunkStreamer::new(row_level("edits", 6), config);
+    let mut world = World::new();
+    settle(&mut streamer, &mut world, Vec3::new(0.5, 0.5, 0.5));
+    assert_eq!(resident(&world), BTreeSet::from([0, 1]));
+
+    // Chunk 3 is in the level but not loaded yet, the chunk above has nothing to stream in.
+    let key = get_xyz_key(0, 0, 0);
+    assert!(streamer.can_edit(&world, key));
+    assert!(!streamer.can_edit(&world, get_xyz_key(3, 0, 0)));
+    assert!(streamer.can_edit(&world, get_xyz_key(0, 1, 0)));
+
+    world.set_voxel(IVec3::new(5, 5, 5), 9);
+    streamer.mark_edited(key);
+    world.set_voxel(IVec3::new(5, CS as i32 + 5, 5), 7);
+    streamer.mark_edited(get_xyz_key(0, 1, 0));
+
+    let (_, unloaded) = settle(&mut streamer, &mut world, Vec3::new(4.5, 0.5, 0.5));
+    assert!(unloaded.contains(&0) && !resident(&world).contains(&0));
+
+    settle(&mut streamer, &mut world, Vec3::new(0.5, 0.5, 0.5));
+    assert_eq!(world.get_voxel(IVec3::new(5, 5, 5)), 9);
+    assert_eq!(world.get_voxel(IVec3::ZERO), 1, "the rest of the chunk is intact");
+    assert_eq!(world.get_voxel(IVec3::new(5, CS as i32 + 5, 5)), 7, "chunks made by edits come back too");
+}
+
+#[test]
+fn moving_within_a_chunk_loads_chunks_coming_into_range() {
+    let config = StreamingConfig {
+        view_radius: 1.0,
+        ..Default::default()
+    };
+    let mut streamer = ChunkStreamer::new(row_level("rescan", 4), config);
+    let mut world = World::new();
+
+    let (loaded, _) = settle(&mut streamer, &mut world, Vec3::new(0.1, 0.5, 0.5));
+    assert_eq!(loaded, BTreeSet::from([0]));
+    // Still in chunk 0, but chunk 1's centre is now within the view radius.
+    let (loaded, _) = settle(&mut streamer, &mut world, Vec3::new(0.9, 0.5, 0.5));
+    assert_eq!(loaded, BTreeSet::from([1]));
+}
+
+#[test]
+fn the_last_chunk_a_level_can_store_is_streamed() {
+    let config = StreamingConfig {
+        view_radius: 1.0,
+        unload_margin: 0.5,
+        ..Default::default()
+    };
+    let mut streamer = ChunkStreamer::new(chunks_level("last", [MAX_CHUNK]), config);
+    let mut world = World::new();
+    let (loaded, _) = settle(&mut streamer, &mut world, Vec3::new(254.5, 0.5, 0.5));
+    assert_eq!(loaded, BTreeSet::from([MAX_CHUNK]));
+
+    // Edits past it are rejected, as a level could not save them.
+    let beyond = IVec3::new(255 * CS as i32 + 5, 0, 0);
+    assert_eq!(World::chunk_key(beyond), None);
+    assert_eq!(world.set_voxel(beyond, 3), None);
+
+    let last = IVec3::new(254 * CS as i32 + 5, 0, 0);
+    world.set_voxel(last, 3).unwrap();
+    streamer.mark_edited(get_xyz_key(MAX_CHUNK, 0, 0));
+    let (_, unloaded) = settle(&mut streamer, &mut world, Vec3::new(250.5, 0.5, 0.5));
+    assert_eq!(unloaded, BTreeSet::from([MAX_CHUNK]));
+
+    let (loaded, _) = settle(&mut streamer, &mut world, Vec3::new(255.5, 0.5, 0.5));
+    assert_eq!(loaded, BTreeSet::from([MAX_CHUNK]));
+    assert_eq!(world.get_voxel(last), 3);
+    assert_eq!(world.chunk_count(), 1);
+}
+
+// Polls until no meshes are pending.
+fn drain(queue: &mut MeshQueue) -> Vec<MeshedChunk> {
+    let mut meshed = Vec::new();
+    let start = Instant::now();
+    while queue.pending() > 0 {
+        meshed.extend(queue.poll());
+        assert!(start.elapsed() < Duration::from_secs(10), "meshing did not finish");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    meshed
+}
+
+#[test]
+fn mesh_queue_meshes_dirty_chunks_like_mesh_dirty() {
+    let mut world = World::new();
+    for x in 0..3 {
+        world.set_voxel(IVec3::new(x * CS as i32 + 61, 0, 0), 2);
+    }
+    let mut expected = World::new();
+    for key in world.keys() {
+        expected.insert_chunk(key, world.chunk(key).unwrap().into());
+    }
+    let lod = |chunk_pos: IVec3| if chunk_pos.x == 2 { Lod::Half } else { Lod::Full };
+    let mut expected: Vec<_> = expected.mesh_dirty(true, lod).into_iter().map(|cm| (cm.chunk_pos.x, cm.faces)).collect();
+    expected.sort_by_key(|(x, _)| *x);
+
+    let mut queue = MeshQueue::new();
+    queue.submit(&mut world, true, lod);
+    assert_eq!(world.dirty_chunks().count(), 0);
+    assert_eq!(queue.pending(), 3);
+    let mut meshed = drain(&mut queue);
+    meshed.sort_by_key(|chunk| chunk.key);
+
+    assert_eq!(meshed.iter().map(|chunk| chunk.lod).collect::<Vec<_>>(), [Lod::Full, Lod::Full, Lod::Half]);
+    let meshed: Vec<_> = meshed.into_iter().map(|chunk| (chunk.mesh.chunk_pos.x, chunk.mesh.faces)).collect();
+    assert_eq!(meshed, expected);
+}
+
+#[test]
+fn mesh_queue_drops_superseded_and_cancelled_meshes() {
+    let mut world = World::new();
+    world.set_voxel(IVec3::new(5, 5, 5), 1);
+    world.set_voxel(IVec3::new(CS as i32 + 5, 5, 5), 1);
+    let (a, b) = (get_xyz_key(0, 0, 0), get_xyz_key(1, 0, 0));
+
+    let mut queue = MeshQueue::new();
+    queue.submit(&mut world, false, |_| Lod::Full);
+    // Edit chunk a before its first mesh is collected and cancel chunk b.
+    world.set_voxel(IVec3::new(6, 5, 5), 1);
+    queue.submit(&mut world, false, |_| Lod::Full);
+    queue.cancel(b);
+    assert!(queue.is_pending(a) && !queue.is_pending(b));
+
+    let meshed = drain(&mut queue);
+    assert_eq!(meshed.len(), 1);
+    assert_eq!(meshed[0].key, a);
+    // The two voxels merge along x: the edited chunk's top face is one 2x1 quad.
+    let top = &meshed[0].mesh.faces[0];
+    assert_eq!(top.len(), 1);
+    let xs = top[0].vertices(0).map(|[x, _, _]| x);
+    assert_eq!(xs.iter().max().unwrap() - xs.iter().min().unwrap(), 2);
+}
@@ -0,0 +1,171 @@
+use binary_greedy_mesher_demo_rs::data::level_file::LevelFile;
+use binary_greedy_mesher_demo_rs::data::rle;
+use binary_greedy_mesher_demo_rs::data::vox::{PaletteMapping, VoxFile};
+use binary_greedy_mesher_demo_rs::data::blocks::BlockRegistry;
+use binary_greedy_mesher_demo_rs::{get_xyz_key, get_zxy_index, CS, CS_P2, CS_P3};
+
+fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    out.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    out.extend_from_slice(content);
+    out.extend_from_slice(children);
+    out
+}
+
+fn ints(values: &[i32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn model(size: [i32; 3], voxels: &[[u8; 4]]) -> Vec<u8> {
+    let mut out = chunk(b"SIZE", &ints(&size), &[]);
+    let mut xyzi = ints(&[voxels.len() as i32]);
+    xyzi.extend(voxels.iter().flatten());
+    out.extend(chunk(b"XYZI", &xyzi, &[]));
+    out
+}
+
+fn vox(children: &[u8]) -> Vec<u8> {
+    let mut out = b"VOX ".to_vec();
+    out.extend_from_slice(&150i32.to_le_bytes());
+    out.extend(chunk(b"MAIN", &[], children));
+    out
+}
+
+fn dict(entries: &[(&str, &str)]) -> Vec<u8> {
+    let mut out = ints(&[entries.len() as i32]);
+    for (k, v) in entries {
+        for s in [k, v] {
+            out.extend(ints(&[s.len() as i32]));
+            out.extend_from_slice(s.as_bytes());
+        }
+    }
+    out
+}
+
+fn transform(id: i32, child: i32, translation: &str) -> Vec<u8> {
+    let mut content = ints(&[id]);
+    content.extend(dict(&[]));
+    content.extend(ints(&[child, -1, 0, 1]));
+    content.extend(dict(&[("_t", translation)]));
+    chunk(b"nTRN", &content, &[])
+}
+
+fn decode(level: &LevelFile, key: u32) -> Vec<u8> {
+    let entry = level.chunk_table.iter().find(|e| e.key == key).expect("chunk is stored");
+    let mut voxels = vec![0u8; CS_P3];
+    let mut mask = vec![0u64; CS_P2];
+    rle::try_decompress_to_voxels_and_opaque_mask(level.chunk_data(entry).unwrap(), &mut voxels, &mut mask).unwrap();
+    voxels
+}
+
+#[test]
+fn import_splits_into_padded_chunks() {
+    // Z-up (x, y, z) becomes Y-up (x, z, -y); the single y = 0 row stays at z = 0.
+    let bytes = vox(&model([70, 1, 10], &[[0, 0, 0, 1], [61, 0, 0, 2], [62, 0, 9, 3]]));
+    let level = VoxFile::parse(&bytes)
+        .unwrap()
+        .to_level(PaletteMapping::Index, &BlockRegistry::default())
+        .unwrap();
+
+    assert_eq!(level.dims(), [2, 1, 1]);
+    assert_eq!(level.chunk_table.len(), 2);
+
+    let first = decode(&level, get_xyz_key(0, 0, 0));
+    assert_eq!(first[get_zxy_index(1, 1, 1)], 1);
+    assert_eq!(first[get_zxy_index(62, 1, 1)], 2);
+    // Padding on the far side holds the neighbour's first column.
+    assert_eq!(first[get_zxy_index(63, 10, 1)], 3);
+
+    let second = decode(&level, get_xyz_key(1, 0, 0));
+    assert_eq!(second[get_zxy_index(1, 10, 1)], 3);
+    assert_eq!(second[get_zxy_index(0, 1, 1)], 2);
+    assert_eq!(second.iter().filter(|&&v| v != 0).count(), 2);
+}
+
+#[test]
+fn import_maps_palette_to_nearest_type() {
+    let mut rgba = vec![0u8; 1024];
+    let blocks = BlockRegistry::default();
+    let [r, g, b] = blocks.color(3).map(|c| (c * 255.0) as u8);
+    rgba[..4].copy_from_slice(&[r, g, b, 255]);
+
+    let mut children = model([1, 1, 1], &[[0, 0, 0, 1]]);
+    children.extend(chunk(b"RGBA", &rgba, &[]));
+    let vox_file = VoxFile::parse(&vox(&children)).unwrap();
+
+    assert_eq!(vox_file.type_table(PaletteMapping::Nearest, &blocks)[1], 3);
+    assert_eq!(vox_file.type_table(PaletteMapping::Index, &blocks)[1], 1);
+}
+
+#[test]
+fn import_places_models_from_scene_graph() {
+    let mut children = model([2, 2, 2], &[[0, 0, 0, 1]]);
+    children.extend(model([2, 2, 2], &[[1, 1, 1, 2]]));
+    children.extend(transform(0, 1, "0 0 0"));
+    let mut group = ints(&[1]);
+    group.extend(dict(&[]));
+    group.extend(ints(&[2, 2, 4]));
+    children.extend(chunk(b"nGRP", &group, &[]));
+    children.extend(transform(2, 3, "-1 -1 -1"));
+    children.extend(transform(4, 5, "100 0 0"));
+    for (id, model) in [(3, 0), (5, 1)] {
+        let mut shape = ints(&[id]);
+        shape.extend(dict(&[]));
+        shape.extend(ints(&[1, model]));
+        shape.extend(dict(&[]));
+        children.extend(chunk(b"nSHP", &shape, &[]));
+    }
+
+    let vox_file = VoxFile::parse(&vox(&children)).unwrap();
+    let mut voxels: Vec<_> = vox_file.world_voxels().collect();
+    voxels.sort();
+    assert_eq!(voxels, vec![([-2, -2, -2], 1), ([100, 0, 0], 2)]);
+}
+
+fn level_with(voxels: &[([usize; 3], u8)]) -> LevelFile {
+    let mut chunks: std::collections::BTreeMap<u32, Vec<u8>> = std::collections::BTreeMap::new();
+    for &([x, y, z], ty) in voxels {
+        let key = get_xyz_key((x / CS) as u8, (y / CS) as u8, (z / CS) as u8);
+        let chunk = chunks.entry(key).or_insert_with(|| vec![0u8; CS_P3]);
+        chunk[get_zxy_index(x % CS + 1, y % CS + 1, z % CS + 1)] = ty;
+    }
+    let mut level = LevelFile::default();
+    for (key, chunk) in chunks {
+        level.insert_chunk(key, &chunk).unwrap();
+    }
+    level
+}
+
+#[test]
+fn export_round_trips_through_import() {
+    let voxels = [([0, 0, 0], 1), ([61, 5, 3], 2), ([62, 70, 0], 7), ([10, 0, 130], 12)];
+    let level = level_with(&voxels);
+
+    let blocks = BlockRegistry::default();
+    let vox_file = VoxFile::from_level(&level, &blocks, |_| true).unwrap();
+    let reparsed = VoxFile::parse(&vox_file.to_bytes()).unwrap();
+    assert_eq!(reparsed, vox_file);
+
+    let imported = reparsed.to_level(PaletteMapping::Index, &blocks).unwrap();
+    for ([x, y, z], ty) in voxels {
+        let key = get_xyz_key((x / CS) as u8, (y / CS) as u8, (z / CS) as u8);
+        assert_eq!(decode(&imported, key)[get_zxy_index(x % CS + 1, y % CS + 1, z % CS + 1)], ty);
+    }
+}
+
+#[test]
+fn export_splits_into_256_models_and_filters_chunks() {
+    let level = level_with(&[([0, 0, 0], 1), ([300, 0, 0], 2), ([0, 0, 300], 3)]);
+
+    let blocks = BlockRegistry::default();
+    let all = VoxFile::from_level(&level, &blocks, |_| true).unwrap();
+    assert_eq!(all.models.len(), 3);
+    assert!(all.models.iter().all(|m| m.size.iter().all(|&s| s <= 256)));
+    assert_eq!(all.palette.unwrap()[0][..3], blocks.color(1).map(|c| (c * 255.0).round() as u8));
+
+    let first_column = VoxFile::from_level(&level, &blocks, |(x, _, _)| x == 0).unwrap();
+    let mut types: Vec<u8> = first_column.models.iter().flat_map(|m| m.voxels.iter().map(|v| v[3])).collect();
+    types.sort();
+    assert_eq!(types, vec![1, 3]);
+}
@@ -0,0 +1,211 @@
+use binary_greedy_mesher_demo_rs::data::level_file::LevelFile;
+use binary_greedy_mesher_demo_rs::mesher::{mesh, ColumnMask, Lod, MeshData};
+use binary_greedy_mesher_demo_rs::world::{get_local_index, ChunkNeighbourhood, World, FACE_NEIGHBOURS};
+use binary_greedy_mesher_demo_rs::{get_xyz_key, get_zxy_index, CS, CS_P, CS_P2, CS_P3};
+use glam::{IVec3, Vec3};
+
+fn solid(ty: u8) -> Vec<u8> {
+    vec![ty; CS * CS * CS]
+}
+
+fn quads(voxels: &[u8], opaque_mask: &[u64]) -> usize {
+    let mut mesh_data = MeshData::new(64);
+    mesh_data.opaque_mask.copy_from_slice(opaque_mask);
+    mesh(voxels, &mut mesh_data);
+    mesh_data.face_vertex_length.iter().sum()
+}
+
+#[test]
+fn face_neighbours_cull_shared_faces() {
+    let centre = solid(1);
+    let neighbour = solid(2);
+    let mut voxels = vec![0u8; CS_P3];
+    let mut mask = vec![0u64; CS_P2];
+
+    let mut neighbourhood = ChunkNeighbourhood::new(&centre);
+    neighbourhood.assemble(&mut voxels, &mut mask);
+    assert_eq!(quads(&voxels, &mask), 6);
+
+    for offset in FACE_NEIGHBOURS {
+        neighbourhood.set(offset, Some(&neighbour));
+    }
+    neighbourhood.assemble(&mut voxels, &mut mask);
+    assert_eq!(quads(&voxels, &mask), 0);
+
+    // Padding holds the neighbour's voxels, not the centre's.
+    assert_eq!(voxels[get_zxy_index(0, 5, 5)], 2);
+    assert_eq!(voxels[get_zxy_index(5, CS_P - 1, 5)], 2);
+    assert_eq!(voxels[get_zxy_index(5, 5, CS_P - 1)], 2);
+    // Edges and corners stay air without diagonal neighbours.
+    assert_eq!(voxels[get_zxy_index(0, 0, 5)], 0);
+}
+
+#[test]
+fn assemble_copies_matching_border_voxels() {
+    let mut centre = vec![0u8; CS * CS * CS];
+    centre[get_local_index(3, 4, 5)] = 7;
+    let mut east = vec![0u8; CS * CS * CS];
+    east[get_local_index(0, 10, CS - 1)] = 3;
+    let mut corner = vec![0u8; CS * CS * CS];
+    corner[get_local_index(CS - 1, CS - 1, CS - 1)] = 9;
+
+    let mut neighbourhood = ChunkNeighbourhood::new(&centre);
+    neighbourhood.set([1, 0, 0], Some(&east));
+    neighbourhood.set([-1, -1, -1], Some(&corner));
+
+    let mut voxels = vec![0u8; CS_P3];
+    let mut mask = vec![u64::MAX; CS_P2];
+    neighbourhood.assemble(&mut voxels, &mut mask);
+
+    assert_eq!(voxels[get_zxy_index(4, 5, 6)], 7);
+    assert_eq!(voxels[get_zxy_index(CS + 1, 11, CS)], 3);
+    assert_eq!(voxels[get_zxy_index(0, 0, 0)], 9);
+    assert_eq!(voxels.iter().filter(|&&v| v != 0).count(), 3);
+
+    for (column, bits) in mask.iter().enumerate() {
+        let expected = (0..CS_P).filter(|&z| voxels[column * CS_P + z] != 0).fold(0u64, |m, z| m | 1 << z);
+        assert_eq!(*bits, expected);
+    }
+}
+
+#[test]
+fn assemble_supports_other_widths() {
+    let cs = u32::CS;
+    let centre = vec![1u8; cs * cs * cs];
+    let below = vec![1u8; cs * cs * cs];
+    let mut neighbourhood = ChunkNeighbourhood::new(&centre);
+    neighbourhood.set([0, -1, 0], Some(&below));
+
+    let mut voxels = vec![0u8; u32::CS_P3];
+    let mut mask = vec![0u32; u32::CS_P2];
+    neighbourhood.assemble(&mut voxels, &mut mask);
+
+    let mut mesh_data = MeshData::<u32>::new(16);
+    mesh_data.opaque_mask.copy_from_slice(&mask);
+    mesh(&voxels, &mut mesh_data);
+    assert_eq!(mesh_data.face_vertex_length, [1, 0, 1, 1, 1, 1]);
+}
+
+#[test]
+fn set_voxel_marks_neighbours_sharing_the_padding() {
+    let mut world = World::new();
+    for key in [get_xyz_key(0, 0, 0), get_xyz_key(1, 0, 0), get_xyz_key(1, 1, 0), get_xyz_key(0, 0, 1)] {
+        world.insert_chunk(key, solid(0).into_boxed_slice());
+    }
+    world.take_dirty();
+
+    // Interior voxels only dirty their own chunk.
+    assert_eq!(world.set_voxel(IVec3::new(10, 10, 10), 4), Some(0));
+    assert_eq!(world.get_voxel(IVec3::new(10, 10, 10)), 4);
+    assert_eq!(world.take_dirty(), vec![get_xyz_key(0, 0, 0)]);
+
+    // The last x column of chunk 0 sits in the padding of chunks +x and +x+y (an edge).
+    let edge = IVec3::new(CS as i32 - 1, CS as i32 - 1, 5);
+    world.set_voxel(edge, 2);
+    assert_eq!(world.take_dirty(), vec![get_xyz_key(0, 0, 0), get_xyz_key(1, 0, 0), get_xyz_key(1, 1, 0)]);
+
+    // Unchanged values and air in missing chunks don't dirty anything.
+    world.set_voxel(edge, 2);
+    assert_eq!(world.set_voxel(IVec3::new(0, 200, 0), 0), Some(0));
+    assert!(world.take_dirty().is_empty());
+
+    // Placing into a missing chunk creates it.
+    assert_eq!(world.set_voxel(IVec3::new(0, 0, 3 * CS as i32), 1), Some(0));
+    assert!(world.chunk(get_xyz_key(0, 0, 3)).is_some());
+    assert_eq!(world.set_voxel(IVec3::new(-1, 0, 0), 1), None);
+    assert_eq!(world.get_voxel(IVec3::new(-1, 0, 0)), 0);
+}
+
+#[test]
+fn mesh_dirty_culls_faces_between_chunks() {
+    let mut world = World::new();
+    world.insert_chunk(get_xyz_key(0, 0, 0), solid(1).into_boxed_slice());
+    world.insert_chunk(get_xyz_key(1, 0, 0), solid(1).into_boxed_slice());
+
+    let meshes = world.mesh_dirty(false, |_| Lod::Full);
+    assert_eq!(meshes.len(), 2);
+    assert!(meshes.iter().all(|m| m.quad_count() == 5 && m.faces[2].len() + m.faces[3].len() == 1));
+    assert_eq!(world.dirty_chunks().count(), 0);
+
+    // Digging through the shared wall re-meshes both chunks and opens faces on each side.
+    world.set_voxel(IVec3::new(CS as i32, 20, 20), 0);
+    let meshes = world.mesh_dirty(false, |_| Lod::Full);
+    assert_eq!(meshes.len(), 2);
+    let west = meshes.iter().find(|m| m.chunk_pos == IVec3::ZERO).unwrap();
+    assert_eq!(west.faces[2].len(), 1);
+}
+
+#[test]
+fn mesh_dirty_skips_removed_chunks() {
+    let mut world = World::new();
+    world.insert_chunk(get_xyz_key(0, 0, 0), solid(1).into_boxed_slice());
+    world.insert_chunk(get_xyz_key(1, 0, 0), solid(2).into_boxed_slice());
+    world.mesh_dirty(false, |_| Lod::Full);
+
+    // Only the remaining neighbour comes back, with the wall towards the removed chunk reopened.
+    world.mark_dirty(get_xyz_key(1, 0, 0));
+    world.remove_chunk(get_xyz_key(1, 0, 0));
+    let meshes = world.mesh_dirty(false, |_| Lod::Full);
+    assert_eq!(meshes.len(), 1);
+    assert_eq!(meshes[0].chunk_pos, IVec3::ZERO);
+    assert_eq!(meshes[0].quad_count(), 6);
+    assert!(meshes[0].quads().all(|(_, quad)| quad.voxel_type() == 1));
+}
+
+#[test]
+fn transparency_applies_to_every_lod_and_dirties_all_chunks() {
+    let mut world = World::new();
+    world.insert_chunk(get_xyz_key(0, 0, 0), solid(1).into_boxed_slice());
+    world.insert_chunk(get_xyz_key(1, 0, 0), solid(9).into_boxed_slice());
+    world.mesh_dirty(false, |_| Lod::Full);
+
+    let mut transparency = [false; 256];
+    transparency[9] = true;
+    world.set_transparency(Some(transparency));
+    assert_eq!(world.dirty_chunks().count(), 2);
+    let meshes = world.mesh_dirty(false, |chunk_pos| if chunk_pos.x == 0 { Lod::Full } else { Lod::Half });
+    let glass = meshes.iter().find(|m| m.chunk_pos.x == 1).unwrap();
+    // The glass is culled against the opaque chunk; the opaque chunk keeps its face towards it.
+    assert_eq!(glass.faces.iter().map(Vec::len).sum::<usize>(), 0);
+    assert_eq!(glass.transparent_faces.iter().map(Vec::len).collect::<Vec<_>>(), [1, 1, 1, 0, 1, 1]);
+    let stone = meshes.iter().find(|m| m.chunk_pos.x == 0).unwrap();
+    assert_eq!(stone.quad_count(), 6);
+
+    // Setting the same table again changes nothing.
+    world.set_transparency(Some(transparency));
+    assert_eq!(world.dirty_chunks().count(), 0);
+}
+
+#[test]
+fn from_level_round_trips_padded_chunks() {
+    let mut level = LevelFile::default();
+    let mut padded = vec![0u8; CS_P3];
+    padded[get_zxy_index(1, 1, 1)] = 3;
+    padded[get_zxy_index(0, 1, 1)] = 9; // padding is dropped
+    level.insert_chunk(get_xyz_key(2, 0, 1), &padded).unwrap();
+
+    let world = World::from_level(&level).unwrap();
+    assert_eq!(world.chunk_count(), 1);
+    assert_eq!(world.get_voxel(IVec3::new(2 * CS as i32, 0, CS as i32)), 3);
+    assert_eq!(world.get_voxel(IVec3::new(2 * CS as i32 - 1, 0, CS as i32)), 0);
+    assert_eq!(world.dirty_chunks().collect::<Vec<_>>(), vec![get_xyz_key(2, 0, 1)]);
+}
+
+#[test]
+fn raycast_reports_the_entered_face() {
+    let mut world = World::new();
+    world.set_voxel(IVec3::new(10, 5, 7), 1);
+    world.set_voxel(IVec3::new(70, 5, 7), 2);
+
+    let hit = world.raycast(Vec3::new(0.5, 5.5, 7.5), Vec3::X, 100.0).unwrap();
+    assert_eq!((hit.voxel, hit.normal), (IVec3::new(10, 5, 7), IVec3::NEG_X));
+    assert!((hit.distance - 9.5).abs() < 1e-4);
+
+    // Crosses a chunk border and comes in from above at an angle.
+    let hit = world.raycast(Vec3::new(68.5, 9.5, 7.5), Vec3::new(0.5, -1.0, 0.0), 100.0).unwrap();
+    assert_eq!((hit.voxel, hit.normal), (IVec3::new(70, 5, 7), IVec3::Y));
+
+    assert_eq!(world.raycast(Vec3::new(0.5, 5.5, 7.5), Vec3::X, 5.0), None);
+    assert_eq!(world.raycast(Vec3::new(0.5, 5.5, 7.5), Vec3::NEG_X, 100.0), None);
+    assert_eq!(world.raycast(Vec3::new(10.5, 5.5, 7.5), Vec3::Y, 10.0).unwrap().normal, IVec3::ZERO);
+}